@@ -0,0 +1,426 @@
+//! The arguments of the `revue` command line tool
+
+use crate::io::console::{TableStyle, TopicStateFilter};
+use crate::io::remind::Notifier;
+use crate::io::status::DEFAULT_STATUS_FORMAT;
+use crate::topics::clock::parse_datetime;
+use crate::topics::scheduling::ReviewOrder;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CliState {
+    /// View today's review topics
+    Today,
+    /// View all the review topics
+    All,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum StatsView {
+    /// View a year-long heatmap of daily reviews
+    Heatmap,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    View {
+        view: CliState,
+        /// Only show the topics in this state. Archived topics are hidden by default.
+        #[arg(long)]
+        state: Option<TopicStateFilter>,
+    },
+    Stats {
+        stats: StatsView,
+    },
+    Edit(EditArgs),
+    Add(AddArgs),
+    Remove {
+        topic_name: String,
+    },
+    Review {
+        topic_name: String,
+        /// Day the topic was actually reviewed on, if it was not today
+        #[arg(long)]
+        on: Option<NaiveDate>,
+    },
+    /// Set when a topic is due next
+    Reschedule(RescheduleArgs),
+    /// Stop a topic from becoming due until it is made active again
+    Suspend {
+        topic_name: String,
+    },
+    /// Hide a topic until tomorrow
+    Bury {
+        topic_name: String,
+    },
+    /// Hide a mastered topic while keeping it for the statistics
+    Archive {
+        topic_name: String,
+    },
+    /// Make a suspended, buried or archived topic active again
+    Activate {
+        topic_name: String,
+    },
+    /// Set the daily limits and the order of today's topics
+    Limits(LimitsArgs),
+    /// Pause the reviews, for example during a vacation
+    Pause {
+        /// Day the reviews resume on
+        #[arg(long)]
+        until: NaiveDate,
+    },
+    /// Resume the reviews and move every schedule forward by the paused days
+    Resume,
+    /// Set the timezone and the hour at which a new day starts
+    Calendar(CalendarArgs),
+    /// Spread the overdue topics over the next few days
+    CatchUp {
+        /// Number of days to spread the overdue topics over
+        #[arg(long, default_value_t = 7)]
+        days: u64,
+    },
+    /// Take a snapshot of the storage now, or manage the snapshots
+    Backup(BackupArgs),
+    /// Replace the storage with the snapshot of the given day
+    Restore {
+        day: NaiveDate,
+    },
+    /// Merge the topics of another storage file, like a copy from another machine
+    Merge {
+        other: String,
+        /// Show what would change without writing the storage
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Encrypt the storage, its snapshots and its journal with a passphrase
+    Encrypt,
+    /// Decrypt the storage, its snapshots and its journal, keeping them in plaintext again
+    Decrypt,
+    /// Check the storage file for problems and optionally repair them
+    Doctor {
+        /// Repair the problems that can be fixed, after taking a snapshot of the file
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Serve the topics as a JSON API over HTTP
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+    /// Answer JSON-RPC requests on stdin, one per line, for editor plugins and scripts
+    Rpc,
+    /// Print a one-line summary for shell prompts and status bars
+    ///
+    /// Exits with 0 when topics are due, with 1 when nothing is due, with 2 when the storage
+    /// cannot be read and with 3 when it is encrypted and no passphrase is given in
+    /// REVUE_PASSPHRASE or encryption.keyfile.
+    Status {
+        /// Placeholders: {due}, {overdue}, {next}, {next_day} and {next_in}
+        #[arg(long, default_value = DEFAULT_STATUS_FORMAT)]
+        format: String,
+    },
+    /// Send a reminder when topics are due, at most once a day
+    ///
+    /// An encrypted storage needs its passphrase in REVUE_PASSPHRASE or encryption.keyfile, as
+    /// reminders never ask for it.
+    Remind(RemindArgs),
+    /// Pull the topics from a git remote, merge them with the ones here and push them back
+    Sync(SyncArgs),
+    /// Show and change the settings in the config file
+    Config(ConfigArgs),
+    /// Keep separate topics and settings in named profiles
+    Profile(ProfileArgs),
+    /// Export the upcoming reviews to other tools
+    Export(ExportArgs),
+    /// Revert the last adds, removes, renames, reviews and merges
+    Undo {
+        /// Number of operations to revert
+        #[arg(default_value_t = 1)]
+        steps: usize,
+    },
+    /// Apply the last undone operations again
+    Redo {
+        /// Number of operations to apply again
+        #[arg(default_value_t = 1)]
+        steps: usize,
+    },
+    /// List the recent operations that can be undone
+    Log {
+        /// Number of operations to list
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    pub topic_name: String,
+    /// Tag to group the topic by. Can be given multiple times.
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+    /// Higher priority topics come first when ordering by priority
+    #[arg(long, default_value_t = 0)]
+    pub priority: u8,
+}
+
+#[derive(Args)]
+#[command(group = ArgGroup::new("when").required(true))]
+pub struct RescheduleArgs {
+    pub topic_name: String,
+    /// Day the topic is due on
+    #[arg(long, group = "when")]
+    pub due: Option<NaiveDate>,
+    /// Number of days from today until the topic is due
+    #[arg(long = "in", group = "when")]
+    pub in_days: Option<u64>,
+    /// Go back to the first review gap, as if the topic was new
+    #[arg(long, group = "when")]
+    pub reset: bool,
+}
+
+#[derive(Args)]
+pub struct BackupArgs {
+    #[command(subcommand)]
+    pub command: Option<BackupCommands>,
+}
+
+#[derive(Args)]
+pub struct RemindArgs {
+    #[command(subcommand)]
+    pub command: Option<RemindCommands>,
+    #[command(flatten)]
+    pub notifier: NotifierArgs,
+    /// Keep running and check again every interval, instead of checking once
+    #[arg(long)]
+    pub daemon: bool,
+    /// Minutes between checks when running as a daemon
+    #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+}
+
+#[derive(Subcommand)]
+pub enum RemindCommands {
+    /// Write a systemd user timer running `revue remind`, and print the matching crontab line
+    Units {
+        /// Minutes between checks. The crontab line needs minutes that divide an hour, or hours
+        /// that divide a day.
+        #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+        every: u64,
+        /// Directory to write the unit files to, instead of printing them
+        #[arg(long)]
+        output_dir: Option<String>,
+        #[command(flatten)]
+        notifier: NotifierArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct SyncArgs {
+    #[command(subcommand)]
+    pub command: Option<SyncCommands>,
+    /// Remote to sync with instead of the configured one
+    #[arg(long)]
+    pub remote: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum SyncCommands {
+    /// Keep the storage in a git repository, committing every change
+    Init,
+}
+
+/// Where reminders are sent. They are printed when neither option is given.
+#[derive(Args)]
+pub struct NotifierArgs {
+    /// Shell command to run with the message as its last argument, for example `notify-send revue`
+    #[arg(long, conflicts_with = "notify_file")]
+    pub notify_command: Option<String>,
+    /// File to append the message to
+    #[arg(long)]
+    pub notify_file: Option<String>,
+}
+
+impl NotifierArgs {
+    pub fn notifier(&self) -> Notifier {
+        match (&self.notify_command, &self.notify_file) {
+            (Some(command), _) => Notifier::Command(command.clone()),
+            (None, Some(path)) => Notifier::File(path.clone()),
+            (None, None) => Notifier::Stdout,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommands,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Show the value of a setting
+    Get { key: String },
+    /// Change a setting in the config file
+    Set { key: String, value: String },
+    /// Remove a setting from the config file
+    Unset { key: String },
+    /// Show every setting, its value and where the value comes from
+    List,
+}
+
+#[derive(Args)]
+pub struct ProfileArgs {
+    #[command(subcommand)]
+    pub command: ProfileCommands,
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommands {
+    /// Show the profiles, marking the one in use
+    List,
+    /// Add a profile with its own storage and config file
+    Create { name: String },
+    /// Use a profile from now on, when no --profile is given
+    Switch { name: String },
+    /// Delete the config file of a profile, keeping its storage
+    Delete { name: String },
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[command(subcommand)]
+    pub format: ExportFormat,
+}
+
+#[derive(Subcommand)]
+pub enum ExportFormat {
+    /// Export the topics due in the next days as an iCalendar file
+    Ics {
+        /// Number of days to export, starting today
+        #[arg(long, default_value_t = 14)]
+        days: u64,
+        /// One event per day listing all its topics, instead of one per topic
+        #[arg(long)]
+        per_day: bool,
+        /// Export to-dos instead of all-day events
+        #[arg(long)]
+        todo: bool,
+        /// File to write to instead of stdout
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BackupCommands {
+    /// List the snapshots, newest first
+    List,
+    /// Show how the topics changed since the snapshot of the given day
+    Diff { day: NaiveDate },
+    /// Set how many snapshots are kept
+    Keep {
+        /// Number of most recent days to keep a snapshot of
+        #[arg(long)]
+        daily: Option<usize>,
+        /// Number of weeks before those days to keep one snapshot of
+        #[arg(long)]
+        weekly: Option<usize>,
+    },
+}
+
+#[derive(Args)]
+pub struct LimitsArgs {
+    /// Maximum number of already reviewed topics to show per day
+    #[arg(long)]
+    pub max_reviews: Option<usize>,
+    /// Maximum number of new topics to show per day
+    #[arg(long)]
+    pub max_new: Option<usize>,
+    /// Order in which today's topics are shown
+    #[arg(long)]
+    pub order: Option<ReviewOrder>,
+    /// Remove both daily limits
+    #[arg(long, conflicts_with_all = ["max_reviews", "max_new"])]
+    pub clear: bool,
+}
+
+#[derive(Args)]
+pub struct CalendarArgs {
+    /// Timezone to count the days in, for example Europe/Berlin
+    #[arg(long, conflicts_with = "system_timezone")]
+    pub timezone: Option<Tz>,
+    /// Count the days in the system timezone
+    #[arg(long)]
+    pub system_timezone: bool,
+    /// Hour of the day at which the next day starts
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..24))]
+    pub rollover_hour: Option<u32>,
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    pub topic_name: String,
+    /// New name of the topic
+    pub new_topic_name: Option<String>,
+    /// Tag to group the topic by, replacing its tags. Can be given multiple times.
+    #[arg(long = "tag")]
+    pub tags: Option<Vec<String>>,
+    /// Higher priority topics come first when ordering by priority
+    #[arg(long)]
+    pub priority: Option<u8>,
+}
+
+#[derive(Parser)]
+#[command(about, long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Commands,
+    /// Run as if the current time was this one
+    #[arg(long, global = true, hide = true, value_parser = parse_datetime)]
+    pub now: Option<DateTime<Utc>>,
+    /// Profile to use instead of the one picked with `revue profile switch` [env: REVUE_PROFILE]
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    /// Config file to read instead of the profile's one in ~/.config/revue
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+    /// Storage file to use instead of the configured one
+    #[arg(long, global = true)]
+    pub storage: Option<String>,
+    /// Style of the tables
+    #[arg(long, global = true)]
+    pub table_style: Option<TableStyle>,
+    /// Change a setting for this run only, like `--set limits.max_new_per_day=5`
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_setting)]
+    pub settings: Vec<(String, String)>,
+}
+
+impl Cli {
+    /// The settings given on the command line, as `(key, value)` pairs
+    pub fn config_flags(&self) -> Vec<(String, String)> {
+        let mut flags = self.settings.clone();
+        if let Some(storage) = &self.storage {
+            flags.push(("storage.path".to_string(), storage.clone()));
+        }
+        if let Some(table_style) = self.table_style {
+            let table_style = table_style
+                .to_possible_value()
+                .expect("Table styles are not skipped");
+            flags.push((
+                "display.table_style".to_string(),
+                table_style.get_name().to_string(),
+            ));
+        }
+        flags
+    }
+}
+
+fn parse_setting(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        None => Err("Settings are given as KEY=VALUE".to_string()),
+        Some((key, value)) => Ok((key.trim().to_string(), value.trim().to_string())),
+    }
+}
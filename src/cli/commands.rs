@@ -0,0 +1,783 @@
+//! Running the commands of the `revue` command line tool
+
+use crate::cli::args::{
+    BackupCommands, Cli, CliState, Commands, ConfigCommands, ExportArgs, ExportFormat,
+    ProfileCommands, RemindArgs, RemindCommands, StatsView, SyncArgs, SyncCommands,
+};
+use crate::io::backup::{
+    convert_backups, diff_topics, find_snapshot, list_snapshots, read_snapshot, restore_snapshot,
+};
+use crate::io::config::{config_dir, Config, LayeredConfig};
+use crate::io::console::{
+    display_all, display_config, display_heatmap, display_log, display_merge_conflicts,
+    display_profiles, display_schedule_settings, display_snapshots, display_today,
+    display_topic_changes,
+};
+use crate::io::doctor::{diagnose, repair, stored_calendar};
+use crate::io::encryption::Passphrase;
+use crate::io::events::{archive_log, history_path, load_document, read_log};
+use crate::io::hooks::run_daily_hook;
+use crate::io::ics::{export_ics, IcsComponent, IcsOptions};
+use crate::io::profiles::Profiles;
+use crate::io::remind::{cron_line, remind, systemd_units, RemindCommand, ReminderState};
+use crate::io::rpc::RpcSession;
+use crate::io::server::Server;
+use crate::io::status::{read_document, Status};
+use crate::io::storage::{PreviousStorage, ReviewError, Storage};
+use crate::io::{encryption, sync};
+use crate::topics::calendar::Calendar;
+use crate::topics::clock::{Clock, FixedClock, SystemClock};
+use crate::topics::merge::merge_by_identity;
+use crate::topics::review_topics::{ReviewTopic, TopicState};
+use chrono::{DateTime, Days, Utc};
+use std::io::{stdin, stdout, IsTerminal};
+use std::path::Path;
+use std::time::Duration;
+use std::{env, fs, process, thread};
+
+/// The exit code of status and remind when the storage is encrypted and no passphrase is given
+const LOCKED_EXIT_CODE: i32 = 3;
+
+/// Run the command given on the command line
+pub fn run(cli: &Cli) {
+    let profiles = Profiles::new(config_dir());
+    // Profile command manages the profiles themselves, so it needs no config or storage
+    if let Commands::Profile(profile_args) = &cli.command {
+        run_profile(&profiles, cli.profile.as_deref(), &profile_args.command);
+        return;
+    }
+    let profile = match profiles.select(cli.profile.as_deref()) {
+        Ok(profile) => profile,
+        Err(profile_error) => {
+            println!("{profile_error}");
+            process::exit(1);
+        }
+    };
+
+    let mut layered_config =
+        match LayeredConfig::load(cli.config.as_deref(), &profile, &cli.config_flags()) {
+            Ok(layered_config) => layered_config,
+            Err(config_error) => {
+                println!("{config_error}");
+                process::exit(1);
+            }
+        };
+
+    // Config command works on the config file alone, so it can fix settings that are not valid
+    if let Commands::Config(config_args) = &cli.command {
+        run_config(&mut layered_config, &config_args.command);
+        return;
+    }
+
+    let config = match layered_config.resolve() {
+        Ok(config) => config,
+        Err(config_error) => {
+            println!("{config_error}");
+            process::exit(1);
+        }
+    };
+    let storage_file_path = config.storage_path().to_string();
+
+    // Status command only reads the storage document, to stay fast enough for shell prompts
+    if let Commands::Status { format } = &cli.command {
+        process::exit(run_status(&storage_file_path, format, &config, cli.now));
+    }
+
+    // Remind command only reads the storage document, and rereads it while running as a daemon
+    if let Commands::Remind(remind_args) = &cli.command {
+        run_remind(&storage_file_path, remind_args, &config, cli);
+        return;
+    }
+
+    // An encrypted storage is unlocked once, before any other command reads it
+    let passphrase = unlock(&storage_file_path, &config, true);
+
+    // Doctor command reads the storage file itself, as loading it normally panics on problems
+    if let Commands::Doctor { fix } = &cli.command {
+        run_doctor(
+            &storage_file_path,
+            *fix,
+            &config,
+            passphrase.as_ref(),
+            cli.now,
+        );
+        return;
+    }
+
+    // Sync command changes the storage file through git, so it runs before the storage is loaded
+    if let Commands::Sync(sync_args) = &cli.command {
+        run_sync(&storage_file_path, sync_args, &config, passphrase.as_ref());
+        return;
+    }
+
+    let mut storage =
+        Storage::with_passphrase(PreviousStorage::Yes, Some(storage_file_path), passphrase);
+    storage.set_config(config.clone());
+
+    let clock = make_clock(cli.now, storage.get_effective_calendar());
+    let clock: &dyn Clock = clock.as_ref();
+
+    if let Some(paused_days) = storage.resume_if_pause_over(clock) {
+        storage.write_storage();
+        println!("Welcome back! Your reviews were resumed after {paused_days} paused days.");
+    }
+
+    let today_list = storage.get_today_list(clock);
+    let storage_file_path = storage.get_storage_file_path();
+    if let Err(hook_error) =
+        run_daily_hook(storage_file_path, &config.hooks, clock.today(), &today_list)
+    {
+        eprintln!("{hook_error}");
+    }
+
+    run_on_storage(&mut storage, &cli.command, &config, clock);
+}
+
+/// Run a command that works on the loaded storage
+fn run_on_storage(storage: &mut Storage, command: &Commands, config: &Config, clock: &dyn Clock) {
+    match command {
+        // Edit command allows changing the name of a topic to a new name
+        Commands::Edit(edit_args) => {
+            let renamed = match &edit_args.new_topic_name {
+                None => Ok(edit_args.topic_name.clone()),
+                Some(new_topic_name) => storage
+                    .rename_review_topic(edit_args.topic_name.clone(), new_topic_name.clone())
+                    .map(|_| new_topic_name.clone()),
+            };
+            match renamed.and_then(|topic_name| {
+                storage.edit_topic(topic_name, edit_args.tags.clone(), edit_args.priority)
+            }) {
+                Ok(_) => storage.write_storage(),
+                Err(_) => {
+                    println!("Review topic was not found.")
+                }
+            }
+        }
+
+        // View command allows viewing today's topics or all of the topics
+        Commands::View { view, state } => match view {
+            CliState::Today => display_today(storage, config.table_style(), clock, stdout()),
+            CliState::All => display_all(storage, *state, config.table_style(), clock, stdout()),
+        },
+
+        // Stats command shows statistics about past reviews
+        Commands::Stats { stats } => match stats {
+            StatsView::Heatmap => display_heatmap(storage, clock, stdout(), stdout().is_terminal()),
+        },
+
+        // Add command allows adding new topics to the storage
+        Commands::Add(add_args) => {
+            let mut new_review_topic = ReviewTopic::new(add_args.topic_name.to_string(), clock);
+            new_review_topic.tags = add_args.tags.clone();
+            new_review_topic.priority = add_args.priority;
+            storage.add_review_topic(new_review_topic);
+            storage.write_storage();
+        }
+
+        // Remove command allows removing topics from the storage
+        Commands::Remove { topic_name } => {
+            match storage.clone().find_review_topic(topic_name.to_string()) {
+                None => {}
+                Some(review_topic) => {
+                    storage.remove_review_topic(review_topic);
+                    storage.write_storage();
+                }
+            }
+        }
+
+        // Review command marks the provided topic as reviewed and recalculates when to review it next
+        Commands::Review { topic_name, on } => {
+            match storage.review_topic(topic_name.to_owned(), *on, clock) {
+                Ok(_) => storage.write_storage(),
+                Err(ReviewError::NotFound(_)) => {
+                    println!("Review topic was not found. Did you misspell?")
+                }
+                Err(review_error) => println!("{review_error}"),
+            }
+        }
+
+        // Reschedule command sets the next due day directly or starts the topic over
+        Commands::Reschedule(reschedule_args) => {
+            let topic_name = reschedule_args.topic_name.to_string();
+            let rescheduled = match (reschedule_args.due, reschedule_args.in_days) {
+                _ if reschedule_args.reset => storage.reset_topic(topic_name, clock),
+                (Some(due), _) => storage.reschedule_topic(topic_name, due),
+                (None, Some(in_days)) => match clock.today().checked_add_days(Days::new(in_days)) {
+                    Some(due) => storage.reschedule_topic(topic_name, due),
+                    None => {
+                        println!("The due day is too far away.");
+                        return;
+                    }
+                },
+                (None, None) => unreachable!("clap requires one of --due, --in and --reset"),
+            };
+            match rescheduled {
+                Ok(_) => storage.write_storage(),
+                Err(_) => {
+                    println!("Review topic was not found.")
+                }
+            }
+        }
+
+        // Suspend, bury, archive and activate commands change whether a topic can become due
+        Commands::Suspend { topic_name } => {
+            change_topic_state(storage, topic_name, TopicState::Suspended)
+        }
+        Commands::Bury { topic_name } => {
+            change_topic_state(storage, topic_name, TopicState::Buried(clock.today()))
+        }
+        Commands::Archive { topic_name } => {
+            change_topic_state(storage, topic_name, TopicState::Archived)
+        }
+        Commands::Activate { topic_name } => {
+            change_topic_state(storage, topic_name, TopicState::Active)
+        }
+
+        // Limits command changes how many of the due topics are shown each day and in which order
+        Commands::Limits(limits_args) => {
+            let mut settings = storage.get_schedule_settings().clone();
+            if limits_args.clear {
+                settings.max_reviews_per_day = None;
+                settings.max_new_per_day = None;
+            }
+            if let Some(max_reviews) = limits_args.max_reviews {
+                settings.max_reviews_per_day = Some(max_reviews);
+            }
+            if let Some(max_new) = limits_args.max_new {
+                settings.max_new_per_day = Some(max_new);
+            }
+            if let Some(order) = limits_args.order {
+                settings.review_order = order;
+            }
+            storage.set_schedule_settings(settings);
+            storage.write_storage();
+            display_schedule_settings(&storage.get_effective_schedule_settings(), stdout());
+        }
+
+        // Pause command stops topics from becoming due until the given day
+        Commands::Pause { until } => {
+            if *until <= clock.today() {
+                println!("The pause has to end after today.");
+            } else {
+                storage.pause(*until, clock);
+                storage.write_storage();
+                println!("Reviews are paused and resume on {until}.");
+            }
+        }
+
+        // Resume command ends the pause early
+        Commands::Resume => match storage.resume(clock) {
+            None => println!("Reviews are not paused."),
+            Some(paused_days) => {
+                storage.write_storage();
+                println!("Reviews resumed after {paused_days} paused days.");
+            }
+        },
+
+        // Calendar command changes how points in time are turned into days
+        Commands::Calendar(calendar_args) => {
+            let mut calendar = storage.get_calendar().clone();
+            if calendar_args.system_timezone {
+                calendar.timezone = None;
+            }
+            if let Some(timezone) = calendar_args.timezone {
+                calendar.timezone = Some(timezone);
+            }
+            if let Some(rollover_hour) = calendar_args.rollover_hour {
+                calendar.day_rollover_hour = rollover_hour;
+            }
+            println!("{calendar}");
+            storage.set_calendar(calendar);
+            storage.write_storage();
+        }
+
+        // Catch up command spreads a backlog of overdue topics over the next few days
+        Commands::CatchUp { days } => {
+            let spread_count = storage.catch_up(*days, clock);
+            storage.write_storage();
+            println!("Spread {spread_count} overdue topics over the next {days} days.");
+        }
+
+        // Backup command takes a snapshot right away or manages the existing ones
+        Commands::Backup(backup_args) => match &backup_args.command {
+            None => match storage.backup_now() {
+                Ok(Some(snapshot)) => println!("Snapshot saved to {}", snapshot.path.display()),
+                Ok(None) => println!("There is no storage file to back up yet."),
+                Err(backup_error) => println!("{backup_error}"),
+            },
+            Some(BackupCommands::List) => {
+                display_snapshots(&list_snapshots(storage.get_storage_file_path()), stdout())
+            }
+            Some(BackupCommands::Diff { day }) => {
+                match find_snapshot(storage.get_storage_file_path(), *day)
+                    .and_then(|snapshot| read_snapshot(&snapshot, storage.get_passphrase()))
+                {
+                    Ok(snapshot_storage) => display_topic_changes(
+                        &diff_topics(
+                            snapshot_storage.review_topics(),
+                            &storage.get_review_topic_list(),
+                        ),
+                        stdout(),
+                    ),
+                    Err(backup_error) => println!("{backup_error}"),
+                }
+            }
+            Some(BackupCommands::Keep { daily, weekly }) => {
+                let mut backup_settings = storage.get_backup_settings().clone();
+                backup_settings.daily = daily.unwrap_or(backup_settings.daily);
+                backup_settings.weekly = weekly.unwrap_or(backup_settings.weekly);
+                println!("{backup_settings}");
+                storage.set_backup_settings(backup_settings);
+                storage.write_storage();
+            }
+        },
+
+        // Restore command replaces the storage file with a snapshot that passes the integrity check
+        Commands::Restore { day } => {
+            match find_snapshot(storage.get_storage_file_path(), *day).and_then(|snapshot| {
+                restore_snapshot(
+                    storage.get_storage_file_path(),
+                    &snapshot,
+                    storage.get_passphrase(),
+                )
+            }) {
+                Ok(_) => println!("Restored the snapshot of {day}."),
+                Err(backup_error) => println!("{backup_error}"),
+            }
+        }
+
+        // Merge command combines the topics with the ones of another storage file, by their ids
+        Commands::Merge { other, dry_run } => {
+            match load_document(other, storage.get_passphrase()) {
+                Ok(other_document) => {
+                    let topics = storage.get_review_topic_list();
+                    let merged = merge_by_identity(&topics, other_document.review_topics());
+                    display_topic_changes(&diff_topics(&topics, &merged.topics), stdout());
+                    display_merge_conflicts(&merged.conflicts, stdout());
+                    if *dry_run {
+                        println!("Nothing was written, as this is a dry run.");
+                    } else {
+                        storage.merge_review_topics(other.clone(), merged.topics);
+                        storage.write_storage();
+                        println!("Merged the topics of {other}.");
+                    }
+                }
+                Err(error) => println!("Could not read {other}: {error}"),
+            }
+        }
+
+        // Encrypt and decrypt commands write the storage, its journal and its snapshots again
+        Commands::Encrypt => run_encryption(storage, true, config),
+        Commands::Decrypt => run_encryption(storage, false, config),
+
+        // Profile, config, doctor, status, remind and sync commands are run before the storage is loaded
+        Commands::Profile(_)
+        | Commands::Config(_)
+        | Commands::Doctor { .. }
+        | Commands::Status { .. }
+        | Commands::Remind(_)
+        | Commands::Sync(_) => {}
+
+        // Serve command answers HTTP requests until it is stopped
+        Commands::Serve { address } => match Server::bind(address, storage.clone()) {
+            Ok(mut server) => {
+                println!("Serving the topics on http://{address}");
+                server.run(clock);
+            }
+            Err(error) => println!("Could not listen on {address}: {error}"),
+        },
+
+        // Export command writes the upcoming reviews in another format
+        Commands::Export(ExportArgs { format }) => match format {
+            ExportFormat::Ics {
+                days,
+                per_day,
+                todo,
+                output,
+            } => {
+                let options = IcsOptions {
+                    days: *days,
+                    per_day: *per_day,
+                    component: match todo {
+                        true => IcsComponent::Todo,
+                        false => IcsComponent::Event,
+                    },
+                };
+                let ics = export_ics(&storage.get_review_topic_list(), options, clock);
+                match output {
+                    None => print!("{ics}"),
+                    Some(output) => match fs::write(output, ics) {
+                        Ok(_) => println!("Exported the reviews to {output}"),
+                        Err(error) => println!("Could not write {output}: {error}"),
+                    },
+                }
+            }
+        },
+
+        // Rpc command answers JSON-RPC requests until stdin is closed
+        Commands::Rpc => {
+            let mut session = RpcSession::new(storage.clone());
+            if let Err(error) = session.run(stdin().lock(), stdout().lock(), clock) {
+                eprintln!("Could not answer the requests: {error}");
+            }
+        }
+
+        // Undo and redo commands walk back and forth through the operation journal
+        Commands::Undo { steps } => {
+            for _ in 0..*steps {
+                match storage.undo() {
+                    Ok(operation) => println!("Undid: {operation}"),
+                    Err(journal_error) => {
+                        println!("{journal_error}");
+                        break;
+                    }
+                }
+            }
+            storage.write_storage();
+        }
+        Commands::Redo { steps } => {
+            for _ in 0..*steps {
+                match storage.redo() {
+                    Ok(operation) => println!("Redid: {operation}"),
+                    Err(journal_error) => {
+                        println!("{journal_error}");
+                        break;
+                    }
+                }
+            }
+            storage.write_storage();
+        }
+
+        // Log command lists the operations that can be undone
+        Commands::Log { limit } => display_log(storage.get_journal(), *limit, stdout()),
+    }
+}
+
+fn make_clock(now: Option<DateTime<Utc>>, calendar: Calendar) -> Box<dyn Clock> {
+    match now {
+        None => Box::new(SystemClock::new(calendar)),
+        Some(now) => Box::new(FixedClock::new(now, calendar)),
+    }
+}
+
+/// The passphrase of an encrypted storage, or `None` when the storage is not encrypted
+///
+/// Exits when the storage cannot be unlocked. Unless `interactive`, the passphrase is never
+/// asked for, and the exit code is [`LOCKED_EXIT_CODE`].
+fn unlock(storage_file_path: &str, config: &Config, interactive: bool) -> Option<Passphrase> {
+    if !encryption::is_encrypted_file(storage_file_path) {
+        return None;
+    }
+    let keyfile = config.encryption.keyfile.as_deref();
+    match encryption::unlock(storage_file_path, keyfile, interactive) {
+        Ok(passphrase) => Some(passphrase),
+        Err(encryption_error) => match interactive {
+            true => {
+                println!("{encryption_error}");
+                process::exit(1);
+            }
+            false => {
+                eprintln!("{encryption_error}");
+                process::exit(LOCKED_EXIT_CODE);
+            }
+        },
+    }
+}
+
+/// Print the status line and return the exit code: 0 when topics are due, 1 when none are
+fn run_status(
+    storage_file_path: &str,
+    format: &str,
+    config: &Config,
+    now: Option<DateTime<Utc>>,
+) -> i32 {
+    let passphrase = unlock(storage_file_path, config, false);
+    let document = match read_document(storage_file_path, passphrase.as_ref()) {
+        Ok(document) => document,
+        Err(error) => {
+            eprintln!("Could not read {storage_file_path}: {error}");
+            return 2;
+        }
+    };
+    let clock = make_clock(now, config.apply_to_calendar(document.calendar()));
+    let status = Status::of(&document, clock.as_ref());
+    println!("{}", status.format(format));
+    match status.due {
+        0 => 1,
+        _ => 0,
+    }
+}
+
+fn run_remind(storage_file_path: &str, remind_args: &RemindArgs, config: &Config, cli: &Cli) {
+    if let Some(RemindCommands::Units {
+        every,
+        output_dir,
+        notifier,
+    }) = &remind_args.command
+    {
+        let (executable, working_directory) = match (env::current_exe(), env::current_dir()) {
+            (Ok(executable), Ok(working_directory)) => (executable, working_directory),
+            (Err(error), _) | (_, Err(error)) => {
+                println!("Could not find where revue is run from: {error}");
+                return;
+            }
+        };
+        let command = RemindCommand {
+            executable: executable.to_string_lossy().into_owned(),
+            working_directory: working_directory.to_string_lossy().into_owned(),
+            profile: cli.profile.clone(),
+            config: cli.config.clone(),
+            storage: cli.storage.clone(),
+            notifier: notifier.notifier(),
+        };
+        write_units(&command, *every, output_dir.as_deref());
+        return;
+    }
+
+    let passphrase = unlock(storage_file_path, config, false);
+    let notifier = remind_args.notifier.notifier();
+    let state_file_path = ReminderState::path_for(storage_file_path);
+    loop {
+        match read_document(storage_file_path, passphrase.as_ref()) {
+            Err(error) => eprintln!("Could not read {storage_file_path}: {error}"),
+            Ok(document) => {
+                let clock = make_clock(cli.now, config.apply_to_calendar(document.calendar()));
+                if let Err(remind_error) =
+                    remind(&document, &state_file_path, &notifier, clock.as_ref())
+                {
+                    eprintln!("Could not send the reminder: {remind_error}");
+                }
+            }
+        }
+        if !remind_args.daemon {
+            return;
+        }
+        thread::sleep(Duration::from_secs(remind_args.interval * 60));
+    }
+}
+
+fn write_units(command: &RemindCommand, every: u64, output_dir: Option<&str>) {
+    let (service, timer) = systemd_units(command, every);
+
+    match output_dir {
+        None => {
+            println!("# revue-remind.service\n{service}");
+            println!("# revue-remind.timer\n{timer}");
+        }
+        Some(output_dir) => {
+            let written = fs::create_dir_all(output_dir)
+                .and_then(|_| {
+                    fs::write(Path::new(output_dir).join("revue-remind.service"), service)
+                })
+                .and_then(|_| fs::write(Path::new(output_dir).join("revue-remind.timer"), timer));
+            match written {
+                Ok(_) => println!(
+                    "Wrote the units to {output_dir}. Enable them with `systemctl --user enable --now revue-remind.timer`."
+                ),
+                Err(error) => println!("Could not write the units to {output_dir}: {error}"),
+            }
+        }
+    }
+    match cron_line(command, every) {
+        Ok(cron_line) => println!("# Or add this line to your crontab instead:\n{cron_line}"),
+        Err(remind_error) => println!("# {remind_error}"),
+    }
+}
+
+fn run_config(layered_config: &mut LayeredConfig, command: &ConfigCommands) {
+    match command {
+        ConfigCommands::Get { key } => match layered_config.get(key) {
+            Ok(Some((value, _))) => println!("{value}"),
+            Ok(None) => println!("{key} is not set"),
+            Err(config_error) => println!("{config_error}"),
+        },
+        ConfigCommands::Set { key, value } => match layered_config.set(key, value) {
+            Ok(_) => println!("{key} = {value}"),
+            Err(config_error) => println!("{config_error}"),
+        },
+        ConfigCommands::Unset { key } => match layered_config.unset(key) {
+            Ok(_) => println!("{key} is back to its default"),
+            Err(config_error) => println!("{config_error}"),
+        },
+        ConfigCommands::List => display_config(layered_config, stdout()),
+    }
+}
+
+fn run_sync(
+    storage_file_path: &str,
+    sync_args: &SyncArgs,
+    config: &Config,
+    passphrase: Option<&Passphrase>,
+) {
+    match sync_args.command {
+        Some(SyncCommands::Init) => match sync::init(storage_file_path, passphrase) {
+            Ok(true) => println!(
+                "Every change to the topics is committed now. Run `revue sync` to sync them with a remote."
+            ),
+            Ok(false) => println!("The topics are synced already."),
+            Err(sync_error) => println!("{sync_error}"),
+        },
+        None => {
+            let remote = sync_args.remote.as_deref().or(config.sync.remote.as_deref());
+            match sync::sync(storage_file_path, remote, passphrase) {
+                Ok(outcome) => println!("{outcome}"),
+                Err(sync_error) => println!("{sync_error}"),
+            }
+        }
+    }
+}
+
+fn run_encryption(storage: &mut Storage, encrypted: bool, config: &Config) {
+    let passphrase = match (storage.get_passphrase().cloned(), encrypted) {
+        (Some(_), true) => {
+            println!("The storage is encrypted already.");
+            return;
+        }
+        (None, false) => {
+            println!("The storage is not encrypted.");
+            return;
+        }
+        (None, true) => {
+            let keyfile = config.encryption.keyfile.as_deref();
+            match encryption::choose_passphrase(keyfile) {
+                Ok(passphrase) => passphrase,
+                Err(encryption_error) => {
+                    println!("{encryption_error}");
+                    process::exit(1);
+                }
+            }
+        }
+        (Some(passphrase), false) => passphrase,
+    };
+
+    storage.set_passphrase(encrypted.then(|| passphrase.clone()));
+    storage.write_storage();
+    let storage_file_path = storage.get_storage_file_path();
+    if let Err(backup_error) = convert_backups(storage_file_path, &passphrase, encrypted) {
+        println!("Could not convert the snapshots: {backup_error}");
+    }
+    if !encrypted {
+        println!("Decrypted the storage.");
+        return;
+    }
+    println!("Encrypted the storage.");
+    let history_path = history_path(storage_file_path);
+    if history_path.exists() {
+        println!(
+            "The past changes in {} are not encrypted. Delete the file to keep no plaintext copy of the topics.",
+            history_path.display()
+        );
+    }
+    if sync::is_initialized(storage_file_path) {
+        println!("The earlier commits of `revue sync` still have the topics in plaintext.");
+    }
+}
+
+fn run_profile(profiles: &Profiles, flag: Option<&str>, command: &ProfileCommands) {
+    match command {
+        ProfileCommands::List => match profiles.select(flag) {
+            Ok(in_use) => display_profiles(&profiles.list(), &in_use, stdout()),
+            Err(profile_error) => println!("{profile_error}"),
+        },
+        ProfileCommands::Create { name } => match profiles.create(name) {
+            Ok(_) => println!(
+                "Created the profile '{name}'. Use it with `revue --profile {name}` or `revue profile switch {name}`."
+            ),
+            Err(profile_error) => println!("{profile_error}"),
+        },
+        ProfileCommands::Switch { name } => match profiles.switch(name) {
+            Ok(_) => println!("Switched to the profile '{name}'."),
+            Err(profile_error) => println!("{profile_error}"),
+        },
+        ProfileCommands::Delete { name } => {
+            // The storage is kept, so say where it is while the profile's config can still tell
+            let storage_path = LayeredConfig::load(None, name, &[])
+                .and_then(|layered_config| layered_config.resolve())
+                .map(|config| config.storage_path().to_string());
+            match profiles.delete(name) {
+                Ok(_) => match storage_path {
+                    Ok(storage_path) => println!(
+                        "Deleted the profile '{name}'. Its topics are still in {storage_path}."
+                    ),
+                    Err(_) => println!("Deleted the profile '{name}'."),
+                },
+                Err(profile_error) => println!("{profile_error}"),
+            }
+        }
+    }
+}
+
+fn run_doctor(
+    storage_file_path: &str,
+    fix: bool,
+    config: &Config,
+    passphrase: Option<&Passphrase>,
+    now: Option<DateTime<Utc>>,
+) {
+    let serialized = match encryption::read_file(storage_file_path, passphrase) {
+        Ok(serialized) => serialized,
+        Err(error) => {
+            println!("Could not read {storage_file_path}: {error}");
+            return;
+        }
+    };
+    // The checks have to see the changes in the event log, which are replayed in memory so
+    // nothing is written before `--fix`. A storage file the log cannot be replayed on is checked
+    // as it is.
+    let replayed = match read_log(storage_file_path).map(|records| records.is_empty()) {
+        Ok(false) => load_document(storage_file_path, passphrase).ok(),
+        Ok(true) | Err(_) => None,
+    };
+    let serialized = match &replayed {
+        Some(document) => {
+            serde_json::to_string_pretty(document).expect("The storage document is valid JSON")
+        }
+        None => serialized,
+    };
+
+    let calendar = config.apply_to_calendar(&stored_calendar(&serialized));
+    let clock = make_clock(now, calendar);
+    let diagnosis = diagnose(&serialized, clock.as_ref());
+    if diagnosis.problems.is_empty() {
+        println!("No problems found.");
+        return;
+    }
+    diagnosis
+        .problems
+        .iter()
+        .for_each(|problem| match problem.is_fixable() {
+            true => println!("- {problem}"),
+            false => println!("- {problem} (cannot be fixed automatically)"),
+        });
+
+    match diagnosis.repaired {
+        None => println!(
+            "The storage cannot be repaired automatically. `revue backup list` shows the snapshots to restore from."
+        ),
+        Some(repaired) if fix => match repair(storage_file_path, &repaired, passphrase) {
+            Ok(copy) => {
+                // The repaired storage includes the logged changes, so the log is history now
+                if replayed.is_some() {
+                    if let Err(error) = archive_log(storage_file_path) {
+                        println!("Archiving the event log failed: {error}");
+                    }
+                }
+                println!("Repaired the storage.");
+                if let Some(copy) = copy {
+                    println!("The damaged file was kept at {}", copy.display());
+                }
+            }
+            Err(backup_error) => println!("Repairing the storage failed: {backup_error}"),
+        },
+        Some(_) => println!("Run `revue doctor --fix` to repair these problems."),
+    }
+}
+
+fn change_topic_state(storage: &mut Storage, topic_name: &str, state: TopicState) {
+    match storage.set_topic_state(topic_name.to_string(), state) {
+        Ok(_) => storage.write_storage(),
+        Err(_) => {
+            println!("Review topic was not found.")
+        }
+    }
+}
@@ -0,0 +1,4 @@
+//! The `revue` command line tool, which the binary only hands its arguments to
+
+pub mod args;
+pub mod commands;
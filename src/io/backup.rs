@@ -0,0 +1,627 @@
+//! Dated snapshots of the storage file
+
+use crate::io::encryption::{is_encrypted_file, read_file, write_file, Passphrase};
+use crate::io::events::{archive_log, load_document, read_log, EventError};
+use crate::io::journal::Journal;
+use crate::io::storage::StorageDocument;
+use crate::topics::review_topics::ReviewTopic;
+use chrono::{Datelike, IsoWeek, NaiveDate};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many snapshots of the storage file are kept in the backups directory
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct BackupSettings {
+    /// Number of most recent days to keep a snapshot of
+    pub daily: usize,
+    /// Number of weeks before those days to keep the newest snapshot of
+    pub weekly: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            daily: 7,
+            weekly: 4,
+        }
+    }
+}
+
+impl fmt::Display for BackupSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Daily snapshots kept: {}\nWeekly snapshots kept: {}",
+            self.daily, self.weekly
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    NotFound(NaiveDate),
+    /// The snapshot is not a storage file revue can read
+    Corrupted(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::NotFound(day) => write!(f, "No snapshot was found for {day}."),
+            BackupError::Corrupted(reason) => write!(f, "The snapshot is corrupted: {reason}"),
+            BackupError::Io(error) => write!(f, "Could not access the snapshot: {error}"),
+        }
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(error: io::Error) -> Self {
+        BackupError::Io(error)
+    }
+}
+
+/// A copy of the storage file as it was at the start of a day
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Snapshot {
+    pub day: NaiveDate,
+    pub path: PathBuf,
+}
+
+/// The backups directory next to the storage file
+pub fn backups_dir(storage_file_path: &str) -> PathBuf {
+    Path::new(storage_file_path)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("backups")
+}
+
+fn snapshot_prefix(storage_file_path: &str) -> String {
+    let stem = Path::new(storage_file_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "storage".to_string());
+    format!("{stem}-")
+}
+
+fn snapshot_path(storage_file_path: &str, day: NaiveDate) -> PathBuf {
+    backups_dir(storage_file_path).join(format!(
+        "{}{}.json",
+        snapshot_prefix(storage_file_path),
+        day.format("%Y-%m-%d")
+    ))
+}
+
+/// The snapshots of the given storage file, newest first
+pub fn list_snapshots(storage_file_path: &str) -> Vec<Snapshot> {
+    let prefix = snapshot_prefix(storage_file_path);
+    let entries = match fs::read_dir(backups_dir(storage_file_path)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut snapshots: Vec<Snapshot> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let day = file_name.strip_prefix(&prefix)?.strip_suffix(".json")?;
+            let day = NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?;
+            Some(Snapshot {
+                day,
+                path: entry.path(),
+            })
+        })
+        .collect();
+    snapshots.sort_by_key(|snapshot| Reverse(snapshot.day));
+    snapshots
+}
+
+pub fn find_snapshot(storage_file_path: &str, day: NaiveDate) -> Result<Snapshot, BackupError> {
+    list_snapshots(storage_file_path)
+        .into_iter()
+        .find(|snapshot| snapshot.day == day)
+        .ok_or(BackupError::NotFound(day))
+}
+
+/// Keep a snapshot of the storage file as it was before the first write of the day, then remove
+/// the snapshots that fall out of the rotation
+///
+/// Backups are a safety net, so failures are logged instead of stopping the write.
+pub fn snapshot_before_write(
+    storage_file_path: &str,
+    today: NaiveDate,
+    settings: &BackupSettings,
+    passphrase: Option<&Passphrase>,
+) {
+    if settings.daily == 0 && settings.weekly == 0 {
+        return;
+    }
+    if !snapshot_path(storage_file_path, today).exists() {
+        if let Err(error_log) = take_snapshot(storage_file_path, today, passphrase) {
+            error!("Taking a snapshot failed: {error_log}");
+        }
+    }
+    rotate_snapshots(storage_file_path, settings);
+}
+
+/// Copy the storage file into the backups directory as the snapshot of the given day
+///
+/// The snapshot of an encrypted storage file is encrypted with the same passphrase.
+///
+/// returns: The snapshot, or `None` if there is no storage file yet
+pub fn take_snapshot(
+    storage_file_path: &str,
+    day: NaiveDate,
+    passphrase: Option<&Passphrase>,
+) -> Result<Option<Snapshot>, BackupError> {
+    if !Path::new(storage_file_path).exists() {
+        return Ok(None);
+    }
+    fs::create_dir_all(backups_dir(storage_file_path))?;
+    let path = snapshot_path(storage_file_path, day);
+    info!("Taking a snapshot at {}", path.display());
+    copy_with_log(storage_file_path, &path, passphrase)?;
+    Ok(Some(Snapshot { day, path }))
+}
+
+/// Copy the storage file along with the changes still in its event log, so the copy can be
+/// restored on its own
+fn copy_with_log(
+    storage_file_path: &str,
+    path: &Path,
+    passphrase: Option<&Passphrase>,
+) -> Result<(), BackupError> {
+    match read_log(storage_file_path)?.is_empty() {
+        true => {
+            fs::copy(storage_file_path, path)?;
+        }
+        false => {
+            let document =
+                load_document(storage_file_path, passphrase).map_err(|error| match error {
+                    EventError::Io(error) => BackupError::Io(error),
+                    EventError::InvalidStorage(reason) => BackupError::Corrupted(reason),
+                })?;
+            let serialized = serde_json::to_string_pretty(&document)
+                .expect("The storage document is valid JSON");
+            let passphrase = passphrase.filter(|_| is_encrypted_file(storage_file_path));
+            write_file(path, &serialized, passphrase)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn rotate_snapshots(storage_file_path: &str, settings: &BackupSettings) {
+    let snapshots = list_snapshots(storage_file_path);
+    let days: Vec<NaiveDate> = snapshots.iter().map(|snapshot| snapshot.day).collect();
+    let expired = expired_snapshots(&days, settings);
+
+    snapshots
+        .iter()
+        .filter(|snapshot| expired.contains(&snapshot.day))
+        .for_each(|snapshot| {
+            info!("Removing the snapshot of {}", snapshot.day);
+            if let Err(error_log) = fs::remove_file(&snapshot.path) {
+                warn!("Removing a snapshot failed: {error_log}");
+            }
+        });
+}
+
+/// The days whose snapshots are no longer kept. `days` has to be sorted newest first.
+fn expired_snapshots(days: &[NaiveDate], settings: &BackupSettings) -> Vec<NaiveDate> {
+    let mut kept_weeks: HashSet<IsoWeek> = HashSet::new();
+    days.iter()
+        .skip(settings.daily)
+        .filter(|day| {
+            let week = day.iso_week();
+            let keep = !kept_weeks.contains(&week) && kept_weeks.len() < settings.weekly;
+            if keep {
+                kept_weeks.insert(week);
+            }
+            !keep
+        })
+        .copied()
+        .collect()
+}
+
+/// Read a snapshot, making sure it is a storage file revue can load
+pub fn read_snapshot(
+    snapshot: &Snapshot,
+    passphrase: Option<&Passphrase>,
+) -> Result<StorageDocument, BackupError> {
+    let serialized = read_file(&snapshot.path, passphrase)?;
+    let document = StorageDocument::from_json(&serialized)
+        .map_err(|error| BackupError::Corrupted(error.to_string()))?;
+
+    let mut names = HashSet::new();
+    if let Some(duplicate) = document
+        .review_topics()
+        .iter()
+        .find(|topic| !names.insert(topic.topic_name.clone()))
+    {
+        return Err(BackupError::Corrupted(format!(
+            "'{}' is in it more than once",
+            duplicate.topic_name
+        )));
+    }
+    Ok(document)
+}
+
+/// Replace the storage file with a snapshot, once the snapshot passed the integrity check
+///
+/// The current storage file is kept as `<name>-before-restore.json` in the backups directory,
+/// and its journal as `<name>-before-restore.journal.json`.
+pub fn restore_snapshot(
+    storage_file_path: &str,
+    snapshot: &Snapshot,
+    passphrase: Option<&Passphrase>,
+) -> Result<(), BackupError> {
+    read_snapshot(snapshot, passphrase)?;
+
+    keep_copy(storage_file_path, "before-restore", passphrase)?;
+    info!("Restoring the snapshot of {}", snapshot.day);
+    fs::copy(&snapshot.path, storage_file_path)?;
+    // The logged changes were made after the snapshot, so they are history now
+    archive_log(storage_file_path)?;
+    // The journaled operations were also made after it, so undoing them would change the wrong
+    // topics
+    let journal_path = Journal::path_for(storage_file_path);
+    if Path::new(&journal_path).exists() {
+        fs::rename(
+            &journal_path,
+            backups_dir(storage_file_path).join(format!(
+                "{}before-restore.journal.json",
+                snapshot_prefix(storage_file_path)
+            )),
+        )?;
+    }
+    Ok(())
+}
+
+/// Copy the storage file into the backups directory as `<name>-<label>.json`, outside of the
+/// rotation, before it is replaced
+///
+/// The copy has the changes still in the event log. If the storage file is too damaged for them
+/// to be replayed, it is copied as it is, and the log is left for the history.
+///
+/// returns: The path of the copy, or `None` if there is no storage file
+pub fn keep_copy(
+    storage_file_path: &str,
+    label: &str,
+    passphrase: Option<&Passphrase>,
+) -> Result<Option<PathBuf>, BackupError> {
+    if !Path::new(storage_file_path).exists() {
+        return Ok(None);
+    }
+    fs::create_dir_all(backups_dir(storage_file_path))?;
+    let path = backups_dir(storage_file_path).join(format!(
+        "{}{label}.json",
+        snapshot_prefix(storage_file_path)
+    ));
+    match copy_with_log(storage_file_path, &path, passphrase) {
+        Err(BackupError::Corrupted(reason)) => {
+            warn!("Keeping the storage file without its event log: {reason}");
+            fs::copy(storage_file_path, &path)?;
+        }
+        result => result?,
+    }
+    Ok(Some(path))
+}
+
+/// Encrypt the snapshots and the copies kept in the backups directory with the passphrase, or
+/// decrypt them with it
+///
+/// returns: How many files were changed
+pub fn convert_backups(
+    storage_file_path: &str,
+    passphrase: &Passphrase,
+    encrypted: bool,
+) -> Result<usize, BackupError> {
+    let prefix = snapshot_prefix(storage_file_path);
+    let entries = match fs::read_dir(backups_dir(storage_file_path)) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(0),
+    };
+
+    let mut converted = 0;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        if !file_name.starts_with(&prefix)
+            || !file_name.ends_with(".json")
+            || is_encrypted_file(&path) == encrypted
+        {
+            continue;
+        }
+        let serialized = read_file(&path, Some(passphrase))?;
+        write_file(&path, &serialized, encrypted.then_some(passphrase))?;
+        converted += 1;
+    }
+    Ok(converted)
+}
+
+/// A difference between the topics of two storage files
+#[derive(Debug, PartialEq, Eq)]
+pub enum TopicChange {
+    Added(String),
+    Removed(String),
+    Changed {
+        topic_name: String,
+        changes: Vec<String>,
+    },
+}
+
+impl fmt::Display for TopicChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TopicChange::Added(topic_name) => write!(f, "+ {topic_name}"),
+            TopicChange::Removed(topic_name) => write!(f, "- {topic_name}"),
+            TopicChange::Changed {
+                topic_name,
+                changes,
+            } => write!(f, "~ {topic_name}: {}", changes.join(", ")),
+        }
+    }
+}
+
+/// What changed from the `old` topics to the `new` ones, matching topics by name
+pub fn diff_topics(old: &[ReviewTopic], new: &[ReviewTopic]) -> Vec<TopicChange> {
+    let find = |list: &[ReviewTopic], topic_name: &str| -> Option<ReviewTopic> {
+        list.iter()
+            .find(|topic| topic.topic_name == topic_name)
+            .cloned()
+    };
+
+    let mut topic_changes: Vec<TopicChange> = old
+        .iter()
+        .filter_map(|old_topic| match find(new, &old_topic.topic_name) {
+            None => Some(TopicChange::Removed(old_topic.topic_name.clone())),
+            Some(new_topic) => {
+                let changes = describe_changes(old_topic, &new_topic);
+                match changes.is_empty() {
+                    true => None,
+                    false => Some(TopicChange::Changed {
+                        topic_name: old_topic.topic_name.clone(),
+                        changes,
+                    }),
+                }
+            }
+        })
+        .collect();
+
+    topic_changes.extend(
+        new.iter()
+            .filter(|new_topic| find(old, &new_topic.topic_name).is_none())
+            .map(|new_topic| TopicChange::Added(new_topic.topic_name.clone())),
+    );
+    topic_changes
+}
+
+fn describe_changes(old: &ReviewTopic, new: &ReviewTopic) -> Vec<String> {
+    let mut changes = Vec::new();
+    if old.review_history.len() != new.review_history.len() {
+        changes.push(format!(
+            "reviews {} -> {}",
+            old.review_history.len(),
+            new.review_history.len()
+        ));
+    }
+    if old.next_review_gap != new.next_review_gap {
+        changes.push(format!(
+            "gap {} -> {}",
+            old.next_review_gap, new.next_review_gap
+        ));
+    }
+    if old.state != new.state {
+        changes.push(format!("state {} -> {}", old.state, new.state));
+    }
+    if old.scheduled_review != new.scheduled_review {
+        changes.push("rescheduled".to_string());
+    }
+    if old.tags != new.tags || old.priority != new.priority {
+        changes.push("tags or priority".to_string());
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::backup::{
+        backups_dir, diff_topics, expired_snapshots, find_snapshot, list_snapshots,
+        restore_snapshot, rotate_snapshots, snapshot_path, take_snapshot, BackupError,
+        BackupSettings, TopicChange,
+    };
+    use crate::io::encryption::read_file;
+    use crate::io::events::{append_to_log, read_log, Event, EventRecord};
+    use crate::io::journal::Journal;
+    use crate::io::storage::StorageDocument;
+    use crate::topics::clock::SystemClock;
+    use crate::topics::review_topics::ReviewTopic;
+    use chrono::{Days, NaiveDate};
+    use std::fs;
+    use std::path::Path;
+
+    static BEFORE: &str = r#"{"review_topic_list": [{"topic_name": "before", "last_reviewed": "2024-06-01T12:00:00Z", "next_review_gap": "Week"}]}"#;
+    static AFTER: &str = r#"{"review_topic_list": [{"topic_name": "after", "last_reviewed": "2024-06-02T12:00:00Z", "next_review_gap": "Day"}]}"#;
+
+    /// A storage file in a directory of its own, so its backups do not mix with other tests'
+    fn storage_file(dir: &str) -> String {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        format!("{dir}/storage.json")
+    }
+
+    #[test]
+    fn test_backups_dir() {
+        assert_eq!(
+            Path::new("./data/backups"),
+            backups_dir("./data/storage.json")
+        );
+    }
+
+    #[test]
+    fn test_restore_snapshot() {
+        let storage_file_path = storage_file("./data/test_restore_snapshot");
+        let day = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        assert_eq!(None, take_snapshot(&storage_file_path, day, None).unwrap());
+
+        fs::write(&storage_file_path, BEFORE).unwrap();
+        let snapshot = take_snapshot(&storage_file_path, day, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(BEFORE, fs::read_to_string(&snapshot.path).unwrap());
+        fs::write(&storage_file_path, AFTER).unwrap();
+        let journal_path = Journal::path_for(&storage_file_path);
+        Journal::default().write(&journal_path, None);
+
+        restore_snapshot(&storage_file_path, &snapshot, None).unwrap();
+        assert_eq!(BEFORE, fs::read_to_string(&storage_file_path).unwrap());
+        assert!(!Path::new(&journal_path).exists());
+        let backups = backups_dir(&storage_file_path);
+        assert_eq!(
+            AFTER,
+            fs::read_to_string(backups.join("storage-before-restore.json")).unwrap()
+        );
+        assert!(backups.join("storage-before-restore.journal.json").exists());
+    }
+
+    #[test]
+    fn test_restore_keeps_the_logged_changes() {
+        let storage_file_path = storage_file("./data/test_restore_keeps_the_logged_changes");
+        let day = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        fs::write(&storage_file_path, BEFORE).unwrap();
+        let snapshot = take_snapshot(&storage_file_path, day, None)
+            .unwrap()
+            .unwrap();
+        let after = StorageDocument::from_json(AFTER).unwrap();
+        let records = vec![EventRecord {
+            at: after.review_topics()[0].last_reviewed,
+            sequence: 1,
+            event: Event::TopicAdded {
+                topic: after.review_topics()[0].clone(),
+            },
+        }];
+        append_to_log(&storage_file_path, &records).unwrap();
+
+        restore_snapshot(&storage_file_path, &snapshot, None).unwrap();
+        let kept = read_file(
+            backups_dir(&storage_file_path).join("storage-before-restore.json"),
+            None,
+        )
+        .unwrap();
+        let names: Vec<String> = StorageDocument::from_json(&kept)
+            .unwrap()
+            .review_topics()
+            .iter()
+            .map(|topic| topic.topic_name.clone())
+            .collect();
+        assert_eq!(vec!["before", "after"], names);
+        assert!(read_log(&storage_file_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corrupted_snapshots_are_not_restored() {
+        let storage_file_path = storage_file("./data/test_corrupted_snapshot");
+        let day = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        fs::write(&storage_file_path, AFTER).unwrap();
+        fs::create_dir_all(backups_dir(&storage_file_path)).unwrap();
+        fs::write(
+            snapshot_path(&storage_file_path, day),
+            "{\"review_topic_list\": [",
+        )
+        .unwrap();
+
+        let snapshot = find_snapshot(&storage_file_path, day).unwrap();
+        assert!(matches!(
+            restore_snapshot(&storage_file_path, &snapshot, None),
+            Err(BackupError::Corrupted(_))
+        ));
+        assert_eq!(AFTER, fs::read_to_string(&storage_file_path).unwrap());
+        assert!(matches!(
+            find_snapshot(&storage_file_path, day + Days::new(1)),
+            Err(BackupError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_rotate_snapshots() {
+        let storage_file_path = storage_file("./data/test_rotate_snapshots");
+        fs::write(&storage_file_path, BEFORE).unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 6, 30).unwrap();
+        for days in 0..10 {
+            take_snapshot(&storage_file_path, today - Days::new(days), None).unwrap();
+        }
+
+        let settings = BackupSettings {
+            daily: 2,
+            weekly: 1,
+        };
+        rotate_snapshots(&storage_file_path, &settings);
+        let days: Vec<NaiveDate> = list_snapshots(&storage_file_path)
+            .iter()
+            .map(|snapshot| snapshot.day)
+            .collect();
+        assert_eq!(
+            vec![today, today - Days::new(1), today - Days::new(2)],
+            days
+        );
+    }
+
+    #[test]
+    fn test_expired_snapshots() {
+        let settings = BackupSettings {
+            daily: 3,
+            weekly: 2,
+        };
+        // A Sunday, followed by 20 days of snapshots going back in time
+        let today = NaiveDate::from_ymd_opt(2024, 6, 30).unwrap();
+        let days: Vec<NaiveDate> = (0..21).map(|days| today - Days::new(days)).collect();
+
+        let expired = expired_snapshots(&days, &settings);
+        let kept: Vec<NaiveDate> = days
+            .iter()
+            .filter(|day| !expired.contains(day))
+            .copied()
+            .collect();
+
+        // The last 3 days, then the newest snapshot of each of the 2 weeks before them
+        assert_eq!(
+            vec![
+                today,
+                today - Days::new(1),
+                today - Days::new(2),
+                today - Days::new(3),
+                today - Days::new(7),
+            ],
+            kept
+        );
+    }
+
+    #[test]
+    fn test_diff_topics() {
+        let clock = SystemClock::default();
+        let kept = ReviewTopic::new("kept".to_string(), &clock);
+        let mut reviewed = kept.clone();
+        reviewed.review(&clock);
+        let removed = ReviewTopic::new("removed".to_string(), &clock);
+        let added = ReviewTopic::new("added".to_string(), &clock);
+
+        assert_eq!(
+            vec![
+                TopicChange::Changed {
+                    topic_name: "kept".to_string(),
+                    changes: vec!["reviews 0 -> 1".to_string(), "gap Day -> Week".to_string()],
+                },
+                TopicChange::Removed("removed".to_string()),
+                TopicChange::Added("added".to_string()),
+            ],
+            diff_topics(&[kept, removed], &[reviewed, added])
+        );
+    }
+}
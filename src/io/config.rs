@@ -0,0 +1,788 @@
+//! Settings read from `~/.config/revue/config.toml`, the environment and the command line
+//!
+//! Every setting has a dotted key such as `display.table_style`. Its value is taken from the
+//! first of these layers that sets it:
+//!
+//! 1. a `--set key=value` flag, or a dedicated flag like `--storage`
+//! 2. an environment variable named after the key, like `REVUE_DISPLAY_TABLE_STYLE`
+//! 3. the config file of the profile in use
+//! 4. the profile, which picks its own storage file
+//! 5. the default
+//!
+//! The daily limits, the review order and the calendar have no default here. When they are not
+//! set, the values kept in the storage by `revue limits` and `revue timezone` are used. Hook
+//! commands have no default either; they run alongside the executables in the hooks directory.
+
+use crate::io::console::TableStyle;
+use crate::io::hooks::HookEvent;
+use crate::io::profiles;
+use crate::io::profiles::Profiles;
+use crate::io::storage::DEFAULT_STORAGE_PATH;
+use crate::topics::calendar::Calendar;
+use crate::topics::review_topics::GapLengths;
+use crate::topics::scheduling::{ReviewOrder, ScheduleSettings, Scheduler};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use toml::{Table, Value};
+
+/// The kind of value a setting takes, to parse it from the command line and the environment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Text,
+    Integer,
+}
+
+/// A setting that can be read and changed with `revue config`
+pub struct Key {
+    pub name: &'static str,
+    pub description: &'static str,
+    kind: ValueKind,
+    /// `None` when the storage decides the value, or the setting is off
+    default: Option<&'static str>,
+}
+
+/// Every setting
+pub const KEYS: &[Key] = &[
+    Key {
+        name: "storage.path",
+        description: "File the topics are kept in",
+        kind: ValueKind::Text,
+        default: Some(DEFAULT_STORAGE_PATH),
+    },
+    Key {
+        name: "scheduler.kind",
+        description: "How the review gap moves on after a review: overdue-aware or fixed",
+        kind: ValueKind::Text,
+        default: Some("overdue-aware"),
+    },
+    Key {
+        name: "scheduler.day_gap",
+        description: "Number of days the first review gap lasts",
+        kind: ValueKind::Integer,
+        default: Some("1"),
+    },
+    Key {
+        name: "scheduler.week_gap",
+        description: "Number of days the second review gap lasts",
+        kind: ValueKind::Integer,
+        default: Some("7"),
+    },
+    Key {
+        name: "scheduler.month_gap",
+        description: "Number of days the last review gap lasts",
+        kind: ValueKind::Integer,
+        default: Some("30"),
+    },
+    Key {
+        name: "limits.max_reviews_per_day",
+        description: "Maximum number of already reviewed topics to show per day",
+        kind: ValueKind::Integer,
+        default: None,
+    },
+    Key {
+        name: "limits.max_new_per_day",
+        description: "Maximum number of never reviewed topics to show per day",
+        kind: ValueKind::Integer,
+        default: None,
+    },
+    Key {
+        name: "limits.review_order",
+        description:
+            "Order of today's topics: most-overdue, random, shortest-interval, tag or priority",
+        kind: ValueKind::Text,
+        default: None,
+    },
+    Key {
+        name: "display.table_style",
+        description: "Style of the tables: sharp, ascii, modern, rounded, psql, markdown or blank",
+        kind: ValueKind::Text,
+        default: Some("sharp"),
+    },
+    Key {
+        name: "calendar.timezone",
+        description: "Timezone the days are counted in, like Europe/Berlin",
+        kind: ValueKind::Text,
+        default: None,
+    },
+    Key {
+        name: "calendar.day_rollover_hour",
+        description: "Hour of the day at which the next day starts, from 0 to 23",
+        kind: ValueKind::Integer,
+        default: None,
+    },
+    Key {
+        name: "sync.remote",
+        description: "Git remote `revue sync` pulls from and pushes to, like a path or an ssh url",
+        kind: ValueKind::Text,
+        default: None,
+    },
+    Key {
+        name: "encryption.keyfile",
+        description: "File whose first line is the passphrase of an encrypted storage",
+        kind: ValueKind::Text,
+        default: None,
+    },
+    Key {
+        name: "hooks.post_add",
+        description: "Shell command run after a topic was added, with the topic on stdin",
+        kind: ValueKind::Text,
+        default: None,
+    },
+    Key {
+        name: "hooks.post_review",
+        description: "Shell command run after a topic was reviewed, with the topic on stdin",
+        kind: ValueKind::Text,
+        default: None,
+    },
+    Key {
+        name: "hooks.post_remove",
+        description: "Shell command run after a topic was removed, with the topic on stdin",
+        kind: ValueKind::Text,
+        default: None,
+    },
+    Key {
+        name: "hooks.post_rename",
+        description: "Shell command run after a topic was renamed, with the topic on stdin",
+        kind: ValueKind::Text,
+        default: None,
+    },
+    Key {
+        name: "hooks.daily_first_run",
+        description: "Shell command run on the first run of a day, with today's topics on stdin",
+        kind: ValueKind::Text,
+        default: None,
+    },
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        reason: String,
+    },
+    /// The config file is not valid TOML, or has settings that are not valid
+    InvalidFile {
+        path: PathBuf,
+        reason: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownKey(key) => write!(f, "There is no setting '{key}'."),
+            ConfigError::InvalidValue { key, reason } => {
+                write!(f, "The value of '{key}' is not valid: {reason}")
+            }
+            ConfigError::InvalidFile { path, reason } => {
+                write!(
+                    f,
+                    "The config file {} is not valid: {reason}",
+                    path.display()
+                )
+            }
+            ConfigError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub path: Option<String>,
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchedulerConfig {
+    pub kind: Option<Scheduler>,
+    pub day_gap: Option<u64>,
+    pub week_gap: Option<u64>,
+    pub month_gap: Option<u64>,
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_reviews_per_day: Option<usize>,
+    pub max_new_per_day: Option<usize>,
+    pub review_order: Option<ReviewOrder>,
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub table_style: Option<TableStyle>,
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarConfig {
+    pub timezone: Option<Tz>,
+    pub day_rollover_hour: Option<u32>,
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    pub remote: Option<String>,
+}
+
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    pub keyfile: Option<String>,
+}
+
+/// Shell commands run as hooks, after the hook executables
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub post_add: Option<String>,
+    pub post_review: Option<String>,
+    pub post_remove: Option<String>,
+    pub post_rename: Option<String>,
+    pub daily_first_run: Option<String>,
+}
+
+impl HooksConfig {
+    pub fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::PostAdd => self.post_add.as_deref(),
+            HookEvent::PostReview => self.post_review.as_deref(),
+            HookEvent::PostRemove => self.post_remove.as_deref(),
+            HookEvent::PostRename => self.post_rename.as_deref(),
+            HookEvent::DailyFirstRun => self.daily_first_run.as_deref(),
+        }
+    }
+}
+
+/// The settings of all layers combined. Settings no layer sets are `None`.
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub storage: StorageConfig,
+    pub scheduler: SchedulerConfig,
+    pub limits: LimitsConfig,
+    pub display: DisplayConfig,
+    pub calendar: CalendarConfig,
+    pub sync: SyncConfig,
+    pub encryption: EncryptionConfig,
+    pub hooks: HooksConfig,
+}
+
+impl Config {
+    pub fn storage_path(&self) -> &str {
+        self.storage.path.as_deref().unwrap_or(DEFAULT_STORAGE_PATH)
+    }
+
+    pub fn table_style(&self) -> TableStyle {
+        self.display.table_style.unwrap_or_default()
+    }
+
+    /// The schedule settings kept in the storage, with the ones set here taking precedence
+    pub fn apply_to_schedule_settings(&self, settings: &ScheduleSettings) -> ScheduleSettings {
+        ScheduleSettings {
+            max_reviews_per_day: self
+                .limits
+                .max_reviews_per_day
+                .or(settings.max_reviews_per_day),
+            max_new_per_day: self.limits.max_new_per_day.or(settings.max_new_per_day),
+            review_order: self.limits.review_order.unwrap_or(settings.review_order),
+            scheduler: self.scheduler.kind.unwrap_or(settings.scheduler),
+        }
+    }
+
+    /// The calendar kept in the storage, with the settings set here taking precedence
+    pub fn apply_to_calendar(&self, calendar: &Calendar) -> Calendar {
+        Calendar {
+            timezone: self.calendar.timezone.or(calendar.timezone),
+            day_rollover_hour: self
+                .calendar
+                .day_rollover_hour
+                .unwrap_or(calendar.day_rollover_hour),
+            gap_lengths: GapLengths {
+                day: self.scheduler.day_gap.unwrap_or(calendar.gap_lengths.day),
+                week: self.scheduler.week_gap.unwrap_or(calendar.gap_lengths.week),
+                month: self
+                    .scheduler
+                    .month_gap
+                    .unwrap_or(calendar.gap_lengths.month),
+            },
+        }
+    }
+
+    /// Check the settings whose type allows values that make no sense
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key: &str, reason: &str| {
+            Err(ConfigError::InvalidValue {
+                key: key.to_string(),
+                reason: reason.to_string(),
+            })
+        };
+        if self
+            .calendar
+            .day_rollover_hour
+            .is_some_and(|hour| hour >= 24)
+        {
+            return invalid(
+                "calendar.day_rollover_hour",
+                "the hour has to be from 0 to 23",
+            );
+        }
+        let gap_lengths = self.apply_to_calendar(&Calendar::default()).gap_lengths;
+        if gap_lengths.day == 0 {
+            return invalid("scheduler.day_gap", "a gap lasts at least 1 day");
+        }
+        if gap_lengths.week < gap_lengths.day {
+            return invalid(
+                "scheduler.week_gap",
+                "the second gap cannot be shorter than the first",
+            );
+        }
+        if gap_lengths.month < gap_lengths.week {
+            return invalid(
+                "scheduler.month_gap",
+                "the last gap cannot be shorter than the second",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Where a setting's value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File,
+    Environment,
+    Flag,
+    Profile,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File => write!(f, "config file"),
+            Source::Environment => write!(f, "environment"),
+            Source::Flag => write!(f, "command line"),
+            Source::Profile => write!(f, "profile"),
+        }
+    }
+}
+
+/// The config file and the settings given on the command line and in the environment, kept
+/// apart so `revue config set` only changes the file
+#[derive(Debug, Default)]
+pub struct LayeredConfig {
+    file_path: Option<PathBuf>,
+    profile: Table,
+    file: Table,
+    environment: Table,
+    flags: Table,
+}
+
+impl LayeredConfig {
+    /// Read the config file of a profile and the environment
+    ///
+    /// `file_path` overrides where the config file is looked for, and `flags` are `key=value`
+    /// settings from the command line.
+    pub fn load(
+        file_path: Option<&str>,
+        profile: &str,
+        flags: &[(String, String)],
+    ) -> Result<Self, ConfigError> {
+        let file_path = file_path
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("REVUE_CONFIG").map(PathBuf::from))
+            .or_else(|| Profiles::new(config_dir()).config_file_path(profile));
+        let mut profile_settings = Table::new();
+        if let Some(storage_path) = profiles::storage_path(profile) {
+            insert(
+                &mut profile_settings,
+                "storage.path",
+                Value::String(storage_path),
+            );
+        }
+        let file = match &file_path {
+            None => Table::new(),
+            Some(file_path) => read_file(file_path)?,
+        };
+        let environment = KEYS
+            .iter()
+            .filter_map(|key| {
+                env::var(environment_variable(key.name))
+                    .ok()
+                    .map(|value| (key.name.to_string(), value))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(LayeredConfig {
+            file_path,
+            profile: profile_settings,
+            file,
+            environment: table_from_pairs(&environment)?,
+            flags: table_from_pairs(flags)?,
+        })
+    }
+
+    pub fn file_path(&self) -> Option<&Path> {
+        self.file_path.as_deref()
+    }
+
+    /// The settings of all layers combined
+    pub fn resolve(&self) -> Result<Config, ConfigError> {
+        let mut merged = self.profile.clone();
+        merge(&mut merged, &self.file);
+        merge(&mut merged, &self.environment);
+        merge(&mut merged, &self.flags);
+        let config: Config =
+            Value::Table(merged)
+                .try_into()
+                .map_err(|error: toml::de::Error| ConfigError::InvalidValue {
+                    key: "config".to_string(),
+                    reason: error.message().to_string(),
+                })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The value of a setting and where it came from, or `None` when it is left to the storage
+    pub fn get(&self, key: &str) -> Result<Option<(String, Source)>, ConfigError> {
+        let key = find_key(key)?;
+        let layers = [
+            (&self.flags, Source::Flag),
+            (&self.environment, Source::Environment),
+            (&self.file, Source::File),
+            (&self.profile, Source::Profile),
+        ];
+        for (table, source) in layers {
+            if let Some(value) = lookup(table, key.name) {
+                return Ok(Some((display_value(value), source)));
+            }
+        }
+        Ok(key
+            .default
+            .map(|default| (default.to_string(), Source::Default)))
+    }
+
+    /// Change a setting in the config file, after checking the value is valid
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let key = find_key(key)?;
+        let mut file = self.file.clone();
+        insert(&mut file, key.name, parse_value(key, value)?);
+        Value::Table(file.clone())
+            .try_into::<Config>()
+            .map_err(|error| ConfigError::InvalidValue {
+                key: key.name.to_string(),
+                reason: error.message().to_string(),
+            })?
+            .validate()?;
+        self.file = file;
+        self.write_file()
+    }
+
+    /// Remove a setting from the config file, going back to its default
+    pub fn unset(&mut self, key: &str) -> Result<(), ConfigError> {
+        let key = find_key(key)?;
+        let (section, name) = key.name.split_once('.').expect("Keys have a section");
+        if let Some(Value::Table(table)) = self.file.get_mut(section) {
+            table.remove(name);
+        }
+        self.write_file()
+    }
+
+    fn write_file(&self) -> Result<(), ConfigError> {
+        let Some(file_path) = &self.file_path else {
+            return Err(ConfigError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                "There is no home directory to keep the config file in.",
+            )));
+        };
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let serialized = toml::to_string(&self.file).expect("The config is valid TOML");
+        fs::write(file_path, serialized)?;
+        Ok(())
+    }
+}
+
+/// `$XDG_CONFIG_HOME/revue`, or `~/.config/revue`
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|config_home| !config_home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("revue"))
+}
+
+fn read_file(file_path: &Path) -> Result<Table, ConfigError> {
+    let serialized = match fs::read_to_string(file_path) {
+        Ok(serialized) => serialized,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Table::new()),
+        Err(error) => return Err(error.into()),
+    };
+    let invalid_file = |reason: String| ConfigError::InvalidFile {
+        path: file_path.to_path_buf(),
+        reason,
+    };
+    let file: Table = serialized
+        .parse()
+        .map_err(|error: toml::de::Error| invalid_file(error.message().to_string()))?;
+    Value::Table(file.clone())
+        .try_into::<Config>()
+        .map_err(|error| invalid_file(error.message().to_string()))?
+        .validate()
+        .map_err(|error| invalid_file(error.to_string()))?;
+    Ok(file)
+}
+
+/// `display.table_style` is read from `REVUE_DISPLAY_TABLE_STYLE`
+pub fn environment_variable(key: &str) -> String {
+    format!("REVUE_{}", key.replace('.', "_").to_uppercase())
+}
+
+fn find_key(name: &str) -> Result<&'static Key, ConfigError> {
+    KEYS.iter()
+        .find(|key| key.name == name)
+        .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))
+}
+
+fn parse_value(key: &Key, value: &str) -> Result<Value, ConfigError> {
+    match key.kind {
+        ValueKind::Text => Ok(Value::String(value.to_string())),
+        ValueKind::Integer => {
+            value
+                .parse::<i64>()
+                .map(Value::Integer)
+                .map_err(|error| ConfigError::InvalidValue {
+                    key: key.name.to_string(),
+                    reason: error.to_string(),
+                })
+        }
+    }
+}
+
+fn table_from_pairs(pairs: &[(String, String)]) -> Result<Table, ConfigError> {
+    let mut table = Table::new();
+    for (name, value) in pairs {
+        let key = find_key(name)?;
+        insert(&mut table, key.name, parse_value(key, value)?);
+    }
+    Ok(table)
+}
+
+fn insert(table: &mut Table, key: &str, value: Value) {
+    let (section, name) = key.split_once('.').expect("Keys have a section");
+    let section = table
+        .entry(section)
+        .or_insert_with(|| Value::Table(Table::new()));
+    if let Value::Table(section) = section {
+        section.insert(name.to_string(), value);
+    }
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let (section, name) = key.split_once('.').expect("Keys have a section");
+    table.get(section)?.as_table()?.get(name)
+}
+
+/// Copy the settings of `upper` over the ones of `lower`
+fn merge(lower: &mut Table, upper: &Table) {
+    for (name, value) in upper {
+        match (lower.get_mut(name), value) {
+            (Some(Value::Table(lower)), Value::Table(upper)) => merge(lower, upper),
+            _ => {
+                lower.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::config::{environment_variable, LayeredConfig, Source};
+    use crate::io::console::TableStyle;
+    use crate::io::profiles::DEFAULT_PROFILE;
+    use crate::topics::calendar::Calendar;
+    use crate::topics::review_topics::GapLengths;
+    use crate::topics::scheduling::{ReviewOrder, ScheduleSettings, Scheduler};
+    use serial_test::serial;
+    use std::fs;
+
+    static CONFIG_PATH: &str = "./data/test_config/config.toml";
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    #[serial]
+    fn test_layers() {
+        let _ = fs::remove_dir_all("./data/test_config");
+        let mut config = LayeredConfig::load(Some(CONFIG_PATH), DEFAULT_PROFILE, &[]).unwrap();
+        assert_eq!(
+            Some(("sharp".to_string(), Source::Default)),
+            config.get("display.table_style").unwrap()
+        );
+        assert_eq!(None, config.get("limits.max_new_per_day").unwrap());
+
+        config.set("display.table_style", "rounded").unwrap();
+        config.set("limits.max_new_per_day", "5").unwrap();
+        let reloaded = LayeredConfig::load(Some(CONFIG_PATH), DEFAULT_PROFILE, &[]).unwrap();
+        assert_eq!(
+            Some(("5".to_string(), Source::File)),
+            reloaded.get("limits.max_new_per_day").unwrap()
+        );
+        assert_eq!(
+            TableStyle::Rounded,
+            reloaded.resolve().unwrap().table_style()
+        );
+
+        let flagged = LayeredConfig::load(
+            Some(CONFIG_PATH),
+            DEFAULT_PROFILE,
+            &pairs(&[("display.table_style", "markdown")]),
+        )
+        .unwrap();
+        assert_eq!(
+            Some(("markdown".to_string(), Source::Flag)),
+            flagged.get("display.table_style").unwrap()
+        );
+        assert_eq!(
+            TableStyle::Markdown,
+            flagged.resolve().unwrap().table_style()
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_invalid_values_are_rejected() {
+        let _ = fs::remove_dir_all("./data/test_config");
+        let mut config = LayeredConfig::load(Some(CONFIG_PATH), DEFAULT_PROFILE, &[]).unwrap();
+        assert!(config.set("display.table_style", "wavy").is_err());
+        assert!(config.set("limits.max_new_per_day", "many").is_err());
+        assert!(config.set("display.colour", "red").is_err());
+        assert!(config.set("calendar.day_rollover_hour", "24").is_err());
+        assert!(config.set("calendar.day_rollover_hour", "23").is_ok());
+        assert!(config.set("scheduler.day_gap", "0").is_err());
+        assert!(config.set("scheduler.month_gap", "5").is_err());
+        assert!(config.set("scheduler.week_gap", "3").is_ok());
+        assert!(LayeredConfig::load(
+            Some(CONFIG_PATH),
+            DEFAULT_PROFILE,
+            &pairs(&[("calendar.day_rollover_hour", "30")])
+        )
+        .unwrap()
+        .resolve()
+        .is_err());
+        assert!(LayeredConfig::load(
+            Some(CONFIG_PATH),
+            DEFAULT_PROFILE,
+            &pairs(&[("nope.nope", "1")])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_apply_to_schedule_settings() {
+        let config = LayeredConfig::load(
+            Some("./data/test_config/missing.toml"),
+            DEFAULT_PROFILE,
+            &pairs(&[("scheduler.kind", "fixed"), ("limits.max_new_per_day", "2")]),
+        )
+        .unwrap()
+        .resolve()
+        .unwrap();
+        let stored = ScheduleSettings {
+            max_reviews_per_day: Some(10),
+            max_new_per_day: Some(5),
+            review_order: ReviewOrder::Random,
+            scheduler: Scheduler::OverdueAware,
+        };
+        let settings = config.apply_to_schedule_settings(&stored);
+        assert_eq!(Some(10), settings.max_reviews_per_day);
+        assert_eq!(Some(2), settings.max_new_per_day);
+        assert_eq!(ReviewOrder::Random, settings.review_order);
+        assert_eq!(Scheduler::Fixed, settings.scheduler);
+    }
+
+    #[test]
+    fn test_apply_to_calendar() {
+        let config = LayeredConfig::load(
+            Some("./data/test_config/missing.toml"),
+            DEFAULT_PROFILE,
+            &pairs(&[
+                ("scheduler.week_gap", "5"),
+                ("calendar.day_rollover_hour", "4"),
+            ]),
+        )
+        .unwrap()
+        .resolve()
+        .unwrap();
+        let calendar = config.apply_to_calendar(&Calendar::default());
+        assert_eq!(4, calendar.day_rollover_hour);
+        assert_eq!(
+            GapLengths {
+                day: 1,
+                week: 5,
+                month: 30
+            },
+            calendar.gap_lengths
+        );
+    }
+
+    #[test]
+    fn test_profile_picks_the_storage() {
+        let layered_config =
+            LayeredConfig::load(Some("./data/test_config/missing.toml"), "work", &[]).unwrap();
+        assert_eq!(
+            Some((
+                "./data/profiles/work/storage.json".to_string(),
+                Source::Profile
+            )),
+            layered_config.get("storage.path").unwrap()
+        );
+        let flagged = LayeredConfig::load(
+            Some("./data/test_config/missing.toml"),
+            "work",
+            &pairs(&[("storage.path", "other.json")]),
+        )
+        .unwrap();
+        assert_eq!("other.json", flagged.resolve().unwrap().storage_path());
+    }
+
+    #[test]
+    fn test_environment_variable() {
+        assert_eq!(
+            "REVUE_DISPLAY_TABLE_STYLE",
+            environment_variable("display.table_style")
+        );
+    }
+}
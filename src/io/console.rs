@@ -1,47 +1,67 @@
+//! Rendering topics, statistics and settings as text
+
+use crate::io::backup::{Snapshot, TopicChange};
+use crate::io::config::{LayeredConfig, KEYS};
+use crate::io::journal::Journal;
 use crate::io::storage::Storage;
-use crate::topics::review_topics::{NextReviewGap, ReviewTopic};
-use clap::{Args, Parser, Subcommand, ValueEnum};
+use crate::topics::clock::Clock;
+use crate::topics::merge::MergeConflict;
+use crate::topics::review_topics::{NextReviewGap, ReviewTopic, TopicState};
+use crate::topics::scheduling::ScheduleSettings;
+use chrono::{Datelike, Days, NaiveDate};
+use clap::ValueEnum;
 use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::io;
 use std::ops::Add;
-use chrono::Local;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-pub enum CliState {
-    /// View today's review topics
-    Today,
-    /// View all the review topics
+pub enum TopicStateFilter {
+    /// Topics that can become due
+    Active,
+    /// Topics that are never due until made active again
+    Suspended,
+    /// Topics hidden for the rest of the day
+    Buried,
+    /// Mastered topics
+    Archived,
+    /// Topics in any state
     All,
 }
 
-#[derive(Subcommand)]
-pub enum Commands {
-    View { view: CliState },
-    Edit(EditArgs),
-    Add { topic_name: String },
-    Remove { topic_name: String },
-    Review { topic_name: String },
-}
-
-#[derive(Args)]
-pub struct EditArgs {
-    pub topic_name: String,
-    pub new_topic_name: String,
+impl TopicStateFilter {
+    fn matches(&self, state: &TopicState) -> bool {
+        matches!(
+            (self, state),
+            (TopicStateFilter::All, _)
+                | (TopicStateFilter::Active, TopicState::Active)
+                | (TopicStateFilter::Suspended, TopicState::Suspended)
+                | (TopicStateFilter::Buried, TopicState::Buried(_))
+                | (TopicStateFilter::Archived, TopicState::Archived)
+        )
+    }
 }
 
-#[derive(Parser)]
-#[command(about, long_about = None)]
-pub struct Cli {
-    #[command(subcommand)]
-    pub command: Commands,
-}
+/// Show the topics due today, or that the reviews are paused
+pub fn display_today(
+    storage: &Storage,
+    style: TableStyle,
+    clock: &dyn Clock,
+    mut writer: impl io::Write,
+) {
+    if let Some(pause) = storage.get_pause() {
+        writeln!(
+            writer,
+            "\nReviews are paused and resume on {}. Run `revue resume` to resume them early.",
+            pause.until
+        )
+        .expect("Console<Today> pause banner display failed");
+    }
 
-pub fn display_today(storage: &Storage, mut writer: impl io::Write) {
     writeln!(writer, "\nToday's Review Topics:").expect("Console<Today> header display failed");
 
-    let review_topic_list: Vec<ReviewTopic> = storage.get_review_topic_list();
-
-    let today_list: Vec<ReviewTopic> = get_today_list(&review_topic_list);
+    let today_list: Vec<ReviewTopic> = storage.get_today_list(clock);
 
     let review_topic_not_found: bool = today_list.is_empty();
 
@@ -49,7 +69,7 @@ pub fn display_today(storage: &Storage, mut writer: impl io::Write) {
         writeln!(writer, "No review topics for today")
             .expect("Console<Today> no topic display failed")
     } else {
-        display_table_today(&today_list, writer);
+        display_table_today(&today_list, style, clock, writer);
     }
 
     // review_topic_list.iter().for_each(|topic| {
@@ -62,33 +82,154 @@ pub fn display_today(storage: &Storage, mut writer: impl io::Write) {
     // });
 }
 
-fn get_today_list(review_list: &[ReviewTopic]) -> Vec<ReviewTopic> {
-    let mut today_list: Vec<ReviewTopic> = Vec::new();
-
-    review_list.iter().for_each(|review_topic| {
-        if review_topic.is_time_to_review() {
-            today_list.push(review_topic.clone());
-        }
-    });
-
-    debug!("Today list: {:#?}", today_list);
-    today_list
-}
-
-pub fn display_all(storage: &Storage, mut writer: impl io::Write) {
+/// Show every topic in a table, sorted by due day
+///
+/// Archived topics are hidden unless `state` asks for them.
+pub fn display_all(
+    storage: &Storage,
+    state: Option<TopicStateFilter>,
+    style: TableStyle,
+    clock: &dyn Clock,
+    mut writer: impl io::Write,
+) {
     writeln!(writer, "\nAll Review Topics:").expect("Console<All> header display failed");
-    let list = storage.get_review_topic_list();
+    let mut list: Vec<ReviewTopic> = storage
+        .get_review_topic_list()
+        .into_iter()
+        .filter(|topic| match state {
+            None => topic.current_state(clock) != TopicState::Archived,
+            Some(state) => state.matches(&topic.current_state(clock)),
+        })
+        .collect();
+    list.sort_by(|topic, other| topic.cmp_due(other, clock));
 
-    display_table_all(&list, writer);
+    display_table_all(&list, style, clock, writer);
 
     // list.iter().for_each(|topic| {
     //     writeln!(writer, "{}", topic.topic_name).expect("Console<All> topic display failed")
     // });
 }
 
+/// List snapshots of the storage file, one per line
+pub fn display_snapshots(snapshots: &[Snapshot], mut writer: impl io::Write) {
+    if snapshots.is_empty() {
+        writeln!(writer, "No snapshots found.").expect("Console<Backup> display failed");
+        return;
+    }
+    for snapshot in snapshots {
+        writeln!(writer, "{}  {}", snapshot.day, snapshot.path.display())
+            .expect("Console<Backup> display failed");
+    }
+}
+
+/// List the profiles, marking the one in use with `*`
+pub fn display_profiles(profiles: &[String], in_use: &str, mut writer: impl io::Write) {
+    for profile in profiles {
+        let marker = match profile == in_use {
+            true => "*",
+            false => " ",
+        };
+        writeln!(writer, "{marker} {profile}").expect("Console<Profile> display failed");
+    }
+}
+
+/// List differences between two sets of topics, one per line
+pub fn display_topic_changes(topic_changes: &[TopicChange], mut writer: impl io::Write) {
+    if topic_changes.is_empty() {
+        writeln!(writer, "No differences.").expect("Console<Backup> display failed");
+        return;
+    }
+    for topic_change in topic_changes {
+        writeln!(writer, "{topic_change}").expect("Console<Backup> display failed");
+    }
+}
+
+/// List what a merge could not settle, one per line
+pub fn display_merge_conflicts(conflicts: &[MergeConflict], mut writer: impl io::Write) {
+    for conflict in conflicts {
+        writeln!(writer, "! {conflict}").expect("Console<Merge> display failed");
+    }
+}
+
+/// List the recent operations, numbered so that `undo <n>` reverts the first `n` of them
+pub fn display_log(journal: &Journal, limit: usize, mut writer: impl io::Write) {
+    let mut operations = journal.recent().take(limit).peekable();
+    if operations.peek().is_none() {
+        writeln!(writer, "No operations recorded.").expect("Console<Log> display failed");
+        return;
+    }
+    for (number, operation) in operations.enumerate() {
+        writeln!(writer, "{}. {operation}", number + 1).expect("Console<Log> display failed");
+    }
+}
+
+/// List every setting with its value and where the value comes from
+pub fn display_config(config: &LayeredConfig, mut writer: impl io::Write) {
+    if let Some(file_path) = config.file_path() {
+        writeln!(writer, "Config file: {}\n", file_path.display())
+            .expect("Console<Config> display failed");
+    }
+    for key in KEYS {
+        match config.get(key.name) {
+            Ok(Some((value, source))) => {
+                writeln!(writer, "{} = {value} ({source})", key.name)
+            }
+            _ => writeln!(writer, "{} is not set", key.name),
+        }
+        .expect("Console<Config> display failed");
+        writeln!(writer, "    {}", key.description).expect("Console<Config> display failed");
+    }
+}
+
+/// Show the daily limits and the review order
+pub fn display_schedule_settings(settings: &ScheduleSettings, mut writer: impl io::Write) {
+    let limit_string = |limit: Option<usize>| match limit {
+        None => "No limit".to_string(),
+        Some(limit) => limit.to_string(),
+    };
+    writeln!(
+        writer,
+        "Max reviews per day: {}\nMax new per day: {}\nReview order: {}\nScheduler: {}",
+        limit_string(settings.max_reviews_per_day),
+        limit_string(settings.max_new_per_day),
+        settings.review_order,
+        settings.scheduler,
+    )
+    .expect("Console<Limits> settings display failed");
+}
+
 use tabled::settings::Style;
 use tabled::{Table, Tabled};
 
+/// How the tables are drawn
+#[derive(Default, Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TableStyle {
+    #[default]
+    Sharp,
+    Ascii,
+    Modern,
+    Rounded,
+    Psql,
+    Markdown,
+    Blank,
+}
+
+impl TableStyle {
+    fn render(&self, table: &mut Table) -> String {
+        match self {
+            TableStyle::Sharp => table.with(Style::sharp()),
+            TableStyle::Ascii => table.with(Style::ascii()),
+            TableStyle::Modern => table.with(Style::modern()),
+            TableStyle::Rounded => table.with(Style::rounded()),
+            TableStyle::Psql => table.with(Style::psql()),
+            TableStyle::Markdown => table.with(Style::markdown()),
+            TableStyle::Blank => table.with(Style::blank()),
+        }
+        .to_string()
+    }
+}
+
 #[derive(Tabled, Debug)]
 struct TableTopicToday {
     name: String,
@@ -97,9 +238,10 @@ struct TableTopicToday {
 }
 
 impl TableTopicToday {
-    fn new(topic: &ReviewTopic) -> TableTopicToday {
-        let days_since_review = Local::now()
-            .signed_duration_since(topic.last_reviewed)
+    fn new(topic: &ReviewTopic, clock: &dyn Clock) -> TableTopicToday {
+        let days_since_review = clock
+            .today()
+            .signed_duration_since(clock.day_of(topic.last_reviewed))
             .num_days();
         TableTopicToday {
             name: topic.topic_name.clone(),
@@ -109,17 +251,22 @@ impl TableTopicToday {
     }
 }
 
-fn display_table_today(list: &[ReviewTopic], mut writer: impl io::Write) {
-    let table_list: Vec<TableTopicToday> = convert_topic_to_table_today(list);
-    let table_string = Table::new(table_list).with(Style::sharp()).to_string();
+fn display_table_today(
+    list: &[ReviewTopic],
+    style: TableStyle,
+    clock: &dyn Clock,
+    mut writer: impl io::Write,
+) {
+    let table_list: Vec<TableTopicToday> = convert_topic_to_table_today(list, clock);
+    let table_string = style.render(&mut Table::new(table_list));
 
     writeln!(writer, "{}", table_string).expect("Writing the table failed");
 }
 
-fn convert_topic_to_table_today(list: &[ReviewTopic]) -> Vec<TableTopicToday> {
+fn convert_topic_to_table_today(list: &[ReviewTopic], clock: &dyn Clock) -> Vec<TableTopicToday> {
     let mut table_list: Vec<TableTopicToday> = Vec::new();
     list.iter().for_each(|topic| {
-        table_list.push(TableTopicToday::new(topic));
+        table_list.push(TableTopicToday::new(topic, clock));
     });
     debug!("Table list: {:#?}", table_list);
     table_list
@@ -130,14 +277,18 @@ struct TableTopicAll {
     name: String,
     review_in_days: String,
     review_gap: NextReviewGap,
+    state: TopicState,
 }
 
 impl TableTopicAll {
-    fn new(topic: &ReviewTopic) -> TableTopicAll {
+    fn new(topic: &ReviewTopic, clock: &dyn Clock) -> TableTopicAll {
         let mut review_in_days: String = "Today".to_string();
+        let state = topic.current_state(clock);
 
-        if !topic.is_time_to_review() {
-            let num_of_days = topic.days_until_review();
+        if state != TopicState::Active {
+            review_in_days = "-".to_string();
+        } else if !topic.is_time_to_review(clock) {
+            let num_of_days = topic.days_until_review(clock);
             let mut day_string = " Days";
             if num_of_days == 1 {
                 day_string = " Day";
@@ -149,25 +300,274 @@ impl TableTopicAll {
             name: topic.topic_name.clone(),
             review_in_days,
             review_gap: topic.next_review_gap.clone(),
+            state,
         }
     }
 }
 
-pub fn display_table_all(list: &[ReviewTopic], mut writer: impl io::Write) {
-    let table_list: Vec<TableTopicAll> = convert_topic_to_table_all(list);
-    let table_string = Table::new(table_list).with(Style::sharp()).to_string();
+/// Show the given topics in a table with their due days, gaps and states
+pub fn display_table_all(
+    list: &[ReviewTopic],
+    style: TableStyle,
+    clock: &dyn Clock,
+    mut writer: impl io::Write,
+) {
+    let table_list: Vec<TableTopicAll> = convert_topic_to_table_all(list, clock);
+    let table_string = style.render(&mut Table::new(table_list));
 
     writeln!(writer, "{}", table_string).expect("Writing the table failed");
 }
 
-fn convert_topic_to_table_all(list: &[ReviewTopic]) -> Vec<TableTopicAll> {
+fn convert_topic_to_table_all(list: &[ReviewTopic], clock: &dyn Clock) -> Vec<TableTopicAll> {
     let mut table_list: Vec<TableTopicAll> = Vec::new();
     list.iter().for_each(|topic| {
-        table_list.push(TableTopicAll::new(topic));
+        table_list.push(TableTopicAll::new(topic, clock));
     });
     debug!("Table list: {:#?}", table_list);
     table_list
 }
 
+const HEATMAP_WEEKS: u64 = 53;
+const HEATMAP_LEVEL_COLORS: [u8; 5] = [237, 22, 28, 34, 40];
+const HEATMAP_LEVEL_CHARS: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+/// Show a year of daily reviews as a heatmap, with the current and longest streaks
+///
+/// `colored` uses ANSI colors for the cells instead of shading characters.
+pub fn display_heatmap(
+    storage: &Storage,
+    clock: &dyn Clock,
+    mut writer: impl io::Write,
+    colored: bool,
+) {
+    writeln!(writer, "\nReview Heatmap:").expect("Console<Heatmap> header display failed");
+
+    let review_topic_list: Vec<ReviewTopic> = storage.get_review_topic_list();
+    let review_counts = get_review_counts(&review_topic_list, clock);
+    let today: NaiveDate = clock.today();
+
+    display_heatmap_grid(&review_counts, today, &mut writer, colored);
+
+    writeln!(
+        writer,
+        "\nCurrent streak: {}\nLongest streak: {}",
+        day_count_string(get_current_streak(&review_counts, today)),
+        day_count_string(get_longest_streak(&review_counts)),
+    )
+    .expect("Console<Heatmap> streak display failed");
+}
+
+fn get_review_counts(review_list: &[ReviewTopic], clock: &dyn Clock) -> BTreeMap<NaiveDate, usize> {
+    let mut review_counts: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+
+    review_list.iter().for_each(|review_topic| {
+        review_topic.review_history.iter().for_each(|reviewed| {
+            *review_counts.entry(clock.day_of(*reviewed)).or_default() += 1;
+        });
+    });
+
+    debug!("Review counts: {:#?}", review_counts);
+    review_counts
+}
+
+fn get_current_streak(review_counts: &BTreeMap<NaiveDate, usize>, today: NaiveDate) -> usize {
+    // A streak is still alive if today has not been reviewed yet but yesterday was
+    let mut day = today;
+    if !review_counts.contains_key(&day) {
+        match day.pred_opt() {
+            None => return 0,
+            Some(yesterday) => day = yesterday,
+        }
+    }
+
+    let mut streak = 0;
+    while review_counts.contains_key(&day) {
+        streak += 1;
+        match day.pred_opt() {
+            None => break,
+            Some(previous_day) => day = previous_day,
+        }
+    }
+    streak
+}
+
+fn get_longest_streak(review_counts: &BTreeMap<NaiveDate, usize>) -> usize {
+    let mut longest_streak = 0;
+    let mut streak = 0;
+    let mut previous_day: Option<NaiveDate> = None;
+
+    review_counts.keys().for_each(|day| {
+        match previous_day.and_then(|previous_day| previous_day.succ_opt()) {
+            Some(next_day) if next_day == *day => streak += 1,
+            _ => streak = 1,
+        }
+        longest_streak = longest_streak.max(streak);
+        previous_day = Some(*day);
+    });
+    longest_streak
+}
+
+fn day_count_string(num_of_days: usize) -> String {
+    match num_of_days {
+        1 => "1 Day".to_string(),
+        _ => num_of_days.to_string().add(" Days"),
+    }
+}
+
+fn display_heatmap_grid(
+    review_counts: &BTreeMap<NaiveDate, usize>,
+    today: NaiveDate,
+    mut writer: impl io::Write,
+    colored: bool,
+) {
+    // Columns are weeks starting on Monday, the last column being the current week
+    let days_since_monday = u64::from(today.weekday().num_days_from_monday());
+    let first_day: NaiveDate = today
+        .checked_sub_days(Days::new(days_since_monday + (HEATMAP_WEEKS - 1) * 7))
+        .expect("Failed to get the first heatmap day");
+    let max_count: usize = review_counts
+        .range(first_day..=today)
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(0);
+
+    // Month labels start above the first week of each month, as long as they do not overlap
+    let mut month_header = String::from("    ");
+    let mut previous_month: Option<u32> = None;
+    for week in 0..HEATMAP_WEEKS {
+        let week_start = first_day + Days::new(week * 7);
+        let column = 4 + (week * 2) as usize;
+        if previous_month != Some(week_start.month()) && month_header.chars().count() <= column {
+            let padding = column - month_header.chars().count();
+            month_header.push_str(&" ".repeat(padding));
+            month_header.push_str(&week_start.format("%b ").to_string());
+        }
+        previous_month = Some(week_start.month());
+    }
+    writeln!(writer, "{}", month_header.trim_end()).expect("Console<Heatmap> month display failed");
+
+    for weekday in 0..7 {
+        let mut row = match weekday {
+            0 => String::from("Mon "),
+            2 => String::from("Wed "),
+            4 => String::from("Fri "),
+            _ => String::from("    "),
+        };
+        for week in 0..HEATMAP_WEEKS {
+            let day = first_day + Days::new(week * 7 + weekday);
+            if day > today {
+                break;
+            }
+            let count = review_counts.get(&day).copied().unwrap_or(0);
+            row.push_str(&heatmap_cell(heatmap_level(count, max_count), colored));
+            row.push(' ');
+        }
+        writeln!(writer, "{}", row.trim_end()).expect("Console<Heatmap> row display failed");
+    }
+
+    let legend: String = (0..HEATMAP_LEVEL_CHARS.len())
+        .map(|level| heatmap_cell(level, colored))
+        .collect::<Vec<String>>()
+        .join(" ");
+    writeln!(writer, "    Less {} More", legend).expect("Console<Heatmap> legend display failed");
+}
+
+fn heatmap_level(count: usize, max_count: usize) -> usize {
+    if count == 0 || max_count == 0 {
+        return 0;
+    }
+    let top_level = HEATMAP_LEVEL_CHARS.len() - 1;
+    (count * top_level).div_ceil(max_count).clamp(1, top_level)
+}
+
+fn heatmap_cell(level: usize, colored: bool) -> String {
+    if colored {
+        format!("\x1b[38;5;{}m■\x1b[0m", HEATMAP_LEVEL_COLORS[level])
+    } else {
+        HEATMAP_LEVEL_CHARS[level].to_string()
+    }
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use crate::io::console::{
+        display_heatmap_grid, get_current_streak, get_longest_streak, get_review_counts,
+        heatmap_level,
+    };
+    use crate::topics::clock::{Clock, SystemClock};
+    use crate::topics::review_topics::ReviewTopic;
+    use chrono::{Days, NaiveDate};
+    use std::collections::BTreeMap;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn counts_for(days: &[NaiveDate]) -> BTreeMap<NaiveDate, usize> {
+        days.iter().map(|day| (*day, 1)).collect()
+    }
+
+    #[test]
+    fn test_review_counts() {
+        let clock = SystemClock::default();
+        let mut topic1 = ReviewTopic::new("topic1".to_owned(), &clock);
+        topic1.review(&clock);
+        topic1.review(&clock);
+        let mut topic2 = ReviewTopic::new("topic2".to_owned(), &clock);
+        topic2.review(&clock);
+
+        let review_counts = get_review_counts(&[topic1.clone(), topic2], &clock);
+        assert_eq!(1, review_counts.len());
+        assert_eq!(
+            Some(&3),
+            review_counts.get(&clock.day_of(topic1.last_reviewed))
+        );
+    }
+
+    #[test]
+    fn test_streaks() {
+        let today = date(2024, 3, 10);
+        let review_counts = counts_for(&[
+            date(2024, 3, 1),
+            date(2024, 3, 2),
+            date(2024, 3, 3),
+            date(2024, 3, 4),
+            date(2024, 3, 8),
+            date(2024, 3, 9),
+        ]);
+
+        // Today has not been reviewed yet, so yesterday's streak is still counted
+        assert_eq!(2, get_current_streak(&review_counts, today));
+        assert_eq!(4, get_longest_streak(&review_counts));
+
+        let today = today + Days::new(1);
+        assert_eq!(0, get_current_streak(&review_counts, today));
+        assert_eq!(0, get_longest_streak(&BTreeMap::new()));
+    }
+
+    #[test]
+    fn test_heatmap_levels() {
+        assert_eq!(0, heatmap_level(0, 0));
+        assert_eq!(0, heatmap_level(0, 8));
+        assert_eq!(1, heatmap_level(1, 8));
+        assert_eq!(2, heatmap_level(3, 8));
+        assert_eq!(4, heatmap_level(8, 8));
+    }
+
+    #[test]
+    fn test_monochrome_heatmap() {
+        let today = date(2024, 3, 13);
+        let review_counts = counts_for(&[today]);
+        let mut output: Vec<u8> = Vec::new();
+
+        display_heatmap_grid(&review_counts, today, &mut output, false);
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(!output.contains('\x1b'));
+        let wednesday_row = output.lines().find(|line| line.starts_with("Wed")).unwrap();
+        assert!(wednesday_row.ends_with('█'));
+        // Thursday of the current week is still in the future
+        let friday_row = output.lines().find(|line| line.starts_with("Fri")).unwrap();
+        assert_eq!(52, friday_row.matches('·').count());
+    }
+}
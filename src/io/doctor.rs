@@ -0,0 +1,337 @@
+//! Finding and repairing problems in a storage file
+
+use crate::io::backup::{keep_copy, BackupError};
+use crate::io::encryption::{is_encrypted_file, write_file, Passphrase};
+use crate::io::storage::StorageDocument;
+use crate::topics::calendar::Calendar;
+use crate::topics::clock::Clock;
+use crate::topics::review_topics::{NextReviewGap, ReviewTopic};
+use chrono::DateTime;
+use log::info;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::PathBuf;
+
+/// Something wrong with a storage file, found by [`diagnose`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    InvalidJson(String),
+    /// The file is valid JSON, but not a storage revue can load even after the other repairs
+    InvalidStorage(String),
+    UnknownGap {
+        topic_name: String,
+        gap: String,
+    },
+    /// A topic that cannot be read. Repairing removes it.
+    InvalidTopic {
+        index: usize,
+        reason: String,
+    },
+    DuplicateName(String),
+    FutureReview {
+        topic_name: String,
+        last_reviewed: String,
+    },
+    /// A review in the history of a topic that has not happened yet. Repairing removes it.
+    FutureHistory {
+        topic_name: String,
+        reviewed: String,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::InvalidJson(reason) => write!(f, "The file is not valid JSON: {reason}"),
+            Problem::InvalidStorage(reason) => {
+                write!(f, "The file is not a revue storage: {reason}")
+            }
+            Problem::UnknownGap { topic_name, gap } => {
+                write!(f, "'{topic_name}' has an unknown review gap '{gap}'")
+            }
+            Problem::InvalidTopic { index, reason } => {
+                write!(f, "Topic number {} cannot be read: {reason}", index + 1)
+            }
+            Problem::DuplicateName(topic_name) => {
+                write!(f, "'{topic_name}' is in the storage more than once")
+            }
+            Problem::FutureReview {
+                topic_name,
+                last_reviewed,
+            } => write!(
+                f,
+                "'{topic_name}' was last reviewed in the future, at {last_reviewed}"
+            ),
+            Problem::FutureHistory {
+                topic_name,
+                reviewed,
+            } => write!(
+                f,
+                "'{topic_name}' has a review in the future in its history, at {reviewed}"
+            ),
+        }
+    }
+}
+
+impl Problem {
+    pub fn is_fixable(&self) -> bool {
+        !matches!(self, Problem::InvalidJson(_) | Problem::InvalidStorage(_))
+    }
+}
+
+/// The problems found in a storage file, and the file with the fixable ones repaired
+#[derive(Debug)]
+pub struct Diagnosis {
+    pub problems: Vec<Problem>,
+    /// `None` when the file cannot be repaired
+    pub repaired: Option<StorageDocument>,
+}
+
+/// The calendar kept in a storage file, or the default one when it cannot be read
+pub fn stored_calendar(serialized: &str) -> Calendar {
+    serde_json::from_str::<Value>(serialized)
+        .ok()
+        .and_then(|mut document| document.get_mut("calendar").map(Value::take))
+        .and_then(|calendar| serde_json::from_value(calendar).ok())
+        .unwrap_or_default()
+}
+
+/// Check a storage file without giving up at the first problem, the way loading it does
+pub fn diagnose(serialized: &str, clock: &dyn Clock) -> Diagnosis {
+    let mut document: Value = match serde_json::from_str(serialized) {
+        Ok(document) => document,
+        Err(error) => {
+            return Diagnosis {
+                problems: vec![Problem::InvalidJson(error.to_string())],
+                repaired: None,
+            }
+        }
+    };
+
+    let mut problems = Vec::new();
+    if let Some(topics) = document
+        .get_mut("review_topic_list")
+        .and_then(Value::as_array_mut)
+    {
+        check_topics(topics, clock, &mut problems);
+    }
+
+    match StorageDocument::from_value(document) {
+        Ok(repaired) => Diagnosis {
+            problems,
+            repaired: Some(repaired),
+        },
+        Err(error) => {
+            problems.push(Problem::InvalidStorage(error.to_string()));
+            Diagnosis {
+                problems,
+                repaired: None,
+            }
+        }
+    }
+}
+
+fn check_topics(topics: &mut Vec<Value>, clock: &dyn Clock, problems: &mut Vec<Problem>) {
+    let mut names: HashSet<String> = HashSet::new();
+    let mut invalid_topics: Vec<usize> = Vec::new();
+
+    for (index, topic) in topics.iter_mut().enumerate() {
+        let topic_name = match topic.get("topic_name").and_then(Value::as_str) {
+            None => {
+                problems.push(Problem::InvalidTopic {
+                    index,
+                    reason: "the topic has no name".to_string(),
+                });
+                invalid_topics.push(index);
+                continue;
+            }
+            Some(topic_name) => topic_name.to_string(),
+        };
+
+        if let Some(gap) = topic.get("next_review_gap") {
+            if serde_json::from_value::<NextReviewGap>(gap.clone()).is_err() {
+                problems.push(Problem::UnknownGap {
+                    topic_name: topic_name.clone(),
+                    gap: match gap.as_str() {
+                        Some(gap) => gap.to_string(),
+                        None => gap.to_string(),
+                    },
+                });
+                topic["next_review_gap"] = serde_json::to_value(NextReviewGap::default())
+                    .expect("A review gap is valid JSON");
+            }
+        }
+
+        let last_reviewed = topic
+            .get("last_reviewed")
+            .and_then(Value::as_str)
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .map(|last_reviewed| last_reviewed.to_utc());
+        if let Some(last_reviewed) = last_reviewed.filter(|time| *time > clock.now()) {
+            problems.push(Problem::FutureReview {
+                topic_name: topic_name.clone(),
+                last_reviewed: last_reviewed.to_rfc3339(),
+            });
+            topic["last_reviewed"] =
+                serde_json::to_value(clock.now()).expect("A point in time is valid JSON");
+        }
+
+        if let Some(history) = topic
+            .get_mut("review_history")
+            .and_then(Value::as_array_mut)
+        {
+            history.retain(|reviewed| {
+                let future = reviewed
+                    .as_str()
+                    .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                    .filter(|reviewed| reviewed.to_utc() > clock.now());
+                if let Some(future) = future {
+                    problems.push(Problem::FutureHistory {
+                        topic_name: topic_name.clone(),
+                        reviewed: future.to_utc().to_rfc3339(),
+                    });
+                }
+                future.is_none()
+            });
+        }
+
+        if !names.insert(topic_name.clone()) {
+            problems.push(Problem::DuplicateName(topic_name.clone()));
+            let new_name = unique_name(&topic_name, &names);
+            names.insert(new_name.clone());
+            topic["topic_name"] = Value::String(new_name);
+        }
+
+        if let Err(error) = serde_json::from_value::<ReviewTopic>(topic.clone()) {
+            problems.push(Problem::InvalidTopic {
+                index,
+                reason: error.to_string(),
+            });
+            invalid_topics.push(index);
+        }
+    }
+
+    invalid_topics.iter().rev().for_each(|index| {
+        topics.remove(*index);
+    });
+}
+
+/// The first of `name (2)`, `name (3)`, ... not used yet
+fn unique_name(topic_name: &str, names: &HashSet<String>) -> String {
+    (2..)
+        .map(|number| format!("{topic_name} ({number})"))
+        .find(|candidate| !names.contains(candidate))
+        .expect("There is always an unused name")
+}
+
+/// Write the repaired storage file, after keeping a copy of the damaged one
+///
+/// An encrypted storage file stays encrypted with the passphrase.
+///
+/// returns: Where the damaged file was copied to
+pub fn repair(
+    storage_file_path: &str,
+    repaired: &StorageDocument,
+    passphrase: Option<&Passphrase>,
+) -> Result<Option<PathBuf>, BackupError> {
+    let copy = keep_copy(storage_file_path, "before-repair", passphrase)?;
+    let serialized =
+        serde_json::to_string_pretty(repaired).expect("The repaired storage is valid JSON");
+    info!("Writing the repaired storage");
+    let passphrase = passphrase.filter(|_| is_encrypted_file(storage_file_path));
+    write_file(storage_file_path, &serialized, passphrase)?;
+    Ok(copy)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::doctor::{diagnose, stored_calendar, Problem};
+    use crate::topics::calendar::Calendar;
+    use crate::topics::clock::{Clock, FixedClock};
+
+    fn topic(topic_name: &str, last_reviewed: &str, gap: &str) -> String {
+        format!(
+            r#"{{"topic_name": "{topic_name}", "last_reviewed": "{last_reviewed}", "next_review_gap": "{gap}"}}"#
+        )
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let diagnosis = diagnose(
+            "{\"review_topic_list\": [",
+            &FixedClock::utc("2024-05-01T12:00:00Z"),
+        );
+        assert!(matches!(diagnosis.problems[..], [Problem::InvalidJson(_)]));
+        assert!(diagnosis.repaired.is_none());
+    }
+
+    #[test]
+    fn test_healthy_storage() {
+        let serialized = format!(
+            r#"{{"review_topic_list": [{}]}}"#,
+            topic("a", "2024-04-30T12:00:00Z", "Week")
+        );
+        let diagnosis = diagnose(&serialized, &FixedClock::utc("2024-05-01T12:00:00Z"));
+        assert!(diagnosis.problems.is_empty());
+    }
+
+    #[test]
+    fn test_future_history() {
+        let serialized = r#"{"review_topic_list": [{"topic_name": "a", "last_reviewed": "2024-04-30T12:00:00Z", "next_review_gap": "Week", "review_history": ["2024-04-20T12:00:00Z", "2024-06-01T12:00:00Z"]}]}"#;
+        let diagnosis = diagnose(serialized, &FixedClock::utc("2024-05-01T12:00:00Z"));
+
+        assert_eq!(
+            vec![Problem::FutureHistory {
+                topic_name: "a".to_string(),
+                reviewed: "2024-06-01T12:00:00+00:00".to_string(),
+            }],
+            diagnosis.problems
+        );
+        let repaired = diagnosis.repaired.unwrap();
+        assert_eq!(1, repaired.review_topics()[0].review_history.len());
+    }
+
+    #[test]
+    fn test_stored_calendar() {
+        let serialized = r#"{"review_topic_list": [], "calendar": {"timezone": "Europe/Berlin", "day_rollover_hour": 4}}"#;
+        let calendar = stored_calendar(serialized);
+        assert_eq!(Some(chrono_tz::Tz::Europe__Berlin), calendar.timezone);
+        assert_eq!(4, calendar.day_rollover_hour);
+        assert_eq!(Calendar::default(), stored_calendar("{"));
+    }
+
+    #[test]
+    fn test_repairs() {
+        let serialized = format!(
+            r#"{{"review_topic_list": [{}, {}, {}, {}], "storage_file_path": "./elsewhere.json"}}"#,
+            topic("a", "2024-04-30T12:00:00Z", "Fortnight"),
+            topic("a", "2024-04-30T12:00:00Z", "Day"),
+            topic("b", "2025-01-01T12:00:00Z", "Day"),
+            r#"{"topic_name": "c"}"#
+        );
+        let diagnosis = diagnose(&serialized, &FixedClock::utc("2024-05-01T12:00:00Z"));
+
+        assert_eq!(4, diagnosis.problems.len());
+        assert!(diagnosis.problems.iter().all(Problem::is_fixable));
+        assert!(diagnosis
+            .problems
+            .contains(&Problem::DuplicateName("a".to_string())));
+
+        let repaired = diagnosis.repaired.unwrap();
+        let names: Vec<&str> = repaired
+            .review_topics()
+            .iter()
+            .map(|topic| topic.topic_name.as_str())
+            .collect();
+        assert_eq!(vec!["a", "a (2)", "b"], names);
+        assert!(repaired
+            .review_topics()
+            .iter()
+            .all(|topic| topic.last_reviewed <= FixedClock::utc("2024-05-01T12:00:00Z").now()));
+        // The path the storage file was written from is not stored anymore
+        let written = serde_json::to_value(&repaired).unwrap();
+        assert!(written.get("storage_file_path").is_none());
+    }
+}
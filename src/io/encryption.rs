@@ -0,0 +1,296 @@
+//! Encrypting the storage file with a passphrase
+//!
+//! An encrypted storage file is an [age](https://age-encryption.org) file, so it can also be
+//! decrypted with `age --decrypt`. Whether a file is encrypted is told from its contents, so once
+//! `revue encrypt` converted the storage, it stays encrypted until `revue decrypt`. Its snapshots
+//! and journal are encrypted with it, and its changes are not kept in the event log.
+//!
+//! The passphrase is read from `REVUE_PASSPHRASE`, then from the keyfile set as
+//! `encryption.keyfile`, and otherwise asked for when revue runs in a terminal. The
+//! [`crate::io::storage::Storage`] keeps it for its reads and writes, and the functions reading
+//! the storage file on their own take it as an argument.
+
+use age::secrecy::{ExposeSecret, SecretString};
+use age::DecryptError;
+use log::info;
+use std::fmt;
+use std::fmt::Formatter;
+use std::io::{stdin, IsTerminal, Read};
+use std::path::Path;
+use std::{env, fs, io};
+
+/// How every age file starts
+const AGE_HEADER: &[u8] = b"age-encryption.org/v1\n";
+
+/// The scrypt work factor, `N = 2^16`, which keeps each read and write of the storage well
+/// under a second
+const WORK_FACTOR: u8 = 16;
+
+/// How many times a wrong passphrase can be typed in
+const PROMPT_ATTEMPTS: u32 = 3;
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    /// There is no passphrase, and no terminal to ask for one
+    NoPassphrase,
+    EmptyPassphrase,
+    /// The passphrase and its confirmation differ
+    PassphrasesDiffer,
+    WrongPassphrase,
+    /// The file is not an encrypted file revue can read
+    Corrupted(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::NoPassphrase => write!(
+                f,
+                "No passphrase was given. Set REVUE_PASSPHRASE or encryption.keyfile, or run revue in a terminal to enter it."
+            ),
+            EncryptionError::EmptyPassphrase => write!(f, "The passphrase cannot be empty."),
+            EncryptionError::PassphrasesDiffer => write!(f, "The passphrases do not match."),
+            EncryptionError::WrongPassphrase => write!(f, "The passphrase is wrong."),
+            EncryptionError::Corrupted(reason) => {
+                write!(f, "The encrypted file could not be read: {reason}")
+            }
+            EncryptionError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+impl From<io::Error> for EncryptionError {
+    fn from(error: io::Error) -> Self {
+        EncryptionError::Io(error)
+    }
+}
+
+/// Lets the readers of the storage file report a wrong passphrase like any other read error
+impl From<EncryptionError> for io::Error {
+    fn from(error: EncryptionError) -> Self {
+        match error {
+            EncryptionError::Io(error) => error,
+            EncryptionError::Corrupted(_) => io::Error::new(io::ErrorKind::InvalidData, error),
+            error => io::Error::new(io::ErrorKind::PermissionDenied, error),
+        }
+    }
+}
+
+/// A passphrase, kept out of the logs and debug output
+#[derive(Clone)]
+pub struct Passphrase(SecretString);
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Passphrase(..)")
+    }
+}
+
+impl PartialEq for Passphrase {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Passphrase {
+    pub fn new(passphrase: String) -> Result<Self, EncryptionError> {
+        match passphrase.is_empty() {
+            true => Err(EncryptionError::EmptyPassphrase),
+            false => Ok(Passphrase(SecretString::from(passphrase))),
+        }
+    }
+
+    /// Read the passphrase from the first line of a keyfile
+    pub fn from_keyfile(keyfile: &Path) -> Result<Self, EncryptionError> {
+        let contents = fs::read_to_string(keyfile)?;
+        Passphrase::new(contents.lines().next().unwrap_or_default().to_string())
+    }
+}
+
+/// The passphrase from `REVUE_PASSPHRASE` or the keyfile, without asking for it
+fn given_passphrase(keyfile: Option<&str>) -> Result<Option<Passphrase>, EncryptionError> {
+    if let Some(passphrase) = env::var("REVUE_PASSPHRASE")
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty())
+    {
+        return Passphrase::new(passphrase).map(Some);
+    }
+    keyfile
+        .map(|keyfile| Passphrase::from_keyfile(Path::new(keyfile)))
+        .transpose()
+}
+
+fn prompt(message: &str) -> Result<Passphrase, EncryptionError> {
+    if !stdin().is_terminal() {
+        return Err(EncryptionError::NoPassphrase);
+    }
+    Passphrase::new(rpassword::prompt_password(message)?)
+}
+
+/// Find the passphrase that decrypts a file, asking for it again when the one typed in is wrong
+///
+/// `keyfile` is the `encryption.keyfile` setting. When `interactive` is false the passphrase is
+/// never asked for, so commands run by timers and shell prompts fail at once without one.
+pub fn unlock(
+    path: impl AsRef<Path>,
+    keyfile: Option<&str>,
+    interactive: bool,
+) -> Result<Passphrase, EncryptionError> {
+    let contents = fs::read(path)?;
+    if let Some(passphrase) = given_passphrase(keyfile)? {
+        decrypt(&contents, &passphrase)?;
+        return Ok(passphrase);
+    }
+    if !interactive {
+        return Err(EncryptionError::NoPassphrase);
+    }
+    for attempt in 1..=PROMPT_ATTEMPTS {
+        let passphrase = prompt("Passphrase for the storage: ")?;
+        match decrypt(&contents, &passphrase) {
+            Ok(_) => return Ok(passphrase),
+            Err(EncryptionError::WrongPassphrase) if attempt < PROMPT_ATTEMPTS => {
+                eprintln!("The passphrase is wrong, try again.");
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Err(EncryptionError::WrongPassphrase)
+}
+
+/// Find the passphrase to encrypt with, asking for it twice when prompting
+pub fn choose_passphrase(keyfile: Option<&str>) -> Result<Passphrase, EncryptionError> {
+    match given_passphrase(keyfile)? {
+        Some(passphrase) => Ok(passphrase),
+        None => {
+            let passphrase = prompt("New passphrase for the storage: ")?;
+            let confirmation = prompt("Repeat the passphrase: ")?;
+            match passphrase == confirmation {
+                true => Ok(passphrase),
+                false => Err(EncryptionError::PassphrasesDiffer),
+            }
+        }
+    }
+}
+
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(AGE_HEADER)
+}
+
+/// Whether the file is encrypted. A missing file is not.
+pub fn is_encrypted_file(path: impl AsRef<Path>) -> bool {
+    let mut header = [0; AGE_HEADER.len()];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| is_encrypted(&header))
+}
+
+pub fn encrypt(plaintext: &[u8], passphrase: &Passphrase) -> Vec<u8> {
+    let mut recipient = age::scrypt::Recipient::new(passphrase.0.clone());
+    recipient.set_work_factor(WORK_FACTOR);
+    age::encrypt(&recipient, plaintext).expect("Encrypting in memory does not fail")
+}
+
+pub fn decrypt(ciphertext: &[u8], passphrase: &Passphrase) -> Result<Vec<u8>, EncryptionError> {
+    let identity = age::scrypt::Identity::new(passphrase.0.clone());
+    age::decrypt(&identity, ciphertext).map_err(|error| match error {
+        DecryptError::DecryptionFailed
+        | DecryptError::KeyDecryptionFailed
+        | DecryptError::NoMatchingKeys => EncryptionError::WrongPassphrase,
+        DecryptError::Io(error) => EncryptionError::Io(error),
+        error => EncryptionError::Corrupted(error.to_string()),
+    })
+}
+
+/// The text of a file, decrypting it with the passphrase if it is encrypted
+pub fn decrypt_contents(
+    contents: Vec<u8>,
+    passphrase: Option<&Passphrase>,
+) -> Result<String, EncryptionError> {
+    let plaintext = match is_encrypted(&contents) {
+        true => {
+            info!("Decrypting the storage");
+            decrypt(&contents, passphrase.ok_or(EncryptionError::NoPassphrase)?)?
+        }
+        false => contents,
+    };
+    String::from_utf8(plaintext).map_err(|error| EncryptionError::Corrupted(error.to_string()))
+}
+
+/// Read a file that may be encrypted, like [`fs::read_to_string`] does for one that is not
+pub fn read_file(path: impl AsRef<Path>, passphrase: Option<&Passphrase>) -> io::Result<String> {
+    Ok(decrypt_contents(fs::read(path)?, passphrase)?)
+}
+
+/// Write a file, encrypted with the passphrase when there is one
+pub fn write_file(
+    path: impl AsRef<Path>,
+    contents: &str,
+    passphrase: Option<&Passphrase>,
+) -> io::Result<()> {
+    match passphrase {
+        Some(passphrase) => fs::write(path, encrypt(contents.as_bytes(), passphrase)),
+        None => fs::write(path, contents),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::encryption::{
+        decrypt, encrypt, is_encrypted_file, read_file, unlock, write_file, EncryptionError,
+        Passphrase,
+    };
+    use std::fs;
+
+    static ENCRYPTED_PATH: &str = "./data/test_encrypted.json";
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let passphrase = Passphrase::new("correct horse".to_string()).unwrap();
+        let encrypted = encrypt(b"{\"review_topic_list\":[]}", &passphrase);
+        assert!(!String::from_utf8_lossy(&encrypted).contains("review_topic_list"));
+        assert_eq!(
+            b"{\"review_topic_list\":[]}".to_vec(),
+            decrypt(&encrypted, &passphrase).unwrap()
+        );
+
+        let wrong = Passphrase::new("battery staple".to_string()).unwrap();
+        assert!(matches!(
+            decrypt(&encrypted, &wrong),
+            Err(EncryptionError::WrongPassphrase)
+        ));
+        assert!(matches!(
+            Passphrase::new(String::new()),
+            Err(EncryptionError::EmptyPassphrase)
+        ));
+    }
+
+    #[test]
+    fn test_read_and_write_files() {
+        fs::create_dir_all("./data").unwrap();
+        let passphrase = Passphrase::new("correct horse".to_string()).unwrap();
+        write_file(ENCRYPTED_PATH, "{}", Some(&passphrase)).unwrap();
+        assert!(is_encrypted_file(ENCRYPTED_PATH));
+        assert_eq!("{}", read_file(ENCRYPTED_PATH, Some(&passphrase)).unwrap());
+        assert!(read_file(ENCRYPTED_PATH, None).is_err());
+
+        // Timers and shell prompts never wait for a passphrase to be typed in
+        let keyfile = "./data/test_encrypted.key";
+        assert!(matches!(
+            unlock(ENCRYPTED_PATH, None, false),
+            Err(EncryptionError::NoPassphrase)
+        ));
+        fs::write(keyfile, "correct horse\n").unwrap();
+        assert_eq!(
+            passphrase,
+            unlock(ENCRYPTED_PATH, Some(keyfile), false).unwrap()
+        );
+
+        write_file(ENCRYPTED_PATH, "{}", None).unwrap();
+        assert!(!is_encrypted_file(ENCRYPTED_PATH));
+        assert_eq!("{}", read_file(ENCRYPTED_PATH, None).unwrap());
+        assert!(!is_encrypted_file("./data/missing.json"));
+    }
+}
@@ -0,0 +1,356 @@
+//! The storage as a snapshot and an append-only log of the topic changes made since
+//!
+//! Adding, reviewing, renaming and removing a topic append one line to the event log next to the
+//! storage file, instead of rewriting the whole storage. Loading replays the log on top of the
+//! storage file. Once the log holds [`COMPACT_AFTER_EVENTS`] events, or when a change cannot be
+//! told as events, the storage file is written in full again and the log is moved to the end of
+//! the history file, which keeps every event ever recorded.
+//!
+//! Every event is numbered, and the storage file records the number of the last event its topics
+//! include. Replaying skips the events up to it, so a log left behind after the storage file was
+//! written in full is not applied over the changes that were made without events.
+
+use crate::io::encryption::{is_encrypted_file, read_file, write_file, Passphrase};
+use crate::io::storage::StorageDocument;
+use crate::topics::review_topics::ReviewTopic;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// How many events the log holds before the storage file is written in full again
+pub const COMPACT_AFTER_EVENTS: usize = 100;
+
+/// A change to the topics
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    TopicAdded {
+        topic: ReviewTopic,
+    },
+    /// The topic as it is after the review, so replaying needs no scheduler
+    TopicReviewed {
+        topic: ReviewTopic,
+    },
+    TopicRenamed {
+        id: String,
+        from: String,
+        to: String,
+    },
+    TopicRemoved {
+        id: String,
+        topic_name: String,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::TopicAdded { topic } => write!(f, "Added '{}'", topic.topic_name),
+            Event::TopicReviewed { topic } => write!(f, "Reviewed '{}'", topic.topic_name),
+            Event::TopicRenamed { from, to, .. } => write!(f, "Renamed '{from}' to '{to}'"),
+            Event::TopicRemoved { topic_name, .. } => write!(f, "Removed '{topic_name}'"),
+        }
+    }
+}
+
+impl Event {
+    /// Make the change to the topics, the same way [`crate::Storage`] made it
+    ///
+    /// An event is not always undone by applying it twice, as the topic it carries may have been
+    /// changed since without an event. [`replay`] only applies the events a document does not
+    /// include yet.
+    pub fn apply(&self, topics: &mut Vec<ReviewTopic>) {
+        let position =
+            |topics: &[ReviewTopic], id: &str| topics.iter().position(|topic| topic.id == id);
+        match self {
+            Event::TopicAdded { topic } => match position(topics, &topic.id) {
+                Some(index) => topics[index] = topic.clone(),
+                None => topics.push(topic.clone()),
+            },
+            Event::TopicReviewed { topic } => match position(topics, &topic.id) {
+                Some(index) => topics[index] = topic.clone(),
+                None => warn!(
+                    "Ignoring the review of '{}', which is not stored",
+                    topic.topic_name
+                ),
+            },
+            // A renamed topic moves to the end of the list
+            Event::TopicRenamed { id, to, .. } => match position(topics, id) {
+                Some(index) if topics[index].topic_name == *to => {}
+                Some(index) => {
+                    let mut topic = topics.remove(index);
+                    topic.topic_name = to.clone();
+                    topics.push(topic);
+                }
+                None => warn!("Ignoring the rename of '{to}', which is not stored"),
+            },
+            Event::TopicRemoved { id, .. } => topics.retain(|topic| topic.id != *id),
+        }
+    }
+}
+
+/// An event and when it was recorded, as one line of the log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventRecord {
+    pub at: DateTime<Utc>,
+    /// Counts up from 1. Events recorded before they were numbered have 0 and are always replayed.
+    #[serde(default)]
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Debug)]
+pub enum EventError {
+    /// The storage file could not be read as a storage
+    InvalidStorage(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EventError::InvalidStorage(reason) => {
+                write!(f, "The storage file could not be read: {reason}")
+            }
+            EventError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for EventError {}
+
+impl From<io::Error> for EventError {
+    fn from(error: io::Error) -> Self {
+        EventError::Io(error)
+    }
+}
+
+/// The events not in the storage file yet
+pub fn log_path(storage_file_path: &str) -> PathBuf {
+    Path::new(storage_file_path).with_extension("events.jsonl")
+}
+
+/// Every event that was compacted into the storage file
+pub fn history_path(storage_file_path: &str) -> PathBuf {
+    Path::new(storage_file_path).with_extension("history.jsonl")
+}
+
+/// The events in the log, oldest first
+///
+/// A line that cannot be read, like one cut off by a crash while it was written, is skipped.
+pub fn read_log(storage_file_path: &str) -> io::Result<Vec<EventRecord>> {
+    let serialized = match fs::read_to_string(log_path(storage_file_path)) {
+        Ok(serialized) => serialized,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+    Ok(serialized
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(error) => {
+                warn!("Skipping an event that could not be read: {error}");
+                None
+            }
+        })
+        .collect())
+}
+
+pub fn append_to_log(storage_file_path: &str, records: &[EventRecord]) -> io::Result<()> {
+    append_records(&log_path(storage_file_path), records)
+}
+
+/// Move the events of the log to the end of the history, once the storage file includes them
+pub fn archive_log(storage_file_path: &str) -> io::Result<()> {
+    let log_path = log_path(storage_file_path);
+    if !log_path.exists() {
+        return Ok(());
+    }
+    append_records(
+        &history_path(storage_file_path),
+        &read_log(storage_file_path)?,
+    )?;
+    fs::remove_file(log_path)
+}
+
+fn append_records(path: &Path, records: &[EventRecord]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    let mut serialized = String::new();
+    // A line cut off by a crash stays on its own, so the records after it can still be read
+    if !ends_with_newline(&mut file)? {
+        serialized.push('\n');
+    }
+    for record in records {
+        serialized += &serde_json::to_string(record).expect("An event is valid JSON");
+        serialized.push('\n');
+    }
+    file.write_all(serialized.as_bytes())
+}
+
+/// Whether the file is empty or its last line is complete
+fn ends_with_newline(file: &mut File) -> io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+    let mut last_byte = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last_byte)?;
+    Ok(last_byte[0] == b'\n')
+}
+
+/// Apply the events the document does not include yet to its topics
+pub fn replay(document: &mut StorageDocument, records: &[EventRecord]) {
+    let compacted_through = document.compacted_through();
+    let mut topics = document.review_topics().to_vec();
+    records
+        .iter()
+        .filter(|record| record.sequence == 0 || record.sequence > compacted_through)
+        .for_each(|record| record.event.apply(&mut topics));
+    document.set_review_topics(topics);
+    let last_sequence = records.iter().map(|record| record.sequence).max();
+    document.set_compacted_through(last_sequence.unwrap_or_default().max(compacted_through));
+}
+
+/// Read the storage file and replay the log on top of it
+///
+/// `passphrase` decrypts the storage file if it is encrypted.
+pub fn load_document(
+    storage_file_path: &str,
+    passphrase: Option<&Passphrase>,
+) -> Result<StorageDocument, EventError> {
+    let serialized = read_file(storage_file_path, passphrase)?;
+    let mut document = StorageDocument::from_json(&serialized)
+        .map_err(|error| EventError::InvalidStorage(error.to_string()))?;
+    replay(&mut document, &read_log(storage_file_path)?);
+    Ok(document)
+}
+
+/// Write the storage file with the events of the log included, and archive the log
+///
+/// Tools that read the storage file itself, like git or a copy to another machine, see every
+/// change after this.
+///
+/// returns: Whether there were events to compact
+pub fn compact(
+    storage_file_path: &str,
+    passphrase: Option<&Passphrase>,
+) -> Result<bool, EventError> {
+    if read_log(storage_file_path)?.is_empty() {
+        return Ok(false);
+    }
+    info!("Compacting the event log into the storage file");
+    let document = load_document(storage_file_path, passphrase)?;
+    let serialized =
+        serde_json::to_string_pretty(&document).expect("The storage document is valid JSON");
+    // The storage file stays encrypted or in plaintext, as it was
+    let passphrase = passphrase.filter(|_| is_encrypted_file(storage_file_path));
+    write_file(storage_file_path, &serialized, passphrase)?;
+    archive_log(storage_file_path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::events::{
+        append_to_log, compact, history_path, load_document, log_path, read_log, Event, EventRecord,
+    };
+    use crate::io::storage::StorageDocument;
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::review_topics::ReviewTopic;
+    use serde_json::json;
+    use serial_test::serial;
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    static STORAGE_PATH: &str = "./data/events_test/storage.json";
+
+    fn record(sequence: u64, event: Event) -> EventRecord {
+        EventRecord {
+            at: FixedClock::utc("2024-05-10T12:00:00Z").now(),
+            sequence,
+            event,
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_replay_and_compact() {
+        let _ = fs::remove_dir_all("./data/events_test");
+        fs::create_dir_all("./data/events_test").unwrap();
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let rust = ReviewTopic::new("rust".to_string(), &clock.before_days(3));
+        let go = ReviewTopic::new("go".to_string(), &clock);
+        let document = StorageDocument::from_value(json!({ "review_topic_list": [rust] })).unwrap();
+        fs::write(STORAGE_PATH, serde_json::to_string(&document).unwrap()).unwrap();
+
+        let mut reviewed = rust.clone();
+        reviewed.review(&clock);
+        append_to_log(
+            STORAGE_PATH,
+            &[
+                record(1, Event::TopicAdded { topic: go.clone() }),
+                record(
+                    2,
+                    Event::TopicReviewed {
+                        topic: reviewed.clone(),
+                    },
+                ),
+                record(
+                    3,
+                    Event::TopicRenamed {
+                        id: rust.id.clone(),
+                        from: "rust".to_string(),
+                        to: "rust lang".to_string(),
+                    },
+                ),
+                record(
+                    4,
+                    Event::TopicRemoved {
+                        id: go.id.clone(),
+                        topic_name: "go".to_string(),
+                    },
+                ),
+            ],
+        )
+        .unwrap();
+        // A line cut off by a crash is skipped
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(log_path(STORAGE_PATH))
+            .unwrap();
+        write!(log, "{{\"at\":").unwrap();
+        assert_eq!(4, read_log(STORAGE_PATH).unwrap().len());
+        // The next event starts a line of its own instead of joining the cut off one
+        let zig = ReviewTopic::new("zig".to_string(), &clock);
+        append_to_log(
+            STORAGE_PATH,
+            &[record(5, Event::TopicAdded { topic: zig.clone() })],
+        )
+        .unwrap();
+        assert_eq!(5, read_log(STORAGE_PATH).unwrap().len());
+
+        let loaded = load_document(STORAGE_PATH, None).unwrap();
+        reviewed.topic_name = "rust lang".to_string();
+        assert_eq!(vec![reviewed, zig], loaded.review_topics());
+
+        assert!(compact(STORAGE_PATH, None).unwrap());
+        assert!(!log_path(STORAGE_PATH).exists());
+        assert_eq!(loaded, load_document(STORAGE_PATH, None).unwrap());
+        let history = fs::read_to_string(history_path(STORAGE_PATH)).unwrap();
+        assert_eq!(5, history.lines().count());
+        assert!(!compact(STORAGE_PATH, None).unwrap());
+    }
+}
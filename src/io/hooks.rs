@@ -0,0 +1,331 @@
+//! Running user scripts when topics change
+//!
+//! An executable in the hooks directory next to the storage file, named after an event such as
+//! `post-review`, runs after that event with the affected topic as JSON on its stdin. So does a
+//! shell command set in the config file, like `hooks.post_review`. Hooks run after the storage
+//! was written, so a failing hook is reported but never loses a change.
+
+use crate::io::config::HooksConfig;
+use crate::topics::review_topics::ReviewTopic;
+use chrono::NaiveDate;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fmt::Formatter;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::{fs, io};
+
+/// Something that happened to the topics that hooks can run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    PostAdd,
+    PostReview,
+    PostRemove,
+    PostRename,
+    /// The first time revue runs on a day. The hook gets the topics due that day.
+    DailyFirstRun,
+}
+
+impl HookEvent {
+    /// The name of the hook executable for the event
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::PostAdd => "post-add",
+            HookEvent::PostReview => "post-review",
+            HookEvent::PostRemove => "post-remove",
+            HookEvent::PostRename => "post-rename",
+            HookEvent::DailyFirstRun => "daily-first-run",
+        }
+    }
+}
+
+/// A change to a topic whose hook has not run yet, because the storage was not written yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingHook {
+    pub event: HookEvent,
+    pub topic: ReviewTopic,
+    /// The name before a rename
+    pub previous_name: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum HookError {
+    /// The hook ran but exited with an error
+    Failed {
+        hook: String,
+        status: String,
+    },
+    Io {
+        hook: String,
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HookError::Failed { hook, status } => {
+                write!(f, "The hook {hook} failed with {status}.")
+            }
+            HookError::Io { hook, error } => {
+                write!(f, "The hook {hook} could not be run: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HookError {}
+
+/// The directory the hooks of a storage file are kept in
+pub fn hooks_dir(storage_file_path: &str) -> PathBuf {
+    Path::new(storage_file_path)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("hooks")
+}
+
+/// The hook executable and the configured hook command for an event, where there are any,
+/// each with a name to report it by
+fn find_hooks(
+    hooks_dir: &Path,
+    hook_commands: &HooksConfig,
+    event: HookEvent,
+) -> Vec<(String, Command)> {
+    let mut hooks = Vec::new();
+    let executable = hooks_dir.join(event.name());
+    if executable.is_file() {
+        hooks.push((executable.display().to_string(), Command::new(executable)));
+    }
+    if let Some(hook_command) = hook_commands.command(event) {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(hook_command);
+        hooks.push((format!("'{hook_command}'"), command));
+    }
+    hooks
+}
+
+/// Run the hooks for an event, if there are any, with the payload as JSON on their stdin
+///
+/// returns: Whether a hook was run
+pub fn run_hook(
+    hooks_dir: &Path,
+    hook_commands: &HooksConfig,
+    event: HookEvent,
+    payload: &Value,
+    environment: &[(&str, &str)],
+) -> Result<bool, HookError> {
+    let hooks = find_hooks(hooks_dir, hook_commands, event);
+    let ran = !hooks.is_empty();
+    for (hook, command) in hooks {
+        run_command(hook, command, event, payload, environment)?;
+    }
+    Ok(ran)
+}
+
+fn run_command(
+    hook: String,
+    mut command: Command,
+    event: HookEvent,
+    payload: &Value,
+    environment: &[(&str, &str)],
+) -> Result<(), HookError> {
+    info!("Running the {} hook {hook}", event.name());
+    let io_error = |error| HookError::Io {
+        hook: hook.clone(),
+        error,
+    };
+    let mut child = command
+        .env("REVUE_EVENT", event.name())
+        .envs(environment.iter().copied())
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(io_error)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that does not read its stdin closes the pipe early, which is not a failure
+        let serialized = serde_json::to_vec(payload).expect("The hook payload is valid JSON");
+        if let Err(error) = stdin.write_all(&serialized) {
+            warn!("The {} hook did not read its input: {error}", event.name());
+        }
+    }
+    let status = child.wait().map_err(io_error)?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(HookError::Failed {
+            hook,
+            status: status.to_string(),
+        }),
+    }
+}
+
+/// Run the hooks of changes that were just written to the storage file
+pub fn run_pending_hooks(
+    storage_file_path: &str,
+    hook_commands: &HooksConfig,
+    pending_hooks: &[PendingHook],
+) -> Vec<HookError> {
+    let hooks_dir = hooks_dir(storage_file_path);
+    pending_hooks
+        .iter()
+        .filter_map(|pending_hook| {
+            let payload = serde_json::to_value(&pending_hook.topic).expect("A topic is valid JSON");
+            let mut environment = vec![("REVUE_STORAGE", storage_file_path)];
+            if let Some(previous_name) = &pending_hook.previous_name {
+                environment.push(("REVUE_PREVIOUS_NAME", previous_name));
+            }
+            run_hook(
+                &hooks_dir,
+                hook_commands,
+                pending_hook.event,
+                &payload,
+                &environment,
+            )
+            .err()
+        })
+        .collect()
+}
+
+/// The day the daily hook last ran, kept next to the storage file
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+struct HookState {
+    last_daily_run: Option<NaiveDate>,
+}
+
+/// Run the `daily-first-run` hook with today's topics, unless it already ran today
+pub fn run_daily_hook(
+    storage_file_path: &str,
+    hook_commands: &HooksConfig,
+    today: NaiveDate,
+    today_topics: &[ReviewTopic],
+) -> Result<bool, HookError> {
+    let hooks_dir = hooks_dir(storage_file_path);
+    if find_hooks(&hooks_dir, hook_commands, HookEvent::DailyFirstRun).is_empty() {
+        return Ok(false);
+    }
+    let state_file_path = Path::new(storage_file_path).with_extension("hooks.json");
+    let mut state: HookState = fs::read_to_string(&state_file_path)
+        .ok()
+        .and_then(|serialized| serde_json::from_str(&serialized).ok())
+        .unwrap_or_default();
+    if state.last_daily_run == Some(today) {
+        return Ok(false);
+    }
+
+    // Recorded before running, so a failing hook is not retried on every command of the day
+    state.last_daily_run = Some(today);
+    let serialized = serde_json::to_string_pretty(&state).expect("The hook state is valid JSON");
+    if let Err(error) = fs::write(&state_file_path, serialized) {
+        warn!("Could not record the daily hook run: {error}");
+    }
+
+    let payload = serde_json::to_value(today_topics).expect("Topics are valid JSON");
+    run_hook(
+        &hooks_dir,
+        hook_commands,
+        HookEvent::DailyFirstRun,
+        &payload,
+        &[("REVUE_STORAGE", storage_file_path)],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::config::HooksConfig;
+    use crate::io::hooks::{hooks_dir, run_daily_hook, run_hook, HookError, HookEvent};
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::review_topics::ReviewTopic;
+    use serde_json::json;
+    use serial_test::serial;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    static STORAGE_PATH: &str = "./data/hooks_test/storage.json";
+
+    fn write_hook(event: HookEvent, script: &str) -> PathBuf {
+        let dir = hooks_dir(STORAGE_PATH);
+        fs::create_dir_all(&dir).unwrap();
+        let hook = dir.join(event.name());
+        fs::write(&hook, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        hook
+    }
+
+    #[test]
+    fn test_hooks_dir() {
+        assert_eq!(Path::new("./data/hooks"), hooks_dir("./data/storage.json"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_run_hook() {
+        let _ = fs::remove_dir_all("./data/hooks_test");
+        let dir = hooks_dir(STORAGE_PATH);
+        assert!(!run_hook(
+            &dir,
+            &HooksConfig::default(),
+            HookEvent::PostAdd,
+            &json!({}),
+            &[]
+        )
+        .unwrap());
+
+        let output = "./data/hooks_test/output.json";
+        write_hook(HookEvent::PostAdd, &format!("cat > {output}"));
+        let payload = json!({"topic_name": "a"});
+        assert!(run_hook(
+            &dir,
+            &HooksConfig::default(),
+            HookEvent::PostAdd,
+            &payload,
+            &[]
+        )
+        .unwrap());
+        assert_eq!(payload.to_string(), fs::read_to_string(output).unwrap());
+
+        write_hook(HookEvent::PostRemove, "exit 3");
+        let failure = run_hook(
+            &dir,
+            &HooksConfig::default(),
+            HookEvent::PostRemove,
+            &payload,
+            &[],
+        );
+        assert!(matches!(failure, Err(HookError::Failed { .. })));
+    }
+
+    #[test]
+    #[serial]
+    fn test_daily_hook_runs_once_a_day() {
+        let _ = fs::remove_dir_all("./data/hooks_test");
+        let counter = "./data/hooks_test/runs.txt";
+        write_hook(HookEvent::DailyFirstRun, &format!("echo run >> {counter}"));
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let topics = vec![ReviewTopic::new("a".to_string(), &clock)];
+
+        assert!(run_daily_hook(
+            STORAGE_PATH,
+            &HooksConfig::default(),
+            clock.today(),
+            &topics
+        )
+        .unwrap());
+        assert!(!run_daily_hook(
+            STORAGE_PATH,
+            &HooksConfig::default(),
+            clock.today(),
+            &topics
+        )
+        .unwrap());
+        assert!(run_daily_hook(
+            STORAGE_PATH,
+            &HooksConfig::default(),
+            clock.after_days(1).today(),
+            &topics
+        )
+        .unwrap());
+        assert_eq!(2, fs::read_to_string(counter).unwrap().lines().count());
+    }
+}
@@ -0,0 +1,254 @@
+//! Exporting upcoming reviews as an iCalendar file, to plan them in a calendar app
+//!
+//! Every topic keeps the same UID from one export to the next, so importing a newer export
+//! moves its event to the new day instead of adding a second one.
+
+use crate::topics::clock::Clock;
+use crate::topics::review_topics::{ReviewTopic, TopicState};
+use chrono::{Days, NaiveDate};
+use std::collections::BTreeMap;
+
+/// How the reviews are put in the calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcsComponent {
+    /// All-day events
+    Event,
+    /// To-dos due on the review day
+    Todo,
+}
+
+/// Which reviews to export and how
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcsOptions {
+    /// Number of days to export, starting today
+    pub days: u64,
+    /// One entry per day listing all its topics, instead of one per topic
+    pub per_day: bool,
+    pub component: IcsComponent,
+}
+
+/// The topics due in the next days as an iCalendar file
+///
+/// Overdue topics are exported as due today.
+pub fn export_ics(topics: &[ReviewTopic], options: IcsOptions, clock: &dyn Clock) -> String {
+    let today = clock.today();
+    let mut due_days: BTreeMap<NaiveDate, Vec<&ReviewTopic>> = BTreeMap::new();
+    for topic in topics {
+        if matches!(
+            topic.current_state(clock),
+            TopicState::Suspended | TopicState::Archived | TopicState::Buried(_)
+        ) {
+            continue;
+        }
+        let days_until_review = topic.days_until_review(clock).max(0) as u64;
+        if days_until_review >= options.days {
+            continue;
+        }
+        due_days
+            .entry(today + Days::new(days_until_review))
+            .or_default()
+            .push(topic);
+    }
+
+    let stamp = clock.now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//revue//review topics//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for (day, day_topics) in &due_days {
+        match options.per_day {
+            true => {
+                let names: Vec<&str> = day_topics
+                    .iter()
+                    .map(|topic| topic.topic_name.as_str())
+                    .collect();
+                let entry = Entry {
+                    uid: format!("day-{}@revue", day.format("%Y%m%d")),
+                    summary: format!("Review {} topics", names.len()),
+                    description: Some(names.join("\n")),
+                };
+                entry.push_lines(&mut lines, *day, &stamp, options.component);
+            }
+            false => {
+                for topic in day_topics {
+                    let entry = Entry {
+                        uid: format!("{}@revue", topic.id),
+                        summary: format!("Review {}", topic.topic_name),
+                        description: None,
+                    };
+                    entry.push_lines(&mut lines, *day, &stamp, options.component);
+                }
+            }
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold_line(line))
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+struct Entry {
+    uid: String,
+    summary: String,
+    description: Option<String>,
+}
+
+impl Entry {
+    fn push_lines(
+        &self,
+        lines: &mut Vec<String>,
+        day: NaiveDate,
+        stamp: &str,
+        component: IcsComponent,
+    ) {
+        let date = day.format("%Y%m%d");
+        match component {
+            IcsComponent::Event => {
+                lines.push("BEGIN:VEVENT".to_string());
+                lines.push(format!("DTSTART;VALUE=DATE:{date}"));
+                lines.push(format!(
+                    "DTEND;VALUE=DATE:{}",
+                    (day + Days::new(1)).format("%Y%m%d")
+                ));
+                lines.push("TRANSP:TRANSPARENT".to_string());
+            }
+            IcsComponent::Todo => {
+                lines.push("BEGIN:VTODO".to_string());
+                lines.push(format!("DUE;VALUE=DATE:{date}"));
+            }
+        }
+        lines.push(format!("UID:{}", escape_text(&self.uid)));
+        lines.push(format!("DTSTAMP:{stamp}"));
+        lines.push(format!("SUMMARY:{}", escape_text(&self.summary)));
+        if let Some(description) = &self.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        lines.push(match component {
+            IcsComponent::Event => "END:VEVENT".to_string(),
+            IcsComponent::Todo => "END:VTODO".to_string(),
+        });
+    }
+}
+
+/// Escape the characters that have a meaning in iCalendar text values
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// End the line with CRLF and split it so no line is longer than 75 bytes, as iCalendar requires
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut line_length = 0;
+    for character in line.chars() {
+        if line_length + character.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            line_length = 1;
+        }
+        folded.push(character);
+        line_length += character.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::ics::{export_ics, fold_line, IcsComponent, IcsOptions};
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::review_topics::{ReviewTopic, TopicState};
+
+    fn options(per_day: bool) -> IcsOptions {
+        IcsOptions {
+            days: 7,
+            per_day,
+            component: IcsComponent::Event,
+        }
+    }
+
+    fn topics() -> Vec<ReviewTopic> {
+        let clock = FixedClock::utc("2024-05-01T12:00:00Z");
+        let mut far = ReviewTopic::new("far".to_string(), &clock);
+        far.reschedule(clock.after_days(30).today());
+        let mut suspended = ReviewTopic::new("suspended".to_string(), &clock);
+        suspended.state = TopicState::Suspended;
+        let mut buried = ReviewTopic::new("buried".to_string(), &clock.before_days(3));
+        buried.state = TopicState::Buried(clock.today());
+        vec![
+            ReviewTopic::new("a, b".to_string(), &clock),
+            ReviewTopic::new("c".to_string(), &clock),
+            far,
+            suspended,
+            buried,
+        ]
+    }
+
+    #[test]
+    fn test_one_event_per_topic() {
+        let topics = topics();
+        let ics = export_ics(
+            &topics,
+            options(false),
+            &FixedClock::utc("2024-05-01T12:00:00Z"),
+        );
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(2, ics.matches("BEGIN:VEVENT").count());
+        assert!(ics.contains("SUMMARY:Review a\\, b\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20240502\r\n"));
+        assert!(ics.contains(&format!("UID:{}@revue\r\n", topics[0].id)));
+    }
+
+    #[test]
+    fn test_uids_survive_renames() {
+        let mut topics = topics();
+        let before = export_ics(
+            &topics,
+            options(false),
+            &FixedClock::utc("2024-05-01T12:00:00Z"),
+        );
+        topics[1].topic_name = "renamed".to_string();
+        let after = export_ics(
+            &topics,
+            options(false),
+            &FixedClock::utc("2024-05-01T12:00:00Z"),
+        );
+
+        let uids = |ics: &str| -> Vec<String> {
+            ics.lines()
+                .filter(|line| line.starts_with("UID:"))
+                .map(str::to_string)
+                .collect()
+        };
+        assert_eq!(uids(&before), uids(&after));
+    }
+
+    #[test]
+    fn test_one_event_per_day() {
+        let ics = export_ics(
+            &topics(),
+            options(true),
+            &FixedClock::utc("2024-05-01T12:00:00Z"),
+        );
+        assert_eq!(1, ics.matches("BEGIN:VEVENT").count());
+        assert!(ics.contains("UID:day-20240502@revue\r\n"));
+        assert!(ics.contains("DESCRIPTION:a\\, b\\nc\r\n"));
+    }
+
+    #[test]
+    fn test_fold_line() {
+        let line = "x".repeat(100);
+        assert_eq!(
+            format!("{}\r\n {}\r\n", "x".repeat(75), "x".repeat(25)),
+            fold_line(&line)
+        );
+    }
+}
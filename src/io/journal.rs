@@ -0,0 +1,441 @@
+//! The operations that can be undone and redone
+
+use crate::io::encryption::{read_file, write_file, Passphrase};
+use crate::topics::review_topics::ReviewTopic;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::Path;
+
+/// Number of operations kept in the journal. Older ones can no longer be undone.
+const JOURNAL_LENGTH: usize = 100;
+
+/// A change made to the topics through [`crate::io::storage::Storage`]
+///
+/// Every operation keeps enough of the topics to be reverted with [`Operation::inverse`].
+/// Reverting a review, an edit or a merge only puts back the fields it changed, so the changes
+/// made since without the journal, like suspending or rescheduling a topic, are kept.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", tag = "operation")]
+pub enum Operation {
+    Add {
+        topic: ReviewTopic,
+    },
+    Remove {
+        topic: ReviewTopic,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+    Review {
+        before: ReviewTopic,
+        after: ReviewTopic,
+    },
+    /// A change of the tags or the priority of a topic
+    Edit {
+        before: ReviewTopic,
+        after: ReviewTopic,
+    },
+    /// The topics of another storage file merged into the list, as they were before and after
+    Merge {
+        other: String,
+        before: Vec<ReviewTopic>,
+        after: Vec<ReviewTopic>,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Add { topic } => write!(f, "Add '{}'", topic.topic_name),
+            Operation::Remove { topic } => write!(f, "Remove '{}'", topic.topic_name),
+            Operation::Rename { from, to } => write!(f, "Rename '{from}' to '{to}'"),
+            Operation::Review { after, .. } => write!(f, "Review '{}'", after.topic_name),
+            Operation::Edit { after, .. } => write!(f, "Edit '{}'", after.topic_name),
+            Operation::Merge { other, .. } => write!(f, "Merge '{other}'"),
+        }
+    }
+}
+
+impl Operation {
+    /// The operation that reverts this one
+    pub fn inverse(&self) -> Operation {
+        match self {
+            Operation::Add { topic } => Operation::Remove {
+                topic: topic.clone(),
+            },
+            Operation::Remove { topic } => Operation::Add {
+                topic: topic.clone(),
+            },
+            Operation::Rename { from, to } => Operation::Rename {
+                from: to.clone(),
+                to: from.clone(),
+            },
+            Operation::Review { before, after } => Operation::Review {
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Operation::Edit { before, after } => Operation::Edit {
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Operation::Merge {
+                other,
+                before,
+                after,
+            } => Operation::Merge {
+                other: other.clone(),
+                before: after.clone(),
+                after: before.clone(),
+            },
+        }
+    }
+
+    fn apply(&self, review_topic_list: &mut Vec<ReviewTopic>) -> Result<(), JournalError> {
+        let position = |topic_name: &str| {
+            review_topic_list
+                .iter()
+                .position(|topic| topic.topic_name == topic_name)
+        };
+
+        match self {
+            Operation::Add { topic } => match position(&topic.topic_name) {
+                Some(_) => return Err(JournalError::TopicExists(topic.topic_name.clone())),
+                None => review_topic_list.push(topic.clone()),
+            },
+            Operation::Remove { topic } => match position(&topic.topic_name) {
+                None => return Err(JournalError::TopicNotFound(topic.topic_name.clone())),
+                Some(index) => {
+                    review_topic_list.remove(index);
+                }
+            },
+            Operation::Rename { from, to } => match (position(from), position(to)) {
+                (None, _) => return Err(JournalError::TopicNotFound(from.clone())),
+                (Some(_), Some(_)) => return Err(JournalError::TopicExists(to.clone())),
+                (Some(index), None) => review_topic_list[index].topic_name = to.clone(),
+            },
+            Operation::Review { before, after } | Operation::Edit { before, after } => {
+                match position(&before.topic_name) {
+                    None => return Err(JournalError::TopicNotFound(before.topic_name.clone())),
+                    Some(index) => apply_changes(&mut review_topic_list[index], before, after),
+                }
+            }
+            Operation::Merge { before, after, .. } => {
+                let find = |topics: &[ReviewTopic], id: &str| {
+                    topics.iter().position(|topic| topic.id == id)
+                };
+                review_topic_list.retain(|topic| {
+                    find(before, &topic.id).is_none() || find(after, &topic.id).is_some()
+                });
+                for topic in after {
+                    match (find(before, &topic.id), find(review_topic_list, &topic.id)) {
+                        (Some(old), Some(index)) => {
+                            apply_changes(&mut review_topic_list[index], &before[old], topic)
+                        }
+                        (None, None) => review_topic_list.push(topic.clone()),
+                        (Some(_), None) | (None, Some(_)) => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Give the fields that differ between `before` and `after` their value in `after`, and keep the
+/// other fields of the topic as they are now
+fn apply_changes(topic: &mut ReviewTopic, before: &ReviewTopic, after: &ReviewTopic) {
+    let to_fields = |topic: &ReviewTopic| match serde_json::to_value(topic) {
+        Ok(Value::Object(fields)) => fields,
+        _ => unreachable!("A topic is a JSON object"),
+    };
+    let (before, after) = (to_fields(before), to_fields(after));
+    let mut fields = to_fields(topic);
+    for key in before.keys().chain(after.keys()) {
+        if before.get(key) == after.get(key) {
+            continue;
+        }
+        match after.get(key) {
+            Some(value) => fields.insert(key.clone(), value.clone()),
+            None => fields.remove(key),
+        };
+    }
+    *topic = serde_json::from_value(Value::Object(fields)).expect("The changed topic is valid");
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum JournalError {
+    NothingToUndo,
+    NothingToRedo,
+    /// The topic the operation needs was removed or renamed without going through the journal
+    TopicNotFound(String),
+    /// The operation would create a topic with the same name as an existing one
+    TopicExists(String),
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::NothingToUndo => write!(f, "Nothing to undo."),
+            JournalError::NothingToRedo => write!(f, "Nothing to redo."),
+            JournalError::TopicNotFound(topic_name) => {
+                write!(f, "Review topic '{topic_name}' was not found.")
+            }
+            JournalError::TopicExists(topic_name) => {
+                write!(f, "Review topic '{topic_name}' already exists.")
+            }
+        }
+    }
+}
+
+/// The recent operations, kept next to the storage file so they can be undone and redone
+#[derive(Default, Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Journal {
+    done: Vec<Operation>,
+    undone: Vec<Operation>,
+}
+
+impl Journal {
+    /// Path of the journal belonging to the given storage file, for example
+    /// `./data/storage.journal.json` for `./data/storage.json`
+    pub fn path_for(storage_file_path: &str) -> String {
+        Path::new(storage_file_path)
+            .with_extension("journal.json")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Read the journal from the given path. A missing or unreadable journal is treated as empty,
+    /// as losing it only means the past operations can no longer be undone.
+    pub fn load(journal_file_path: &str, passphrase: Option<&Passphrase>) -> Self {
+        let serialized = match read_file(journal_file_path, passphrase) {
+            Ok(serialized) => serialized,
+            Err(_) => {
+                info!("No journal found");
+                return Journal::default();
+            }
+        };
+        match serde_json::from_str(&serialized) {
+            Ok(journal) => journal,
+            Err(error) => {
+                warn!("Ignoring the journal as it could not be read: {error}");
+                Journal::default()
+            }
+        }
+    }
+
+    /// Write the journal, encrypted with the passphrase of the storage it belongs to if it has one
+    pub fn write(&self, journal_file_path: &str, passphrase: Option<&Passphrase>) {
+        let serialized = match serde_json::to_string_pretty(self) {
+            Ok(serialized) => serialized,
+            Err(_) => panic!("Something went wrong serializing the journal"),
+        };
+        match write_file(journal_file_path, &serialized, passphrase) {
+            Ok(_) => info!("Journal written"),
+            Err(error_log) => error!("Writing the journal failed: {error_log}"),
+        }
+    }
+
+    /// Add a new operation. Operations that were undone can no longer be redone afterwards.
+    pub fn record(&mut self, operation: Operation) {
+        self.done.push(operation);
+        if self.done.len() > JOURNAL_LENGTH {
+            self.done.remove(0);
+        }
+        self.undone.clear();
+    }
+
+    /// Revert the most recent operation
+    pub fn undo(
+        &mut self,
+        review_topic_list: &mut Vec<ReviewTopic>,
+    ) -> Result<Operation, JournalError> {
+        let operation = self.done.last().ok_or(JournalError::NothingToUndo)?;
+        operation.inverse().apply(review_topic_list)?;
+
+        let operation = self.done.pop().expect("The journal is not empty");
+        self.undone.push(operation.clone());
+        Ok(operation)
+    }
+
+    /// Apply the most recently undone operation again
+    pub fn redo(
+        &mut self,
+        review_topic_list: &mut Vec<ReviewTopic>,
+    ) -> Result<Operation, JournalError> {
+        let operation = self.undone.last().ok_or(JournalError::NothingToRedo)?;
+        operation.apply(review_topic_list)?;
+
+        let operation = self
+            .undone
+            .pop()
+            .expect("The undone operations are not empty");
+        self.done.push(operation.clone());
+        Ok(operation)
+    }
+
+    /// The operations that can be undone, most recent first
+    pub fn recent(&self) -> impl Iterator<Item = &Operation> {
+        self.done.iter().rev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::journal::{Journal, JournalError, Operation};
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::review_topics::{id_from_name, ReviewTopic, TopicState};
+
+    #[test]
+    fn test_path_for() {
+        assert_eq!(
+            "./data/storage.journal.json",
+            Journal::path_for("./data/storage.json")
+        );
+    }
+
+    #[test]
+    fn test_topics_without_id_get_one() {
+        let serialized = r#"{
+            "done": [{
+                "operation": "add",
+                "topic": {
+                    "topic_name": "rust",
+                    "last_reviewed": "2024-05-01T12:00:00Z",
+                    "next_review_gap": "Day"
+                }
+            }],
+            "undone": []
+        }"#;
+        let journal: Journal = serde_json::from_str(serialized).unwrap();
+        let Some(Operation::Add { topic }) = journal.recent().next() else {
+            panic!("The journal has the added topic");
+        };
+        assert_eq!(id_from_name("rust"), topic.id);
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let topic = ReviewTopic::new("topic".to_string(), &clock);
+        let mut reviewed = topic.clone();
+        reviewed.review(&clock);
+
+        let mut journal = Journal::default();
+        let mut list = vec![reviewed.clone()];
+        journal.record(Operation::Add {
+            topic: topic.clone(),
+        });
+        journal.record(Operation::Review {
+            before: topic.clone(),
+            after: reviewed.clone(),
+        });
+
+        assert!(matches!(
+            journal.undo(&mut list),
+            Ok(Operation::Review { .. })
+        ));
+        assert_eq!(vec![topic.clone()], list);
+        assert!(matches!(journal.undo(&mut list), Ok(Operation::Add { .. })));
+        assert!(list.is_empty());
+        assert_eq!(Err(JournalError::NothingToUndo), journal.undo(&mut list));
+
+        assert!(journal.redo(&mut list).is_ok());
+        assert!(journal.redo(&mut list).is_ok());
+        assert_eq!(vec![reviewed], list);
+        assert_eq!(Err(JournalError::NothingToRedo), journal.redo(&mut list));
+    }
+
+    #[test]
+    fn test_undo_keeps_later_changes() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let topic = ReviewTopic::new("topic".to_string(), &clock.before_days(3));
+        let mut reviewed = topic.clone();
+        reviewed.review(&clock);
+        let mut journal = Journal::default();
+        journal.record(Operation::Review {
+            before: topic.clone(),
+            after: reviewed.clone(),
+        });
+
+        // Suspended and rescheduled afterwards, which the journal does not record
+        let mut changed = reviewed.clone();
+        changed.state = TopicState::Suspended;
+        changed.scheduled_review = Some(clock.after_days(2).today());
+        let mut list = vec![changed.clone()];
+        journal.undo(&mut list).unwrap();
+
+        assert_eq!(topic.review_history, list[0].review_history);
+        assert_eq!(topic.last_reviewed, list[0].last_reviewed);
+        assert_eq!(TopicState::Suspended, list[0].state);
+        assert_eq!(changed.scheduled_review, list[0].scheduled_review);
+        journal.redo(&mut list).unwrap();
+        assert_eq!(vec![changed], list);
+    }
+
+    #[test]
+    fn test_undo_merge_keeps_later_changes() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let kept = ReviewTopic::new("kept".to_string(), &clock);
+        let mut merged = kept.clone();
+        merged.tags = vec!["rust".to_string()];
+        let added = ReviewTopic::new("added".to_string(), &clock);
+        let mut journal = Journal::default();
+        journal.record(Operation::Merge {
+            other: "other.json".to_string(),
+            before: vec![kept.clone()],
+            after: vec![merged.clone(), added],
+        });
+
+        let mut changed = merged.clone();
+        changed.state = TopicState::Archived;
+        let mut list = vec![changed, ReviewTopic::new("later".to_string(), &clock)];
+        journal.undo(&mut list).unwrap();
+
+        let names: Vec<&str> = list.iter().map(|topic| topic.topic_name.as_str()).collect();
+        assert_eq!(vec!["kept", "later"], names);
+        assert!(list[0].tags.is_empty());
+        assert_eq!(TopicState::Archived, list[0].state);
+    }
+
+    #[test]
+    fn test_record_clears_redo() {
+        let topic = ReviewTopic::new(
+            "topic".to_string(),
+            &FixedClock::utc("2024-05-10T12:00:00Z"),
+        );
+        let mut journal = Journal::default();
+        let mut list = Vec::new();
+        journal.record(Operation::Add {
+            topic: topic.clone(),
+        });
+        list.push(topic.clone());
+        journal.undo(&mut list).unwrap();
+
+        journal.record(Operation::Rename {
+            from: "a".to_string(),
+            to: "b".to_string(),
+        });
+        assert_eq!(Err(JournalError::NothingToRedo), journal.redo(&mut list));
+        assert_eq!(1, journal.recent().count());
+    }
+
+    #[test]
+    fn test_failed_undo_keeps_the_operation() {
+        let mut journal = Journal::default();
+        let mut list = Vec::new();
+        journal.record(Operation::Rename {
+            from: "a".to_string(),
+            to: "b".to_string(),
+        });
+
+        assert_eq!(
+            Err(JournalError::TopicNotFound("b".to_string())),
+            journal.undo(&mut list)
+        );
+        assert_eq!(1, journal.recent().count());
+    }
+}
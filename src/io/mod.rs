@@ -1,2 +1,18 @@
-pub(crate) mod console;
-pub(crate) mod storage;
+//! Reading and writing the topics, and showing them on the console
+
+pub mod backup;
+pub mod config;
+pub mod console;
+pub mod doctor;
+pub mod encryption;
+pub mod events;
+pub mod hooks;
+pub mod ics;
+pub mod journal;
+pub mod profiles;
+pub mod remind;
+pub mod rpc;
+pub mod server;
+pub mod status;
+pub mod storage;
+pub mod sync;
@@ -0,0 +1,285 @@
+//! Named profiles, each with its own config file and storage
+//!
+//! The default profile uses `~/.config/revue/config.toml` and `./data/storage.json`. A profile
+//! named `work` uses `~/.config/revue/profiles/work/config.toml` and
+//! `./data/profiles/work/storage.json`, unless its config file sets another storage path.
+//!
+//! The profile used is the one given with `--profile`, then the one in `REVUE_PROFILE`, then the
+//! one last picked with `revue profile switch`.
+
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// The profile used when none was created or picked
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug)]
+pub enum ProfileError {
+    /// Names are made of letters, digits, `-` and `_`
+    InvalidName(String),
+    NotFound(String),
+    Exists(String),
+    CannotDeleteDefault,
+    /// There is no home directory to keep the profiles in
+    NoConfigDirectory,
+    Io(io::Error),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::InvalidName(profile) => write!(
+                f,
+                "'{profile}' is not a valid profile name. Use letters, digits, '-' and '_'."
+            ),
+            ProfileError::NotFound(profile) => write!(
+                f,
+                "There is no profile '{profile}'. Create it with `revue profile create {profile}`."
+            ),
+            ProfileError::Exists(profile) => write!(f, "The profile '{profile}' already exists."),
+            ProfileError::CannotDeleteDefault => {
+                write!(f, "The {DEFAULT_PROFILE} profile cannot be deleted.")
+            }
+            ProfileError::NoConfigDirectory => {
+                write!(f, "There is no home directory to keep the profiles in.")
+            }
+            ProfileError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl From<io::Error> for ProfileError {
+    fn from(error: io::Error) -> Self {
+        ProfileError::Io(error)
+    }
+}
+
+/// The profiles kept in a config directory
+#[derive(Debug, Clone)]
+pub struct Profiles {
+    config_dir: Option<PathBuf>,
+}
+
+impl Profiles {
+    /// `config_dir` is the directory of the default config file, like `~/.config/revue`
+    pub fn new(config_dir: Option<PathBuf>) -> Self {
+        Profiles { config_dir }
+    }
+
+    /// The config file of a profile
+    pub fn config_file_path(&self, profile: &str) -> Option<PathBuf> {
+        let config_dir = self.config_dir.as_ref()?;
+        Some(match profile {
+            DEFAULT_PROFILE => config_dir.join("config.toml"),
+            profile => profiles_dir(config_dir).join(profile).join("config.toml"),
+        })
+    }
+
+    /// The default profile first, then the created ones by name
+    pub fn list(&self) -> Vec<String> {
+        let mut created: Vec<String> = self
+            .config_dir
+            .as_ref()
+            .and_then(|config_dir| fs::read_dir(profiles_dir(config_dir)).ok())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|profile| validate_name(profile).is_ok())
+            .collect();
+        created.sort();
+        let mut profiles = vec![DEFAULT_PROFILE.to_string()];
+        profiles.extend(created);
+        profiles
+    }
+
+    pub fn exists(&self, profile: &str) -> bool {
+        profile == DEFAULT_PROFILE || self.list().iter().any(|created| created == profile)
+    }
+
+    /// The profile picked with `revue profile switch`
+    pub fn remembered(&self) -> String {
+        self.remembered_file_path()
+            .and_then(|file_path| fs::read_to_string(file_path).ok())
+            .map(|profile| profile.trim().to_string())
+            .filter(|profile| self.exists(profile))
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    /// The profile to use, given the `--profile` flag
+    pub fn select(&self, flag: Option<&str>) -> Result<String, ProfileError> {
+        let chosen = flag
+            .map(str::to_string)
+            .or_else(|| env::var("REVUE_PROFILE").ok())
+            .filter(|profile| !profile.is_empty());
+        match chosen {
+            None => Ok(self.remembered()),
+            Some(profile) => {
+                validate_name(&profile)?;
+                match self.exists(&profile) {
+                    true => Ok(profile),
+                    false => Err(ProfileError::NotFound(profile)),
+                }
+            }
+        }
+    }
+
+    pub fn create(&self, profile: &str) -> Result<(), ProfileError> {
+        validate_name(profile)?;
+        if self.exists(profile) {
+            return Err(ProfileError::Exists(profile.to_string()));
+        }
+        let config_file_path = self
+            .config_file_path(profile)
+            .ok_or(ProfileError::NoConfigDirectory)?;
+        let profile_dir = config_file_path
+            .parent()
+            .expect("Profiles have a directory");
+        fs::create_dir_all(profile_dir)?;
+        Ok(())
+    }
+
+    /// Remember a profile as the one to use from now on
+    pub fn switch(&self, profile: &str) -> Result<(), ProfileError> {
+        validate_name(profile)?;
+        if !self.exists(profile) {
+            return Err(ProfileError::NotFound(profile.to_string()));
+        }
+        let file_path = self
+            .remembered_file_path()
+            .ok_or(ProfileError::NoConfigDirectory)?;
+        if let Some(config_dir) = file_path.parent() {
+            fs::create_dir_all(config_dir)?;
+        }
+        fs::write(file_path, profile)?;
+        Ok(())
+    }
+
+    /// Delete the config of a profile. Its storage is kept.
+    pub fn delete(&self, profile: &str) -> Result<(), ProfileError> {
+        validate_name(profile)?;
+        if profile == DEFAULT_PROFILE {
+            return Err(ProfileError::CannotDeleteDefault);
+        }
+        if !self.exists(profile) {
+            return Err(ProfileError::NotFound(profile.to_string()));
+        }
+        if self.remembered() == profile {
+            self.switch(DEFAULT_PROFILE)?;
+        }
+        let config_file_path = self
+            .config_file_path(profile)
+            .ok_or(ProfileError::NoConfigDirectory)?;
+        fs::remove_dir_all(
+            config_file_path
+                .parent()
+                .expect("Profiles have a directory"),
+        )?;
+        Ok(())
+    }
+
+    fn remembered_file_path(&self) -> Option<PathBuf> {
+        Some(self.config_dir.as_ref()?.join("profile"))
+    }
+}
+
+fn profiles_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("profiles")
+}
+
+/// The storage file of a profile whose config does not set one
+pub fn storage_path(profile: &str) -> Option<String> {
+    match profile {
+        DEFAULT_PROFILE => None,
+        profile => Some(format!("./data/profiles/{profile}/storage.json")),
+    }
+}
+
+fn validate_name(profile: &str) -> Result<(), ProfileError> {
+    let valid = !profile.is_empty()
+        && !profile.starts_with('-')
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    match valid {
+        true => Ok(()),
+        false => Err(ProfileError::InvalidName(profile.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::profiles::{storage_path, ProfileError, Profiles, DEFAULT_PROFILE};
+    use serial_test::serial;
+    use std::fs;
+    use std::path::PathBuf;
+
+    static CONFIG_DIR: &str = "./data/test_profiles";
+
+    fn profiles() -> Profiles {
+        let _ = fs::remove_dir_all(CONFIG_DIR);
+        Profiles::new(Some(PathBuf::from(CONFIG_DIR)))
+    }
+
+    #[test]
+    #[serial]
+    fn test_create_switch_delete() {
+        let profiles = profiles();
+        assert_eq!(vec![DEFAULT_PROFILE], profiles.list());
+        assert_eq!(DEFAULT_PROFILE, profiles.remembered());
+
+        profiles.create("work").unwrap();
+        profiles.create("onboarding").unwrap();
+        assert!(matches!(
+            profiles.create("work"),
+            Err(ProfileError::Exists(_))
+        ));
+        assert_eq!(vec!["default", "onboarding", "work"], profiles.list());
+
+        profiles.switch("work").unwrap();
+        assert_eq!("work", profiles.remembered());
+        assert_eq!("onboarding", profiles.select(Some("onboarding")).unwrap());
+        assert!(matches!(
+            profiles.select(Some("missing")),
+            Err(ProfileError::NotFound(_))
+        ));
+
+        profiles.delete("work").unwrap();
+        assert_eq!(DEFAULT_PROFILE, profiles.remembered());
+        assert_eq!(vec!["default", "onboarding"], profiles.list());
+        assert!(matches!(
+            profiles.delete(DEFAULT_PROFILE),
+            Err(ProfileError::CannotDeleteDefault)
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn test_paths() {
+        let profiles = profiles();
+        assert_eq!(
+            Some(PathBuf::from("./data/test_profiles/config.toml")),
+            profiles.config_file_path(DEFAULT_PROFILE)
+        );
+        assert_eq!(
+            Some(PathBuf::from(
+                "./data/test_profiles/profiles/work/config.toml"
+            )),
+            profiles.config_file_path("work")
+        );
+        assert_eq!(None, storage_path(DEFAULT_PROFILE));
+        assert_eq!(
+            Some("./data/profiles/work/storage.json".to_string()),
+            storage_path("work")
+        );
+        assert!(matches!(
+            profiles.create("../escape"),
+            Err(ProfileError::InvalidName(_))
+        ));
+    }
+}
@@ -0,0 +1,362 @@
+//! Reminding about due topics through a notifier, at most once a day
+//!
+//! `revue remind` checks once and is meant to be run by a systemd user timer or cron, for which
+//! `revue remind units` writes the configuration. `revue remind --daemon` keeps checking instead.
+
+use crate::io::status::Status;
+use crate::io::storage::StorageDocument;
+use crate::topics::clock::Clock;
+use chrono::NaiveDate;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::{fs, io};
+
+/// Where a reminder is sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notifier {
+    /// A shell command, called with the message as its last argument and in `REVUE_MESSAGE`
+    Command(String),
+    /// A file the message is appended to
+    File(String),
+    /// Printed on stdout
+    Stdout,
+}
+
+#[derive(Debug)]
+pub enum RemindError {
+    /// The notifier command ran but did not succeed
+    CommandFailed(String),
+    /// Cron cannot run a command evenly every this many minutes
+    UnsupportedInterval(u64),
+    Io(io::Error),
+}
+
+impl fmt::Display for RemindError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RemindError::CommandFailed(status) => {
+                write!(f, "The notifier command failed with {status}.")
+            }
+            RemindError::UnsupportedInterval(every_minutes) => write!(
+                f,
+                "Cron cannot run revue every {every_minutes} minutes. Use a number of minutes that divides 60, or of hours that divides 24."
+            ),
+            RemindError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for RemindError {}
+
+impl From<io::Error> for RemindError {
+    fn from(error: io::Error) -> Self {
+        RemindError::Io(error)
+    }
+}
+
+impl Notifier {
+    pub fn notify(&self, message: &str) -> Result<(), RemindError> {
+        match self {
+            Notifier::Command(command) => {
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(format!("{command} \"$1\""))
+                    .arg("revue")
+                    .arg(message)
+                    .env("REVUE_MESSAGE", message)
+                    .status()?;
+                match status.success() {
+                    true => Ok(()),
+                    false => Err(RemindError::CommandFailed(status.to_string())),
+                }
+            }
+            Notifier::File(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{message}")?;
+                Ok(())
+            }
+            Notifier::Stdout => {
+                println!("{message}");
+                Ok(())
+            }
+        }
+    }
+
+    /// The `revue remind` arguments that select this notifier
+    fn arguments(&self) -> Vec<String> {
+        match self {
+            Notifier::Command(command) => vec!["--notify-command".to_string(), command.clone()],
+            Notifier::File(path) => vec!["--notify-file".to_string(), path.clone()],
+            Notifier::Stdout => Vec::new(),
+        }
+    }
+}
+
+/// How a systemd timer or cron runs `revue remind`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemindCommand {
+    pub executable: String,
+    pub working_directory: String,
+    /// The `--profile` given to `revue remind units`, so the reminders read the same storage
+    pub profile: Option<String>,
+    /// The `--config` given to `revue remind units`
+    pub config: Option<String>,
+    /// The `--storage` given to `revue remind units`
+    pub storage: Option<String>,
+    pub notifier: Notifier,
+}
+
+impl RemindCommand {
+    /// The executable and its arguments
+    fn arguments(&self) -> Vec<String> {
+        let mut arguments = vec![self.executable.clone()];
+        let flags = [
+            ("--profile", &self.profile),
+            ("--config", &self.config),
+            ("--storage", &self.storage),
+        ];
+        for (flag, value) in flags {
+            if let Some(value) = value {
+                arguments.push(flag.to_string());
+                arguments.push(value.clone());
+            }
+        }
+        arguments.push("remind".to_string());
+        arguments.extend(self.notifier.arguments());
+        arguments
+    }
+}
+
+/// The day the last reminder was sent, kept next to the storage file
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct ReminderState {
+    last_notified: Option<NaiveDate>,
+}
+
+impl ReminderState {
+    pub fn path_for(storage_file_path: &str) -> String {
+        Path::new(storage_file_path)
+            .with_extension("remind.json")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// A missing or unreadable state only means a reminder may be sent twice on one day
+    pub fn load(state_file_path: &str) -> Self {
+        match fs::read_to_string(state_file_path) {
+            Err(_) => ReminderState::default(),
+            Ok(serialized) => serde_json::from_str(&serialized).unwrap_or_else(|error| {
+                warn!("Ignoring the reminder state as it could not be read: {error}");
+                ReminderState::default()
+            }),
+        }
+    }
+
+    pub fn write(&self, state_file_path: &str) -> io::Result<()> {
+        let serialized =
+            serde_json::to_string_pretty(self).expect("The reminder state is valid JSON");
+        fs::write(state_file_path, serialized)
+    }
+}
+
+/// Send a reminder if topics are due and none was sent today yet
+///
+/// returns: The message that was sent, if any
+pub fn remind(
+    document: &StorageDocument,
+    state_file_path: &str,
+    notifier: &Notifier,
+    clock: &dyn Clock,
+) -> Result<Option<String>, RemindError> {
+    let mut state = ReminderState::load(state_file_path);
+    if state.last_notified == Some(clock.today()) {
+        info!("Already reminded today");
+        return Ok(None);
+    }
+
+    let status = Status::of(document, clock);
+    let due = match status.due {
+        0 => return Ok(None),
+        1 => "1 topic is".to_string(),
+        due => format!("{due} topics are"),
+    };
+    let message = match status.overdue {
+        0 => format!("{due} due for review"),
+        overdue => format!("{due} due for review, {overdue} overdue"),
+    };
+
+    notifier.notify(&message)?;
+    state.last_notified = Some(clock.today());
+    state.write(state_file_path)?;
+    Ok(Some(message))
+}
+
+/// A systemd user service and timer running `revue remind` every `every_minutes` minutes
+///
+/// returns: The service and the timer unit files
+pub fn systemd_units(command: &RemindCommand, every_minutes: u64) -> (String, String) {
+    let exec_start: Vec<String> = command
+        .arguments()
+        .iter()
+        .map(|arg| systemd_quote(arg))
+        .collect();
+
+    let service = format!(
+        "[Unit]\nDescription=Remind about due revue topics\n\n[Service]\nType=oneshot\nWorkingDirectory={}\nExecStart={}\n",
+        command.working_directory,
+        exec_start.join(" ")
+    );
+    let timer = format!(
+        "[Unit]\nDescription=Check for due revue topics every {every_minutes} minutes\n\n[Timer]\nOnBootSec=5min\nOnUnitActiveSec={every_minutes}min\n\n[Install]\nWantedBy=timers.target\n"
+    );
+    (service, timer)
+}
+
+/// A crontab line running `revue remind` every `every_minutes` minutes
+///
+/// Cron counts minutes within the hour and hours within the day, so it only runs a command
+/// evenly when the minutes divide an hour, or the hours divide a day.
+pub fn cron_line(command: &RemindCommand, every_minutes: u64) -> Result<String, RemindError> {
+    let schedule = match (every_minutes / 60, every_minutes % 60) {
+        (0, minutes) if minutes > 0 && 60 % minutes == 0 => format!("*/{minutes} * * * *"),
+        (1, 0) => "0 * * * *".to_string(),
+        (24, 0) => "0 0 * * *".to_string(),
+        (hours, 0) if 24 % hours == 0 => format!("0 */{hours} * * *"),
+        _ => return Err(RemindError::UnsupportedInterval(every_minutes)),
+    };
+    let arguments: Vec<String> = command
+        .arguments()
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect();
+    Ok(format!(
+        "{schedule} cd {} && {}",
+        shell_quote(&command.working_directory),
+        arguments.join(" ")
+    ))
+}
+
+fn systemd_quote(argument: &str) -> String {
+    format!(
+        "\"{}\"",
+        argument
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+    )
+}
+
+fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::remind::{
+        cron_line, remind, systemd_units, Notifier, RemindCommand, RemindError, ReminderState,
+    };
+    use crate::io::storage::StorageDocument;
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::review_topics::ReviewTopic;
+    use serde_json::json;
+    use serial_test::serial;
+    use std::fs;
+
+    static STATE_PATH: &str = "./data/test_storage.remind.json";
+    static NOTIFY_PATH: &str = "./data/test_notifications.txt";
+
+    #[test]
+    #[serial]
+    fn test_reminds_once_a_day() {
+        fs::create_dir_all("./data").unwrap();
+        let _ = fs::remove_file(STATE_PATH);
+        let _ = fs::remove_file(NOTIFY_PATH);
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let topic = ReviewTopic::new("a".to_string(), &clock.before_days(1));
+        let document =
+            StorageDocument::from_value(json!({ "review_topic_list": [topic] })).unwrap();
+        let notifier = Notifier::File(NOTIFY_PATH.to_string());
+
+        let message = remind(&document, STATE_PATH, &notifier, &clock).unwrap();
+        assert_eq!(Some("1 topic is due for review".to_string()), message);
+        assert_eq!(
+            None,
+            remind(&document, STATE_PATH, &notifier, &clock).unwrap()
+        );
+        remind(&document, STATE_PATH, &notifier, &clock.after_days(1)).unwrap();
+
+        assert_eq!(2, fs::read_to_string(NOTIFY_PATH).unwrap().lines().count());
+        assert_eq!(
+            Some(clock.after_days(1).today()),
+            ReminderState::load(STATE_PATH).last_notified
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn test_nothing_due() {
+        let _ = fs::remove_file(STATE_PATH);
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let topic = ReviewTopic::new("a".to_string(), &clock);
+        let document =
+            StorageDocument::from_value(json!({ "review_topic_list": [topic] })).unwrap();
+        let notifier = Notifier::Command("false".to_string());
+        assert_eq!(
+            None,
+            remind(&document, STATE_PATH, &notifier, &clock).unwrap()
+        );
+    }
+
+    fn command(profile: Option<&str>) -> RemindCommand {
+        RemindCommand {
+            executable: "/usr/bin/revue".to_string(),
+            working_directory: "/home/me".to_string(),
+            profile: profile.map(str::to_string),
+            config: None,
+            storage: None,
+            notifier: Notifier::Command("notify-send 'It is time'".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_units() {
+        let (service, timer) = systemd_units(&command(None), 30);
+        assert!(service.contains(
+            "ExecStart=\"/usr/bin/revue\" \"remind\" \"--notify-command\" \"notify-send 'It is time'\"\n"
+        ));
+        assert!(timer.contains("OnUnitActiveSec=30min\n"));
+
+        assert_eq!(
+            "0 * * * * cd '/home/me' && '/usr/bin/revue' 'remind' '--notify-command' 'notify-send '\\''It is time'\\'''",
+            cron_line(&command(None), 60).unwrap()
+        );
+        let schedule = |every_minutes| {
+            cron_line(&command(None), every_minutes)
+                .map(|line| line.split(" cd ").next().unwrap().to_string())
+        };
+        assert_eq!("*/15 * * * *", schedule(15).unwrap());
+        assert_eq!("0 */6 * * *", schedule(360).unwrap());
+        assert_eq!("0 0 * * *", schedule(1440).unwrap());
+        for every_minutes in [45, 90, 1500] {
+            assert!(matches!(
+                schedule(every_minutes),
+                Err(RemindError::UnsupportedInterval(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_units_keep_the_profile() {
+        let (service, _) = systemd_units(&command(Some("work")), 30);
+        assert!(service.contains("\"/usr/bin/revue\" \"--profile\" \"work\" \"remind\""));
+        assert!(cron_line(&command(Some("work")), 30)
+            .unwrap()
+            .contains("'/usr/bin/revue' '--profile' 'work' 'remind'"));
+    }
+}
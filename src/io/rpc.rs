@@ -0,0 +1,723 @@
+//! A JSON-RPC 2.0 interface on stdin and stdout, for editor plugins and other tools
+//!
+//! Every line read is one request, and every response is written as one line. The storage file is
+//! read again before every request, so the changes made by other revue commands in the meantime
+//! are kept, and written after every change.
+//!
+//! ```text
+//! --> {"jsonrpc": "2.0", "id": 1, "method": "review", "params": {"topic_name": "Ownership"}}
+//! <-- {"jsonrpc":"2.0","id":1,"result":{"topic_name":"Ownership",...}}
+//! ```
+//!
+//! The methods mirror the commands: `list`, `today`, `add`, `remove`, `rename`, `edit`, `review`,
+//! `reschedule`, `suspend`, `bury`, `archive`, `activate`, `pause`, `resume`, `catch_up`, `undo`,
+//! `redo` and `log`.
+
+use crate::io::journal::{JournalError, Operation};
+use crate::io::storage::{ReviewError, Storage, TopicNotFound};
+use crate::topics::clock::Clock;
+use crate::topics::review_topics::{ReviewTopic, TopicState};
+use chrono::{Days, NaiveDate};
+use log::{error, info};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fmt::Formatter;
+use std::io::{BufRead, Write};
+
+/// Why a request failed. Each kind has its own JSON-RPC error code.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RpcError {
+    /// The line is not valid JSON
+    Parse(String),
+    /// The JSON is not a JSON-RPC request
+    InvalidRequest(String),
+    MethodNotFound(String),
+    InvalidParams(String),
+    TopicNotFound(String),
+    TopicExists(String),
+    /// The day given for a review or a pause is not allowed
+    InvalidDay(String),
+    /// There is nothing to undo or redo, or the journal no longer matches the topics
+    Journal(String),
+    /// The storage file could not be read again
+    Storage(String),
+}
+
+impl RpcError {
+    /// The JSON-RPC error code. Codes below -32000 are the ones defined by JSON-RPC.
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcError::Parse(_) => -32700,
+            RpcError::InvalidRequest(_) => -32600,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::InvalidParams(_) => -32602,
+            RpcError::TopicNotFound(_) => 1,
+            RpcError::TopicExists(_) => 2,
+            RpcError::InvalidDay(_) => 3,
+            RpcError::Journal(_) => 4,
+            RpcError::Storage(_) => 5,
+        }
+    }
+
+    /// A name for the kind of error, sent along so clients do not need to know the codes
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RpcError::Parse(_) => "parse_error",
+            RpcError::InvalidRequest(_) => "invalid_request",
+            RpcError::MethodNotFound(_) => "method_not_found",
+            RpcError::InvalidParams(_) => "invalid_params",
+            RpcError::TopicNotFound(_) => "topic_not_found",
+            RpcError::TopicExists(_) => "topic_exists",
+            RpcError::InvalidDay(_) => "invalid_day",
+            RpcError::Journal(_) => "journal",
+            RpcError::Storage(_) => "storage",
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::Parse(message)
+            | RpcError::InvalidRequest(message)
+            | RpcError::InvalidParams(message)
+            | RpcError::InvalidDay(message)
+            | RpcError::Journal(message)
+            | RpcError::Storage(message) => write!(f, "{message}"),
+            RpcError::MethodNotFound(method) => write!(f, "There is no method '{method}'."),
+            RpcError::TopicNotFound(topic_name) => {
+                write!(f, "Review topic '{topic_name}' was not found.")
+            }
+            RpcError::TopicExists(topic_name) => {
+                write!(f, "Review topic '{topic_name}' already exists.")
+            }
+        }
+    }
+}
+
+impl From<TopicNotFound> for RpcError {
+    fn from(not_found: TopicNotFound) -> Self {
+        RpcError::TopicNotFound(not_found.0)
+    }
+}
+
+impl From<ReviewError> for RpcError {
+    fn from(review_error: ReviewError) -> Self {
+        match review_error {
+            ReviewError::NotFound(not_found) => not_found.into(),
+            ReviewError::InvalidDay(review_date_error) => {
+                RpcError::InvalidDay(review_date_error.to_string())
+            }
+        }
+    }
+}
+
+impl From<JournalError> for RpcError {
+    fn from(journal_error: JournalError) -> Self {
+        RpcError::Journal(journal_error.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Requests without an id are notifications, which get no response. A null id is an id.
+    #[serde(default, deserialize_with = "present_id")]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+/// Keep an id given as null apart from a missing one, which `Option<Value>` reads as `None`
+fn present_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct TopicParams {
+    topic_name: String,
+}
+
+#[derive(Deserialize)]
+struct AddParams {
+    topic_name: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    priority: u8,
+}
+
+#[derive(Deserialize)]
+struct RenameParams {
+    topic_name: String,
+    new_topic_name: String,
+}
+
+#[derive(Deserialize)]
+struct EditParams {
+    topic_name: String,
+    #[serde(default)]
+    new_topic_name: Option<String>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    priority: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct ReviewParams {
+    topic_name: String,
+    #[serde(default)]
+    on: Option<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+struct RescheduleParams {
+    topic_name: String,
+    #[serde(default)]
+    due: Option<NaiveDate>,
+    #[serde(default, rename = "in")]
+    in_days: Option<u64>,
+    #[serde(default)]
+    reset: bool,
+}
+
+#[derive(Deserialize)]
+struct PauseParams {
+    until: NaiveDate,
+}
+
+#[derive(Deserialize)]
+struct CatchUpParams {
+    #[serde(default = "default_catch_up_days")]
+    days: u64,
+}
+
+fn default_catch_up_days() -> u64 {
+    7
+}
+
+#[derive(Deserialize)]
+struct StepsParams {
+    #[serde(default = "default_steps")]
+    steps: usize,
+}
+
+fn default_steps() -> usize {
+    1
+}
+
+#[derive(Deserialize)]
+struct LogParams {
+    #[serde(default = "default_log_limit")]
+    limit: usize,
+}
+
+fn default_log_limit() -> usize {
+    10
+}
+
+/// Answers JSON-RPC requests against a storage that stays loaded
+pub struct RpcSession {
+    storage: Storage,
+}
+
+impl RpcSession {
+    pub fn new(storage: Storage) -> Self {
+        RpcSession { storage }
+    }
+
+    /// Answer every line of `reader` on `writer` until the input ends
+    pub fn run(
+        &mut self,
+        reader: impl BufRead,
+        mut writer: impl Write,
+        clock: &dyn Clock,
+    ) -> std::io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_line(&line, clock) {
+                writeln!(writer, "{response}")?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer a single request. Notifications get no response.
+    pub fn handle_line(&mut self, line: &str, clock: &dyn Clock) -> Option<String> {
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(error) => {
+                return Some(error_response(
+                    Value::Null,
+                    RpcError::Parse(error.to_string()),
+                ))
+            }
+        };
+        let request: Request = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(error) => {
+                return Some(error_response(
+                    Value::Null,
+                    RpcError::InvalidRequest(error.to_string()),
+                ))
+            }
+        };
+        if request.jsonrpc != "2.0" {
+            return Some(error_response(
+                request.id.unwrap_or(Value::Null),
+                RpcError::InvalidRequest("Only JSON-RPC 2.0 is supported.".to_string()),
+            ));
+        }
+
+        info!("RPC method {}", request.method);
+        let outcome = self.call(&request.method, request.params, clock);
+        let id = request.id?;
+        Some(match outcome {
+            Ok(result) => serialize(&Response {
+                jsonrpc: "2.0",
+                id,
+                result: Some(result),
+                error: None,
+            }),
+            Err(rpc_error) => error_response(id, rpc_error),
+        })
+    }
+
+    fn call(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+        clock: &dyn Clock,
+    ) -> Result<Value, RpcError> {
+        self.storage
+            .reload()
+            .map_err(|error| RpcError::Storage(error.to_string()))?;
+        match method {
+            "list" => Ok(to_value(&self.storage.get_review_topic_list())),
+            "today" => Ok(to_value(&self.storage.get_today_list(clock))),
+            "add" => {
+                let params: AddParams = parse_params(params)?;
+                if self
+                    .storage
+                    .find_review_topic(params.topic_name.clone())
+                    .is_some()
+                {
+                    return Err(RpcError::TopicExists(params.topic_name));
+                }
+                let mut review_topic = ReviewTopic::new(params.topic_name, clock);
+                review_topic.tags = params.tags;
+                review_topic.priority = params.priority;
+                self.storage.add_review_topic(review_topic.clone());
+                self.storage.write_storage();
+                Ok(to_value(&review_topic))
+            }
+            "remove" => {
+                let params: TopicParams = parse_params(params)?;
+                let review_topic = self.find(&params.topic_name)?;
+                self.storage.remove_review_topic(&review_topic);
+                self.storage.write_storage();
+                Ok(to_value(&review_topic))
+            }
+            "rename" => {
+                let params: RenameParams = parse_params(params)?;
+                if self
+                    .storage
+                    .find_review_topic(params.new_topic_name.clone())
+                    .is_some()
+                {
+                    return Err(RpcError::TopicExists(params.new_topic_name));
+                }
+                self.storage
+                    .rename_review_topic(params.topic_name, params.new_topic_name.clone())?;
+                self.storage.write_storage();
+                self.topic_result(&params.new_topic_name)
+            }
+            "edit" => {
+                let params: EditParams = parse_params(params)?;
+                self.find(&params.topic_name)?;
+                let topic_name = match params.new_topic_name {
+                    None => params.topic_name,
+                    Some(new_topic_name) => {
+                        if self
+                            .storage
+                            .find_review_topic(new_topic_name.clone())
+                            .is_some()
+                        {
+                            return Err(RpcError::TopicExists(new_topic_name));
+                        }
+                        self.storage
+                            .rename_review_topic(params.topic_name, new_topic_name.clone())?;
+                        new_topic_name
+                    }
+                };
+                self.storage
+                    .edit_topic(topic_name.clone(), params.tags, params.priority)?;
+                self.storage.write_storage();
+                self.topic_result(&topic_name)
+            }
+            "review" => {
+                let params: ReviewParams = parse_params(params)?;
+                self.storage
+                    .review_topic(params.topic_name.clone(), params.on, clock)?;
+                self.storage.write_storage();
+                self.topic_result(&params.topic_name)
+            }
+            "reschedule" => {
+                let params: RescheduleParams = parse_params(params)?;
+                match (params.reset, params.due, params.in_days) {
+                    (true, None, None) => {
+                        self.storage.reset_topic(params.topic_name.clone(), clock)?
+                    }
+                    (false, Some(due), None) => self
+                        .storage
+                        .reschedule_topic(params.topic_name.clone(), due)?,
+                    (false, None, Some(in_days)) => {
+                        let due = clock.today().checked_add_days(Days::new(in_days)).ok_or(
+                            RpcError::InvalidParams("The due day is too far away.".to_string()),
+                        )?;
+                        self.storage
+                            .reschedule_topic(params.topic_name.clone(), due)?
+                    }
+                    _ => {
+                        return Err(RpcError::InvalidParams(
+                            "Exactly one of due, in and reset has to be given.".to_string(),
+                        ))
+                    }
+                }
+                self.storage.write_storage();
+                self.topic_result(&params.topic_name)
+            }
+            "suspend" => self.set_state(params, TopicState::Suspended),
+            "bury" => self.set_state(params, TopicState::Buried(clock.today())),
+            "archive" => self.set_state(params, TopicState::Archived),
+            "activate" => self.set_state(params, TopicState::Active),
+            "pause" => {
+                let params: PauseParams = parse_params(params)?;
+                if params.until <= clock.today() {
+                    return Err(RpcError::InvalidDay(
+                        "The pause has to end after today.".to_string(),
+                    ));
+                }
+                self.storage.pause(params.until, clock);
+                self.storage.write_storage();
+                Ok(to_value(&self.storage.get_pause()))
+            }
+            "resume" => {
+                let paused_days = self.storage.resume(clock);
+                if paused_days.is_some() {
+                    self.storage.write_storage();
+                }
+                Ok(json!({ "paused_days": paused_days }))
+            }
+            "catch_up" => {
+                let params: CatchUpParams = parse_params(params)?;
+                let spread_count = self.storage.catch_up(params.days, clock);
+                self.storage.write_storage();
+                Ok(json!({ "spread_count": spread_count }))
+            }
+            "undo" => {
+                let params: StepsParams = parse_params(params)?;
+                self.walk_journal(params.steps, Storage::undo)
+            }
+            "redo" => {
+                let params: StepsParams = parse_params(params)?;
+                self.walk_journal(params.steps, Storage::redo)
+            }
+            "log" => {
+                let params: LogParams = parse_params(params)?;
+                let operations: Vec<String> = self
+                    .storage
+                    .get_journal()
+                    .recent()
+                    .take(params.limit)
+                    .map(|operation| operation.to_string())
+                    .collect();
+                Ok(to_value(&operations))
+            }
+            _ => Err(RpcError::MethodNotFound(method.to_string())),
+        }
+    }
+
+    fn find(&mut self, topic_name: &str) -> Result<ReviewTopic, RpcError> {
+        self.storage
+            .find_review_topic(topic_name.to_string())
+            .cloned()
+            .ok_or_else(|| RpcError::TopicNotFound(topic_name.to_string()))
+    }
+
+    fn topic_result(&mut self, topic_name: &str) -> Result<Value, RpcError> {
+        self.find(topic_name)
+            .map(|review_topic| to_value(&review_topic))
+    }
+
+    fn set_state(&mut self, params: Option<Value>, state: TopicState) -> Result<Value, RpcError> {
+        let params: TopicParams = parse_params(params)?;
+        self.storage
+            .set_topic_state(params.topic_name.clone(), state)?;
+        self.storage.write_storage();
+        self.topic_result(&params.topic_name)
+    }
+
+    /// Undo or redo up to `steps` operations, stopping at the first one that fails
+    fn walk_journal(
+        &mut self,
+        steps: usize,
+        step: fn(&mut Storage) -> Result<Operation, JournalError>,
+    ) -> Result<Value, RpcError> {
+        let mut operations = Vec::new();
+        let mut outcome = Ok(());
+        for _ in 0..steps {
+            match step(&mut self.storage) {
+                Ok(operation) => operations.push(operation.to_string()),
+                Err(journal_error) => {
+                    outcome = Err(journal_error);
+                    break;
+                }
+            }
+        }
+        if !operations.is_empty() {
+            self.storage.write_storage();
+        }
+        match (outcome, operations.is_empty()) {
+            (Err(journal_error), true) => Err(journal_error.into()),
+            _ => Ok(to_value(&operations)),
+        }
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Option<Value>) -> Result<T, RpcError> {
+    serde_json::from_value(params.unwrap_or_else(|| json!({})))
+        .map_err(|error| RpcError::InvalidParams(error.to_string()))
+}
+
+fn to_value<T: Serialize + ?Sized>(value: &T) -> Value {
+    serde_json::to_value(value).expect("Failed to serialize the result")
+}
+
+fn serialize(response: &Response) -> String {
+    match serde_json::to_string(response) {
+        Ok(serialized) => serialized,
+        Err(error_log) => {
+            error!("Serializing a response failed: {error_log}");
+            String::new()
+        }
+    }
+}
+
+fn error_response(id: Value, rpc_error: RpcError) -> String {
+    serialize(&Response {
+        jsonrpc: "2.0",
+        id,
+        result: None,
+        error: Some(json!({
+            "code": rpc_error.code(),
+            "message": rpc_error.to_string(),
+            "data": { "kind": rpc_error.kind() },
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::events::log_path;
+    use crate::io::journal::Journal;
+    use crate::io::rpc::RpcSession;
+    use crate::io::storage::{PreviousStorage, Storage};
+    use crate::topics::clock::FixedClock;
+    use crate::topics::review_topics::ReviewTopic;
+    use serde_json::{json, Value};
+    use serial_test::serial;
+    use std::fs;
+
+    static TEST_PATH: &str = "./data/test_rpc_storage.json";
+
+    fn session() -> RpcSession {
+        // Every call reads the storage file again, so the one of an earlier test has to go
+        let _ = fs::remove_file(TEST_PATH);
+        let _ = fs::remove_file(log_path(TEST_PATH));
+        let _ = fs::remove_file(Journal::path_for(TEST_PATH));
+        RpcSession::new(Storage::new(
+            PreviousStorage::No,
+            Some(TEST_PATH.to_string()),
+        ))
+    }
+
+    fn call(session: &mut RpcSession, request: Value) -> Value {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let response = session.handle_line(&request.to_string(), &clock).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[test]
+    #[serial]
+    fn test_methods() {
+        let mut session = session();
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "add", "params": {"topic_name": "a"}}),
+        );
+        assert_eq!(json!(1), response["id"]);
+        assert_eq!("a", response["result"]["topic_name"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "review", "params": {"topic_name": "a"}}),
+        );
+        assert_eq!("Week", response["result"]["next_review_gap"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": "three", "method": "undo"}),
+        );
+        assert_eq!(json!(["Review 'a'"]), response["result"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 4, "method": "list"}),
+        );
+        assert_eq!("Day", response["result"][0]["next_review_gap"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 5, "method": "edit", "params": {"topic_name": "a", "new_topic_name": "b", "tags": ["rust"], "priority": 2}}),
+        );
+        assert_eq!("b", response["result"]["topic_name"]);
+        assert_eq!(json!(["rust"]), response["result"]["tags"]);
+        assert_eq!(2, response["result"]["priority"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 6, "method": "edit", "params": {"topic_name": "b", "priority": 1}}),
+        );
+        assert_eq!(json!(["rust"]), response["result"]["tags"]);
+        assert_eq!(1, response["result"]["priority"]);
+    }
+
+    #[test]
+    #[serial]
+    fn test_errors() {
+        let mut session = session();
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+
+        let response: Value =
+            serde_json::from_str(&session.handle_line("{not json", &clock).unwrap()).unwrap();
+        assert_eq!(-32700, response["error"]["code"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "fly"}),
+        );
+        assert_eq!(-32601, response["error"]["code"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "review", "params": {}}),
+        );
+        assert_eq!("invalid_params", response["error"]["data"]["kind"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 3, "method": "review", "params": {"topic_name": "b"}}),
+        );
+        assert_eq!("topic_not_found", response["error"]["data"]["kind"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 4, "method": "redo"}),
+        );
+        assert_eq!("journal", response["error"]["data"]["kind"]);
+
+        // Notifications are handled without a response
+        let notification =
+            json!({"jsonrpc": "2.0", "method": "add", "params": {"topic_name": "c"}});
+        assert_eq!(None, session.handle_line(&notification.to_string(), &clock));
+        assert!(session.storage.find_review_topic("c".to_string()).is_some());
+
+        // A null id is still answered
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": null, "method": "fly"}),
+        );
+        assert_eq!(Value::Null, response["id"]);
+        assert_eq!(-32601, response["error"]["code"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 5, "method": "edit", "params": {"topic_name": "c", "new_topic_name": "c"}}),
+        );
+        assert_eq!("topic_exists", response["error"]["data"]["kind"]);
+
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 6, "method": "reschedule", "params": {"topic_name": "c", "in": u64::MAX}}),
+        );
+        assert_eq!("invalid_params", response["error"]["data"]["kind"]);
+    }
+
+    #[test]
+    #[serial]
+    fn test_changes_by_other_commands_are_kept() {
+        let mut session = session();
+        call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "add", "params": {"topic_name": "a"}}),
+        );
+
+        let mut storage = Storage::new(PreviousStorage::Yes, Some(TEST_PATH.to_string()));
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        storage.add_review_topic(ReviewTopic::new("x".to_string(), &clock));
+        storage.write_storage();
+
+        call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "add", "params": {"topic_name": "b"}}),
+        );
+        let response = call(
+            &mut session,
+            json!({"jsonrpc": "2.0", "id": 3, "method": "list"}),
+        );
+        let names: Vec<&str> = response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|topic| topic["topic_name"].as_str().unwrap())
+            .collect();
+        assert_eq!(vec!["a", "x", "b"], names);
+    }
+
+    #[test]
+    #[serial]
+    fn test_run_answers_every_line() {
+        let mut session = session();
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let input = "{\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"today\"}\n\n{\"jsonrpc\": \"2.0\", \"id\": 2, \"method\": \"log\"}\n";
+        let mut output = Vec::new();
+        session.run(input.as_bytes(), &mut output, &clock).unwrap();
+
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(json!([]), lines[1]["result"]);
+    }
+}
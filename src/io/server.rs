@@ -0,0 +1,306 @@
+//! A local HTTP server exposing the topics as a JSON API
+//!
+//! - `GET /topics` lists every topic
+//! - `GET /topics/today` lists today's topics
+//! - `POST /topics` with `{"topic_name": "...", "tags": [...], "priority": 0}` adds a topic
+//! - `PATCH /topics/<name>` with `{"topic_name": "..."}` renames a topic
+//! - `DELETE /topics/<name>` removes a topic
+//! - `POST /topics/<name>/reviews`, optionally with `{"on": "2024-05-01"}`, reviews a topic
+//!
+//! Errors are returned as `{"error": "<message>"}` with a matching status code.
+//!
+//! Requests other than `GET` need the content type `application/json`, requests sent from a
+//! web page need it to be served by this server, and every request has to be addressed to the
+//! server by a local name, so other sites cannot change the topics through the browser, even by
+//! pointing their own domain at this machine. The storage file is read again before every request, so the server sees the
+//! changes made by other revue commands while it runs.
+
+use crate::io::storage::{ReviewError, Storage};
+use crate::topics::clock::Clock;
+use crate::topics::review_topics::ReviewTopic;
+use chrono::NaiveDate;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::Cursor;
+use std::net::SocketAddr;
+use tiny_http::{Header, Method, Request, Response};
+
+type JsonResponse = Response<Cursor<Vec<u8>>>;
+
+#[derive(Deserialize)]
+struct NewTopic {
+    topic_name: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    priority: u8,
+}
+
+#[derive(Deserialize)]
+struct RenameTopic {
+    topic_name: String,
+}
+
+#[derive(Deserialize, Default)]
+struct NewReview {
+    #[serde(default)]
+    on: Option<NaiveDate>,
+}
+
+/// Serves the topics of a storage over HTTP, writing the storage after every change
+pub struct Server {
+    http: tiny_http::Server,
+    storage: Storage,
+}
+
+impl Server {
+    /// Listen on the given address, for example `127.0.0.1:8080`. Port 0 picks a free port.
+    pub fn bind(address: &str, storage: Storage) -> Result<Self, String> {
+        let http = tiny_http::Server::http(address).map_err(|error| error.to_string())?;
+        Ok(Server { http, storage })
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Answer requests until the process is stopped
+    pub fn run(&mut self, clock: &dyn Clock) {
+        loop {
+            let mut request = match self.http.recv() {
+                Ok(request) => request,
+                Err(error_log) => {
+                    error!("Receiving a request failed: {error_log}");
+                    continue;
+                }
+            };
+            info!("{} {}", request.method(), request.url());
+
+            let response = self.handle(&mut request, clock);
+            if let Err(error_log) = request.respond(response) {
+                error!("Sending a response failed: {error_log}");
+            }
+        }
+    }
+
+    fn handle(&mut self, request: &mut Request, clock: &dyn Clock) -> JsonResponse {
+        if !self.is_local_host(header(request, "Host")) {
+            return error_response(403, "Requests have to be addressed to a local host name.");
+        }
+        if let Some(origin) = header(request, "Origin") {
+            let own_origin = header(request, "Host").map(|host| format!("http://{host}"));
+            if own_origin.as_deref() != Some(origin) {
+                return error_response(403, "Requests from other sites are not allowed.");
+            }
+        }
+        if *request.method() != Method::Get && !is_json(request) {
+            return error_response(415, "The content type must be application/json.");
+        }
+        if let Err(error) = self.storage.reload() {
+            return error_response(500, &error.to_string());
+        }
+
+        let mut body = String::new();
+        if request.as_reader().read_to_string(&mut body).is_err() {
+            return error_response(400, "The request body is not valid UTF-8.");
+        }
+
+        let path = request.url().split('?').next().unwrap_or_default();
+        let segments: Option<Vec<String>> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(decode_segment)
+            .collect();
+        let segments = match segments {
+            None => return error_response(400, "The path is not correctly encoded."),
+            Some(segments) => segments,
+        };
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        match (request.method(), segments.as_slice()) {
+            (Method::Get, ["topics"]) => self.list_topics(),
+            (Method::Get, ["topics", "today"]) => {
+                json_response(200, &self.storage.get_today_list(clock))
+            }
+            (Method::Post, ["topics"]) => self.add_topic(&body, clock),
+            (Method::Patch, ["topics", topic_name]) => self.rename_topic(topic_name, &body),
+            (Method::Delete, ["topics", topic_name]) => self.remove_topic(topic_name),
+            (Method::Post, ["topics", topic_name, "reviews"]) => {
+                self.review_topic(topic_name, &body, clock)
+            }
+            _ => error_response(404, "There is nothing at this path."),
+        }
+    }
+
+    /// Whether the `Host` header names this server by a loopback address, which a page served
+    /// from another domain cannot send
+    fn is_local_host(&self, host: Option<&str>) -> bool {
+        let (Some(host), Some(address)) = (host, self.local_addr()) else {
+            return false;
+        };
+        let port = address.port();
+        host == address.to_string()
+            || ["127.0.0.1", "localhost", "[::1]"]
+                .iter()
+                .any(|name| host == format!("{name}:{port}"))
+    }
+
+    fn list_topics(&self) -> JsonResponse {
+        json_response(200, &self.storage.get_review_topic_list())
+    }
+
+    fn add_topic(&mut self, body: &str, clock: &dyn Clock) -> JsonResponse {
+        let new_topic: NewTopic = match serde_json::from_str(body) {
+            Ok(new_topic) => new_topic,
+            Err(error) => return error_response(400, &error.to_string()),
+        };
+        if self
+            .storage
+            .find_review_topic(new_topic.topic_name.clone())
+            .is_some()
+        {
+            return error_response(409, "A topic with this name already exists.");
+        }
+
+        let mut review_topic = ReviewTopic::new(new_topic.topic_name, clock);
+        review_topic.tags = new_topic.tags;
+        review_topic.priority = new_topic.priority;
+        self.storage.add_review_topic(review_topic.clone());
+        self.storage.write_storage();
+        json_response(201, &review_topic)
+    }
+
+    fn rename_topic(&mut self, topic_name: &str, body: &str) -> JsonResponse {
+        let rename: RenameTopic = match serde_json::from_str(body) {
+            Ok(rename) => rename,
+            Err(error) => return error_response(400, &error.to_string()),
+        };
+        if self
+            .storage
+            .find_review_topic(rename.topic_name.clone())
+            .is_some()
+        {
+            return error_response(409, "A topic with this name already exists.");
+        }
+
+        match self
+            .storage
+            .rename_review_topic(topic_name.to_string(), rename.topic_name.clone())
+        {
+            Err(not_found) => error_response(404, &not_found.to_string()),
+            Ok(_) => {
+                self.storage.write_storage();
+                self.topic_response(200, &rename.topic_name)
+            }
+        }
+    }
+
+    fn remove_topic(&mut self, topic_name: &str) -> JsonResponse {
+        match self
+            .storage
+            .find_review_topic(topic_name.to_string())
+            .cloned()
+        {
+            None => error_response(404, "Review topic was not found."),
+            Some(review_topic) => {
+                self.storage.remove_review_topic(&review_topic);
+                self.storage.write_storage();
+                json_response(200, &review_topic)
+            }
+        }
+    }
+
+    fn review_topic(&mut self, topic_name: &str, body: &str, clock: &dyn Clock) -> JsonResponse {
+        let new_review: NewReview = match body.trim().is_empty() {
+            true => NewReview::default(),
+            false => match serde_json::from_str(body) {
+                Ok(new_review) => new_review,
+                Err(error) => return error_response(400, &error.to_string()),
+            },
+        };
+
+        match self
+            .storage
+            .review_topic(topic_name.to_string(), new_review.on, clock)
+        {
+            Err(review_error @ ReviewError::NotFound(_)) => {
+                error_response(404, &review_error.to_string())
+            }
+            Err(review_error) => error_response(422, &review_error.to_string()),
+            Ok(_) => {
+                self.storage.write_storage();
+                self.topic_response(200, topic_name)
+            }
+        }
+    }
+
+    fn topic_response(&mut self, status: u16, topic_name: &str) -> JsonResponse {
+        match self.storage.find_review_topic(topic_name.to_string()) {
+            None => error_response(404, "Review topic was not found."),
+            Some(review_topic) => json_response(status, review_topic),
+        }
+    }
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Whether the request body is declared as JSON, which HTML forms cannot send
+fn is_json(request: &Request) -> bool {
+    header(request, "Content-Type")
+        .and_then(|content_type| content_type.split(';').next())
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("application/json"))
+}
+
+fn json_response<T: Serialize + ?Sized>(status: u16, body: &T) -> JsonResponse {
+    let serialized = serde_json::to_vec(body).expect("Failed to serialize the response");
+    let content_type = Header::from_bytes("Content-Type", "application/json")
+        .expect("The content type header is valid");
+    Response::from_data(serialized)
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+fn error_response(status: u16, message: &str) -> JsonResponse {
+    json_response(status, &json!({ "error": message }))
+}
+
+/// Decode the `%XX` escapes of a path segment, so topic names can contain spaces and slashes
+fn decode_segment(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match byte {
+            b'%' => {
+                let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::server::decode_segment;
+
+    #[test]
+    fn test_decode_segment() {
+        assert_eq!(Some("a b/c".to_string()), decode_segment("a%20b%2Fc"));
+        assert_eq!(Some("Größe".to_string()), decode_segment("Gr%C3%B6%C3%9Fe"));
+        assert_eq!(None, decode_segment("100%"));
+        assert_eq!(None, decode_segment("%zz"));
+    }
+}
@@ -0,0 +1,145 @@
+//! A one-line summary of the reviews, for shell prompts and status bars
+//!
+//! The summary is worked out from the storage document alone, without the journal or any table
+//! rendering, so it is cheap enough to run on every prompt.
+
+use crate::io::encryption::Passphrase;
+use crate::io::events::{load_document, EventError};
+use crate::io::storage::StorageDocument;
+use crate::topics::clock::Clock;
+use crate::topics::review_topics::{ReviewTopic, TopicState};
+use chrono::NaiveDate;
+use std::io;
+
+/// The format used when none is given
+pub const DEFAULT_STATUS_FORMAT: &str = "{due} due";
+
+/// The topic to review next
+#[derive(Debug, PartialEq, Eq)]
+pub struct NextTopic {
+    pub topic_name: String,
+    pub review_day: NaiveDate,
+    /// Negative when the topic is overdue
+    pub days_until_review: i64,
+}
+
+/// How many topics are due, and which one is next
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct Status {
+    /// Topics due today, including the overdue ones
+    pub due: usize,
+    pub overdue: usize,
+    pub next: Option<NextTopic>,
+}
+
+impl Status {
+    /// The status of the topics in a storage document. Nothing is due while the reviews are paused.
+    pub fn of(document: &StorageDocument, clock: &dyn Clock) -> Self {
+        if document.is_paused() {
+            return Status::default();
+        }
+
+        let active: Vec<&ReviewTopic> = document
+            .review_topics()
+            .iter()
+            .filter(|topic| topic.current_state(clock) == TopicState::Active)
+            .collect();
+        let next = active
+            .iter()
+            .min_by(|topic, other| topic.cmp_due(other, clock))
+            .map(|topic| NextTopic {
+                topic_name: topic.topic_name.clone(),
+                review_day: topic.review_day(clock),
+                days_until_review: topic.days_until_review(clock),
+            });
+
+        Status {
+            due: active
+                .iter()
+                .filter(|topic| topic.is_time_to_review(clock))
+                .count(),
+            overdue: active
+                .iter()
+                .filter(|topic| topic.days_until_review(clock) < 0)
+                .count(),
+            next,
+        }
+    }
+
+    /// Fill in a format string
+    ///
+    /// `{due}`, `{overdue}`, `{next}` (the name of the next topic), `{next_day}` and `{next_in}`
+    /// (days until the next topic is due) are replaced. The `{next...}` placeholders are empty
+    /// when there are no topics.
+    pub fn format(&self, format: &str) -> String {
+        let (next, next_day, next_in) = match &self.next {
+            None => (String::new(), String::new(), String::new()),
+            Some(next) => (
+                next.topic_name.clone(),
+                next.review_day.to_string(),
+                next.days_until_review.to_string(),
+            ),
+        };
+        format
+            .replace("{due}", &self.due.to_string())
+            .replace("{overdue}", &self.overdue.to_string())
+            .replace("{next_day}", &next_day)
+            .replace("{next_in}", &next_in)
+            .replace("{next}", &next)
+    }
+}
+
+/// Read only the storage document, treating a missing storage file as one without topics
+pub fn read_document(
+    storage_file_path: &str,
+    passphrase: Option<&Passphrase>,
+) -> Result<StorageDocument, String> {
+    match load_document(storage_file_path, passphrase) {
+        Ok(document) => Ok(document),
+        Err(EventError::Io(error)) if error.kind() == io::ErrorKind::NotFound => {
+            Ok(StorageDocument::default())
+        }
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::status::{Status, DEFAULT_STATUS_FORMAT};
+    use crate::io::storage::StorageDocument;
+    use crate::topics::clock::FixedClock;
+    use crate::topics::review_topics::{ReviewTopic, TopicState};
+    use serde_json::json;
+
+    fn document(topics: Vec<ReviewTopic>) -> StorageDocument {
+        StorageDocument::from_value(json!({ "review_topic_list": topics })).unwrap()
+    }
+
+    #[test]
+    fn test_status() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let overdue = ReviewTopic::new("overdue".to_string(), &clock.before_days(3));
+        let due = ReviewTopic::new("due".to_string(), &clock.before_days(1));
+        let upcoming = ReviewTopic::new("upcoming".to_string(), &clock);
+        let mut suspended = ReviewTopic::new("suspended".to_string(), &clock.before_days(9));
+        suspended.state = TopicState::Suspended;
+        let status = Status::of(&document(vec![upcoming, due, overdue, suspended]), &clock);
+
+        assert_eq!(2, status.due);
+        assert_eq!(1, status.overdue);
+        assert_eq!(
+            "2 due, 1 overdue, next overdue on 2024-05-08 (-2)",
+            status.format("{due} due, {overdue} overdue, next {next} on {next_day} ({next_in})")
+        );
+    }
+
+    #[test]
+    fn test_no_topics() {
+        let status = Status::of(
+            &document(Vec::new()),
+            &FixedClock::utc("2024-05-10T12:00:00Z"),
+        );
+        assert_eq!("0 due", status.format(DEFAULT_STATUS_FORMAT));
+        assert_eq!("[]", status.format("[{next}]"));
+    }
+}
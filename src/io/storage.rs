@@ -1,17 +1,185 @@
+//! Keeping the topics and settings in a file
+
 use log::{debug, error, info, warn};
 
-use crate::topics::review_topics::ReviewTopic;
+use crate::io::backup::{
+    rotate_snapshots, snapshot_before_write, take_snapshot, BackupError, BackupSettings, Snapshot,
+};
+use crate::io::config::Config;
+use crate::io::encryption::{is_encrypted_file, read_file, write_file, Passphrase};
+use crate::io::events::{
+    append_to_log, archive_log, load_document, read_log, replay, Event, EventError, EventRecord,
+    COMPACT_AFTER_EVENTS,
+};
+use crate::io::hooks::{run_pending_hooks, HookEvent, PendingHook};
+use crate::io::journal::{Journal, JournalError, Operation};
+use crate::io::sync::{commit, commit_message, is_initialized};
+use crate::topics::calendar::Calendar;
+use crate::topics::clock::{Clock, SystemClock};
+use crate::topics::review_topics::{id_from_name, ReviewDateError, ReviewTopic, TopicState};
+use crate::topics::scheduling::{
+    get_today_list, shift_schedules, spread_overdue, Pause, ScheduleSettings,
+};
+use chrono::{NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fmt::Formatter;
+
+/// Where the storage is kept when no other file path is given
+pub const DEFAULT_STORAGE_PATH: &str = "./data/storage.json";
+
+/// Version of the storage file format written by this version of revue
+const FORMAT_VERSION: u32 = 2;
 
+/// The topics and settings, as they are written to the storage file
 #[derive(Default, Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct Storage {
+pub struct StorageDocument {
+    #[serde(default)]
+    format_version: u32,
     review_topic_list: Vec<ReviewTopic>,
+    #[serde(default)]
+    schedule_settings: ScheduleSettings,
+    #[serde(default)]
+    pause: Option<Pause>,
+    #[serde(default)]
+    calendar: Calendar,
+    #[serde(default)]
+    backup_settings: BackupSettings,
+    /// The sequence number of the last event the topics include
+    #[serde(default)]
+    compacted_through: u64,
+}
+
+impl StorageDocument {
+    /// Read a storage file, migrating it from older formats
+    pub fn from_json(serialized: &str) -> Result<Self, serde_json::Error> {
+        Self::from_value(serde_json::from_str(serialized)?)
+    }
+
+    /// Read a storage file that was already parsed as JSON, migrating it from older formats
+    pub fn from_value(mut document: Value) -> Result<Self, serde_json::Error> {
+        migrate(&mut document);
+        serde_json::from_value(document)
+    }
+
+    /// The topics in the order they are stored in
+    pub fn review_topics(&self) -> &[ReviewTopic] {
+        &self.review_topic_list
+    }
+
+    /// Whether the reviews are paused
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_some()
+    }
+
+    pub fn calendar(&self) -> &Calendar {
+        &self.calendar
+    }
+
+    pub fn set_review_topics(&mut self, review_topic_list: Vec<ReviewTopic>) {
+        self.review_topic_list = review_topic_list;
+    }
+
+    pub fn compacted_through(&self) -> u64 {
+        self.compacted_through
+    }
+
+    pub fn set_compacted_through(&mut self, compacted_through: u64) {
+        self.compacted_through = compacted_through;
+    }
+}
+
+/// Bring a storage file written by an older version of revue up to the current format
+fn migrate(document: &mut Value) {
+    let Some(fields) = document.as_object_mut() else {
+        return;
+    };
+    let format_version = fields
+        .get("format_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+
+    if format_version < 1 {
+        // Version 0 recorded where the file was written, which went stale once the file was moved
+        info!("Dropping the storage file path recorded in the storage");
+        fields.remove("storage_file_path");
+    }
+    // Topics from before version 2, or added to the file by hand, have no id yet
+    let topics = fields
+        .get_mut("review_topic_list")
+        .and_then(Value::as_array_mut);
+    for topic in topics.into_iter().flatten() {
+        let Some(topic) = topic.as_object_mut() else {
+            continue;
+        };
+        if topic.contains_key("id") {
+            continue;
+        }
+        if let Some(topic_name) = topic.get("topic_name").and_then(Value::as_str) {
+            let id = id_from_name(topic_name);
+            topic.insert("id".to_string(), Value::from(id));
+        }
+    }
+    fields.insert("format_version".to_string(), Value::from(FORMAT_VERSION));
+}
+
+/// The storage document together with where it is kept
+///
+/// The file path only lives here, so a storage loaded from a path is always written back to it.
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct Storage {
+    document: StorageDocument,
     storage_file_path: String,
+    /// Kept in its own file next to the storage
+    journal: Journal,
+    /// Changes whose hooks run once they are written
+    pending_hooks: Vec<PendingHook>,
+    /// Changes to append to the event log once they are written
+    pending_events: Vec<Event>,
+    /// The document as the storage file and the event log have it, `None` when that is unknown
+    written: Option<StorageDocument>,
+    /// The passphrase the storage file and its journal are encrypted with, `None` when they are not
+    passphrase: Option<Passphrase>,
+    /// Settings from the configuration, which take precedence over the ones in the document
+    config: Config,
+}
+
+/// Returned when a topic that is looked up by name is not in the storage
+#[derive(Debug, PartialEq, Eq)]
+pub struct TopicNotFound(pub String);
+
+impl fmt::Display for TopicNotFound {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Review topic '{}' was not found.", self.0)
+    }
+}
+
+impl std::error::Error for TopicNotFound {}
+
+/// Returned by [`Storage::review_topic`] when the review could not be logged
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReviewError {
+    NotFound(TopicNotFound),
+    InvalidDay(ReviewDateError),
+}
+
+impl fmt::Display for ReviewError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReviewError::NotFound(not_found) => write!(f, "{not_found}"),
+            ReviewError::InvalidDay(review_date_error) => write!(f, "{review_date_error}"),
+        }
+    }
 }
 
+impl std::error::Error for ReviewError {}
+
+/// Whether [`Storage::new`] loads the storage already written at its file path
 pub enum PreviousStorage {
+    /// Load the storage file if there is one
     Yes,
-    #[allow(dead_code)]
+    /// Start with an empty storage
     No,
 }
 
@@ -29,11 +197,36 @@ impl Storage {
     ///
     /// This will create a Storage using the previous storage data found in the default location.
     /// ```
-    /// let storage = Storage::new(PreviousStorage::Yes, None)
+    /// use revue::{PreviousStorage, Storage};
+    ///
+    /// let storage = Storage::new(PreviousStorage::Yes, None);
+    /// assert_eq!("./data/storage.json", storage.get_storage_file_path());
+    /// ```
+    ///
+    /// This will create an empty Storage, which replaces the file at the given path once written.
+    /// ```
+    /// use revue::{PreviousStorage, ReviewTopic, Storage, SystemClock};
+    ///
+    /// let clock = SystemClock::default();
+    /// let mut storage = Storage::new(PreviousStorage::No, Some("./data/example.json".to_string()));
+    /// storage.add_review_topic(ReviewTopic::new("Lifetimes".to_string(), &clock));
+    /// assert!(storage.get_today_list(&clock).is_empty());
+    /// assert_eq!(1, storage.get_review_topic_list().len());
     /// ```
     pub fn new(find_prev_storage: PreviousStorage, file_path: Option<String>) -> Self {
+        Storage::with_passphrase(find_prev_storage, file_path, None)
+    }
+
+    /// Like [`Storage::new`], for a storage file encrypted with the passphrase
+    ///
+    /// A new storage is encrypted from its first write on when there is a passphrase.
+    pub fn with_passphrase(
+        find_prev_storage: PreviousStorage,
+        file_path: Option<String>,
+        passphrase: Option<Passphrase>,
+    ) -> Self {
         let review_topic_list: Vec<ReviewTopic> = Vec::new();
-        let storage_file_path = file_path.unwrap_or_else(|| "./data/storage.json".to_string());
+        let storage_file_path = file_path.unwrap_or_else(|| DEFAULT_STORAGE_PATH.to_string());
 
         match find_prev_storage {
             PreviousStorage::Yes => {
@@ -42,7 +235,7 @@ impl Storage {
 
                 if previous_storage_found {
                     info!("Prior storage found");
-                    return get_previous_storage(&storage_file_path);
+                    return get_previous_storage(&storage_file_path, passphrase);
                 }
                 info!("Prior storage not found");
             }
@@ -50,41 +243,242 @@ impl Storage {
         }
 
         Storage {
-            review_topic_list,
+            document: StorageDocument {
+                format_version: FORMAT_VERSION,
+                review_topic_list,
+                ..Default::default()
+            },
             storage_file_path,
+            journal: Default::default(),
+            pending_hooks: Vec::new(),
+            pending_events: Vec::new(),
+            written: None,
+            passphrase,
+            config: Config::default(),
         }
     }
 
-    pub fn review_topic(&mut self, topic_name: String) {
-        let mut review_list = self.get_review_topic_list();
-        let topic_index = review_list
-            .iter()
-            .position(|topic| topic.topic_name == topic_name);
-
-        match topic_index {
-            None => {
-                println!("Review topic was not found. Did you misspell?")
-            }
-            Some(topic_index) => {
-                let mut review_topic = review_list.remove(topic_index);
-                review_topic.review();
-                review_list.push(review_topic);
-                self.set_review_topic_list(review_list);
-                self.write_storage();
-            }
+    /// Read the storage file again, to pick up the changes other revue commands wrote to it
+    ///
+    /// The topics are kept as they are when there is no storage file yet.
+    pub fn reload(&mut self) -> Result<(), EventError> {
+        if !find_previous_storage(&self.storage_file_path) {
+            return Ok(());
+        }
+        let document = load_document(&self.storage_file_path, self.passphrase.as_ref())?;
+        if self.written.as_ref() == Some(&document) {
+            return Ok(());
         }
+        info!("The storage file changed, reloading it");
+        self.document = document;
+        self.journal = Journal::load(
+            &Journal::path_for(&self.storage_file_path),
+            self.passphrase.as_ref(),
+        );
+        // Written in full on the next write, as the file may be in an older format
+        self.written = None;
+        Ok(())
+    }
+
+    /// Mark a topic as reviewed today, or on an earlier day when `reviewed_on` is given
+    pub fn review_topic(
+        &mut self,
+        topic_name: String,
+        reviewed_on: Option<NaiveDate>,
+        clock: &dyn Clock,
+    ) -> Result<(), ReviewError> {
+        let scheduler = self.get_effective_schedule_settings().scheduler;
+        let review_topic = match self
+            .document
+            .review_topic_list
+            .iter_mut()
+            .find(|topic| topic.topic_name == topic_name)
+        {
+            None => return Err(ReviewError::NotFound(TopicNotFound(topic_name))),
+            Some(review_topic) => review_topic,
+        };
+
+        let before = review_topic.clone();
+        review_topic
+            .review_with(reviewed_on, scheduler, clock)
+            .map_err(ReviewError::InvalidDay)?;
+        self.pending_hooks.push(PendingHook {
+            event: HookEvent::PostReview,
+            topic: review_topic.clone(),
+            previous_name: None,
+        });
+        self.pending_events.push(Event::TopicReviewed {
+            topic: review_topic.clone(),
+        });
+        self.journal.record(Operation::Review {
+            before,
+            after: review_topic.clone(),
+        });
+        Ok(())
     }
 
+    /// A copy of every topic, in the order they are stored in
     pub fn get_review_topic_list(&self) -> Vec<ReviewTopic> {
-        self.review_topic_list.clone()
+        self.document.review_topic_list.clone()
     }
 
+    /// Replace every topic
     pub fn set_review_topic_list(&mut self, review_topic_list: Vec<ReviewTopic>) {
-        self.review_topic_list = review_topic_list;
+        self.document.review_topic_list = review_topic_list;
+    }
+
+    /// Get the topics due today, with the daily limits and review order applied
+    ///
+    /// Nothing is due while the reviews are paused.
+    pub fn get_today_list(&self, clock: &dyn Clock) -> Vec<ReviewTopic> {
+        if self.document.pause.is_some() {
+            return Vec::new();
+        }
+        get_today_list(
+            &self.document.review_topic_list,
+            &self.get_effective_schedule_settings(),
+            clock,
+        )
+    }
+
+    /// The current pause, if the reviews are paused
+    pub fn get_pause(&self) -> Option<&Pause> {
+        self.document.pause.as_ref()
+    }
+
+    /// Pause the reviews until the given day. Pausing again only changes the end of the pause.
+    pub fn pause(&mut self, until: NaiveDate, clock: &dyn Clock) {
+        let since = match &self.document.pause {
+            None => clock.today(),
+            Some(pause) => pause.since,
+        };
+        info!("Pausing reviews until {until}");
+        self.document.pause = Some(Pause { since, until });
+    }
+
+    /// End the pause and move every schedule forward by the days spent paused
+    ///
+    /// returns: The number of days the reviews were paused for, or `None` if they were not paused
+    pub fn resume(&mut self, clock: &dyn Clock) -> Option<u64> {
+        let pause = self.document.pause.take()?;
+        info!("Resuming reviews");
+        shift_schedules(&mut self.document.review_topic_list, &pause, clock);
+        Some(pause.days_paused(clock.today()))
+    }
+
+    /// Resume the reviews if the pause has reached its end
+    pub fn resume_if_pause_over(&mut self, clock: &dyn Clock) -> Option<u64> {
+        match &self.document.pause {
+            Some(pause) if pause.is_over(clock.today()) => self.resume(clock),
+            _ => None,
+        }
+    }
+
+    /// Spread the overdue topics over the next `days` days instead of having them all due today
+    ///
+    /// returns: The number of overdue topics that were spread
+    pub fn catch_up(&mut self, days: u64, clock: &dyn Clock) -> usize {
+        info!("Spreading overdue topics over {days} days");
+        let review_order = self.get_effective_schedule_settings().review_order;
+        spread_overdue(
+            &mut self.document.review_topic_list,
+            days,
+            review_order,
+            clock,
+        )
+    }
+
+    /// The timezone and day rollover hour the days are counted with
+    pub fn get_calendar(&self) -> &Calendar {
+        &self.document.calendar
+    }
+
+    /// Change how points in time are turned into days
+    pub fn set_calendar(&mut self, calendar: Calendar) {
+        self.document.calendar = calendar;
+    }
+
+    /// The calendar in use, which is the one kept in the storage unless the config overrides it
+    pub fn get_effective_calendar(&self) -> Calendar {
+        self.config.apply_to_calendar(&self.document.calendar)
+    }
+
+    /// Use settings from the configuration instead of the ones kept in the storage, without
+    /// writing them to the storage file
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    /// How many snapshots of the storage file are kept
+    pub fn get_backup_settings(&self) -> &BackupSettings {
+        &self.document.backup_settings
+    }
+
+    /// Change how many snapshots of the storage file are kept
+    pub fn set_backup_settings(&mut self, backup_settings: BackupSettings) {
+        self.document.backup_settings = backup_settings;
+    }
+
+    /// Whether the storage file is encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.passphrase.is_some()
+    }
+
+    /// The passphrase the storage file is encrypted with
+    pub fn get_passphrase(&self) -> Option<&Passphrase> {
+        self.passphrase.as_ref()
+    }
+
+    /// Encrypt the storage file with the passphrase from the next write on, or stop encrypting
+    /// it with `None`
+    pub fn set_passphrase(&mut self, passphrase: Option<Passphrase>) {
+        self.passphrase = passphrase;
+        // The whole file is written again in the new form
+        self.written = None;
+    }
+
+    /// Where the storage is read from and written to
+    pub fn get_storage_file_path(&self) -> &str {
+        &self.storage_file_path
+    }
+
+    /// Take a snapshot of the storage file as it is on disk, replacing today's snapshot
+    pub fn backup_now(&self) -> Result<Option<Snapshot>, BackupError> {
+        let snapshot = take_snapshot(
+            &self.storage_file_path,
+            self.snapshot_day(),
+            self.passphrase.as_ref(),
+        )?;
+        rotate_snapshots(&self.storage_file_path, &self.document.backup_settings);
+        Ok(snapshot)
+    }
+
+    /// Snapshots are dated by the real day, even when simulating another one with `--now`
+    fn snapshot_day(&self) -> NaiveDate {
+        SystemClock::new(self.get_effective_calendar()).today()
+    }
+
+    /// The daily limits and the order of today's topics
+    pub fn get_schedule_settings(&self) -> &ScheduleSettings {
+        &self.document.schedule_settings
     }
 
+    /// The schedule settings in use, which are the ones kept in the storage unless the config
+    /// overrides them
+    pub fn get_effective_schedule_settings(&self) -> ScheduleSettings {
+        self.config
+            .apply_to_schedule_settings(&self.document.schedule_settings)
+    }
+
+    /// Change the daily limits and the order of today's topics
+    pub fn set_schedule_settings(&mut self, schedule_settings: ScheduleSettings) {
+        self.document.schedule_settings = schedule_settings;
+    }
+
+    /// Add a topic, unless there already is a topic with the same name
     pub fn add_review_topic(&mut self, review_topic: ReviewTopic) {
         if let Some(_duplicate_found) = self
+            .document
             .review_topic_list
             .iter()
             .find(|topic| topic.topic_name == review_topic.topic_name)
@@ -94,77 +488,348 @@ impl Storage {
         }
 
         info!("Adding topic to list");
-        self.review_topic_list.push(review_topic);
-
-        self.review_topic_list.sort();
+        self.journal.record(Operation::Add {
+            topic: review_topic.clone(),
+        });
+        self.pending_hooks.push(PendingHook {
+            event: HookEvent::PostAdd,
+            topic: review_topic.clone(),
+            previous_name: None,
+        });
+        self.pending_events.push(Event::TopicAdded {
+            topic: review_topic.clone(),
+        });
+        self.document.review_topic_list.push(review_topic);
     }
 
+    /// Remove the topic with the same name as the given one
     pub fn remove_review_topic(&mut self, review_topic: &ReviewTopic) {
         if let Some(topic_index) = self
+            .document
             .review_topic_list
             .iter()
             .position(|topic| topic.topic_name == review_topic.topic_name)
         {
             info!("Removing topic from list");
-            self.review_topic_list.remove(topic_index);
+            let topic = self.document.review_topic_list.remove(topic_index);
+            self.pending_hooks.push(PendingHook {
+                event: HookEvent::PostRemove,
+                topic: topic.clone(),
+                previous_name: None,
+            });
+            self.pending_events.push(Event::TopicRemoved {
+                id: topic.id.clone(),
+                topic_name: topic.topic_name.clone(),
+            });
+            self.journal.record(Operation::Remove { topic });
         }
     }
 
+    /// The topic with the given name
     pub fn find_review_topic(&mut self, review_topic_name: String) -> Option<&ReviewTopic> {
         if let Some(topic_index) = self
+            .document
             .review_topic_list
             .iter()
             .position(|topic| topic.topic_name == review_topic_name)
         {
             info!("Topic was found");
-            return self.review_topic_list.get(topic_index);
+            return self.document.review_topic_list.get(topic_index);
         }
         info!("Topic not found");
         None
     }
 
+    /// Give a topic a new name
     pub fn rename_review_topic(
         &mut self,
         review_topic_name: String,
         new_name: String,
-    ) -> Result<(), ()> {
+    ) -> Result<(), TopicNotFound> {
         let topic_index = self
+            .document
             .review_topic_list
             .iter()
             .position(|topic| topic.topic_name == review_topic_name);
         match topic_index {
-            None => Err(()),
+            None => Err(TopicNotFound(review_topic_name)),
             Some(index) => {
-                let mut review_topic = self.review_topic_list.remove(index);
+                let mut review_topic = self.document.review_topic_list.remove(index);
+                self.journal.record(Operation::Rename {
+                    from: review_topic_name.clone(),
+                    to: new_name.clone(),
+                });
+                self.pending_events.push(Event::TopicRenamed {
+                    id: review_topic.id.clone(),
+                    from: review_topic_name.clone(),
+                    to: new_name.clone(),
+                });
                 review_topic.topic_name = new_name;
+                self.pending_hooks.push(PendingHook {
+                    event: HookEvent::PostRename,
+                    topic: review_topic.clone(),
+                    previous_name: Some(review_topic_name),
+                });
                 info!("Topic renamed");
-                self.review_topic_list.push(review_topic);
+                self.document.review_topic_list.push(review_topic);
                 Ok(())
             }
         }
     }
 
-    pub fn write_storage(&self) {
-        let serialized: String = match serde_json::to_string_pretty(self) {
+    /// Replace the tags or the priority of a topic, leaving the ones not given as they are
+    pub fn edit_topic(
+        &mut self,
+        review_topic_name: String,
+        tags: Option<Vec<String>>,
+        priority: Option<u8>,
+    ) -> Result<(), TopicNotFound> {
+        match self
+            .document
+            .review_topic_list
+            .iter_mut()
+            .find(|topic| topic.topic_name == review_topic_name)
+        {
+            None => Err(TopicNotFound(review_topic_name)),
+            Some(_) if tags.is_none() && priority.is_none() => Ok(()),
+            Some(review_topic) => {
+                let before = review_topic.clone();
+                if let Some(tags) = tags {
+                    review_topic.tags = tags;
+                }
+                if let Some(priority) = priority {
+                    review_topic.priority = priority;
+                }
+                info!("Topic edited");
+                self.journal.record(Operation::Edit {
+                    before,
+                    after: review_topic.clone(),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Suspend, bury, archive or activate a topic
+    pub fn set_topic_state(
+        &mut self,
+        review_topic_name: String,
+        state: TopicState,
+    ) -> Result<(), TopicNotFound> {
+        match self
+            .document
+            .review_topic_list
+            .iter_mut()
+            .find(|topic| topic.topic_name == review_topic_name)
+        {
+            None => Err(TopicNotFound(review_topic_name)),
+            Some(review_topic) => {
+                info!("Topic state changed to {state}");
+                review_topic.state = state;
+                Ok(())
+            }
+        }
+    }
+
+    /// Make a topic due on the given day
+    pub fn reschedule_topic(
+        &mut self,
+        review_topic_name: String,
+        review_day: NaiveDate,
+    ) -> Result<(), TopicNotFound> {
+        match self
+            .document
+            .review_topic_list
+            .iter_mut()
+            .find(|topic| topic.topic_name == review_topic_name)
+        {
+            None => Err(TopicNotFound(review_topic_name)),
+            Some(review_topic) => {
+                info!("Topic rescheduled to {review_day}");
+                review_topic.reschedule(review_day);
+                Ok(())
+            }
+        }
+    }
+
+    /// Send a topic back to the first review gap
+    pub fn reset_topic(
+        &mut self,
+        review_topic_name: String,
+        clock: &dyn Clock,
+    ) -> Result<(), TopicNotFound> {
+        match self
+            .document
+            .review_topic_list
+            .iter_mut()
+            .find(|topic| topic.topic_name == review_topic_name)
+        {
+            None => Err(TopicNotFound(review_topic_name)),
+            Some(review_topic) => {
+                info!("Topic reset to the first review gap");
+                review_topic.reset(clock);
+                Ok(())
+            }
+        }
+    }
+
+    /// Replace every topic with the result of merging another storage file, so the merge can
+    /// be undone
+    pub fn merge_review_topics(&mut self, other: String, merged: Vec<ReviewTopic>) {
+        info!("Topics of {other} merged");
+        self.journal.record(Operation::Merge {
+            other,
+            before: self.document.review_topic_list.clone(),
+            after: merged.clone(),
+        });
+        self.document.review_topic_list = merged;
+    }
+
+    /// Revert the most recent add, remove, rename, review, edit or merge
+    pub fn undo(&mut self) -> Result<Operation, JournalError> {
+        info!("Undoing the last operation");
+        self.journal.undo(&mut self.document.review_topic_list)
+    }
+
+    /// Apply the most recently undone operation again
+    pub fn redo(&mut self) -> Result<Operation, JournalError> {
+        info!("Redoing the last undone operation");
+        self.journal.redo(&mut self.document.review_topic_list)
+    }
+
+    /// The operations that can be undone, most recent first
+    pub fn get_journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Write the storage and its journal, after taking the day's snapshot
+    ///
+    /// Changes that can be told as events are appended to the event log. Anything else, or a log
+    /// that grew long, writes the whole storage file and archives the log.
+    pub fn write_storage(&mut self) {
+        info!("Attempting to write to storage");
+        warn!("Program crashes if directory does not exist");
+        if let Some(path) = Path::new(&self.storage_file_path).parent() {
+            check_if_directory_exists(path);
+        }
+
+        snapshot_before_write(
+            &self.storage_file_path,
+            self.snapshot_day(),
+            &self.document.backup_settings,
+            self.passphrase.as_ref(),
+        );
+
+        let now = Utc::now();
+        let first_sequence = self.document.compacted_through + 1;
+        let records: Vec<EventRecord> = std::mem::take(&mut self.pending_events)
+            .into_iter()
+            .zip(first_sequence..)
+            .map(|(event, sequence)| EventRecord {
+                at: now,
+                sequence,
+                event,
+            })
+            .collect();
+        self.document.compacted_through += records.len() as u64;
+        let written = match self.can_append(&records) {
+            true => self.append_events(&records),
+            false => self.write_document(&records),
+        };
+        if !written {
+            return;
+        }
+        self.written = Some(self.document.clone());
+        self.journal.write(
+            &Journal::path_for(&self.storage_file_path),
+            self.passphrase.as_ref(),
+        );
+
+        let pending_hooks = std::mem::take(&mut self.pending_hooks);
+        if let Err(sync_error) = commit(&self.storage_file_path, &commit_message(&pending_hooks)) {
+            error!("Could not commit the storage: {sync_error}");
+        }
+        for hook_error in
+            run_pending_hooks(&self.storage_file_path, &self.config.hooks, &pending_hooks)
+        {
+            error!("{hook_error}");
+        }
+    }
+
+    /// Whether appending the events to the log gives the document that is to be written
+    fn can_append(&self, records: &[EventRecord]) -> bool {
+        let Some(written) = &self.written else {
+            return false;
+        };
+        // The log would keep the topics of an encrypted storage in plaintext
+        if self.is_encrypted() {
+            return false;
+        }
+        // Git and the other tools reading the storage file itself need every change in it
+        if !Path::new(&self.storage_file_path).exists() || is_initialized(&self.storage_file_path) {
+            return false;
+        }
+        let Ok(log) = read_log(&self.storage_file_path) else {
+            return false;
+        };
+        if log.len() + records.len() >= COMPACT_AFTER_EVENTS {
+            return false;
+        }
+        let mut replayed = written.clone();
+        replay(&mut replayed, records);
+        replayed == self.document
+    }
+
+    fn append_events(&self, records: &[EventRecord]) -> bool {
+        if records.is_empty() {
+            info!("Nothing to write");
+            return true;
+        }
+        match append_to_log(&self.storage_file_path, records) {
+            Ok(_) => {
+                info!("Appended {} events", records.len());
+                true
+            }
+            Err(error_log) => {
+                error!("Write failed: {error_log}");
+                false
+            }
+        }
+    }
+
+    /// Write the whole document, and move the events into the history
+    fn write_document(&self, records: &[EventRecord]) -> bool {
+        let serialized: String = match serde_json::to_string_pretty(&self.document) {
             Ok(x) => {
                 debug!("Serialize succeeded: {}", x);
                 x
             }
             Err(_) => panic!("Something went wrong serializing the storage"),
         };
-        info!("Attempting to write to storage");
-        warn!("Program crashes if directory does not exist");
-        let path = Path::new("./data");
-        check_if_directory_exists(path);
-
-        match fs::write(self.storage_file_path.clone(), serialized) {
+        // Logged first, so the events reach the history with the ones logged before
+        if !self.is_encrypted() {
+            if let Err(error_log) = append_to_log(&self.storage_file_path, records) {
+                warn!("Could not log the events: {error_log}");
+            }
+        }
+        match write_file(
+            &self.storage_file_path,
+            &serialized,
+            self.passphrase.as_ref(),
+        ) {
             Ok(_) => {
                 info!("Write succeeded");
             }
             Err(error_log) => {
                 error!("Write failed: {error_log}");
+                return false;
             }
         }
+        // A log that stays behind is skipped when replayed, as the document includes its events
+        if let Err(error_log) = archive_log(&self.storage_file_path) {
+            warn!("Could not archive the event log: {error_log}");
+        }
+        true
     }
 }
 
@@ -204,11 +869,11 @@ use std::path::Path;
 use std::{fs, io};
 
 fn find_previous_storage(storage_file_path: &str) -> bool {
-    fs::read_to_string(storage_file_path).is_ok()
+    fs::read(storage_file_path).is_ok()
 }
 
-fn get_previous_storage(storage_file_path: &str) -> Storage {
-    let serialized = match fs::read_to_string(storage_file_path) {
+fn get_previous_storage(storage_file_path: &str, passphrase: Option<Passphrase>) -> Storage {
+    let serialized = match read_file(storage_file_path, passphrase.as_ref()) {
         Ok(serialized) => serialized,
         Err(error) => {
             panic!(
@@ -217,8 +882,21 @@ fn get_previous_storage(storage_file_path: &str) -> Storage {
             );
         }
     };
-    let storage: Storage = match serde_json::from_str(&serialized) {
-        Ok(storage) => storage,
+    let parsed: Value = match serde_json::from_str(&serialized) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            panic!(
+                "Had an error converting json into Storage struct: {}",
+                error
+            );
+        }
+    };
+    // A file in an older format is written in full on the next write, to bring it up to date
+    let mut migrated = parsed.clone();
+    migrate(&mut migrated);
+    let up_to_date = migrated == parsed;
+    let mut document = match StorageDocument::from_value(parsed) {
+        Ok(document) => document,
         Err(error) => {
             panic!(
                 "Had an error converting json into Storage struct: {}",
@@ -226,40 +904,65 @@ fn get_previous_storage(storage_file_path: &str) -> Storage {
             );
         }
     };
+    match read_log(storage_file_path) {
+        Ok(records) => replay(&mut document, &records),
+        Err(error) => panic!("Couldn't read the event log: {}", error),
+    }
 
-    storage
+    Storage {
+        written: up_to_date.then(|| document.clone()),
+        document,
+        storage_file_path: storage_file_path.to_string(),
+        journal: Journal::load(&Journal::path_for(storage_file_path), passphrase.as_ref()),
+        pending_hooks: Vec::new(),
+        pending_events: Vec::new(),
+        // A storage file in plaintext stays so until it is encrypted on purpose
+        passphrase: passphrase.filter(|_| is_encrypted_file(storage_file_path)),
+        config: Config::default(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::io::storage::{get_previous_storage, PreviousStorage, Storage};
-    use crate::topics::review_topics::ReviewTopic;
+    use crate::io::encryption::{is_encrypted_file, Passphrase};
+    use crate::io::events::{log_path, read_log};
+    use crate::io::journal::Journal;
+    use crate::io::storage::{
+        get_previous_storage, migrate, PreviousStorage, Storage, FORMAT_VERSION,
+    };
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::review_topics::{id_from_name, ReviewTopic, TopicState};
+    use chrono::Days;
+    use serde_json::json;
     use serial_test::serial;
+    use std::fs;
+    use std::fs::create_dir_all;
+    use std::path::Path;
 
     static TEST_PATH: &str = "./data/test_storage.json";
 
     #[test]
     #[serial]
     fn test_read_write() {
-        let storage = Storage::new(PreviousStorage::No, TEST_PATH.to_string().into());
+        let mut storage = Storage::new(PreviousStorage::No, TEST_PATH.to_string().into());
         storage.write_storage();
-        assert_eq!(storage, get_previous_storage(TEST_PATH));
+        assert_eq!(storage, get_previous_storage(TEST_PATH, None));
     }
 
     #[test]
     fn update_today_reviews() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
         let test_storage_file_path = TEST_PATH.to_string();
         let mut storage: Storage = Storage::new(PreviousStorage::No, test_storage_file_path.into());
 
-        let mut test_1 = ReviewTopic::new("test_1".to_string());
-        test_1.sub_days(2);
+        let test_1 = ReviewTopic::new("test_1".to_string(), &clock.before_days(2));
         storage.add_review_topic(test_1.clone());
-        storage.add_review_topic(ReviewTopic::new("test2".to_string()));
+        storage.add_review_topic(ReviewTopic::new("test2".to_string(), &clock));
 
         let mut today_topics: Vec<ReviewTopic> = Vec::new();
         let review_list = storage.get_review_topic_list();
         review_list.iter().for_each(|topic| {
-            if topic.is_time_to_review() {
+            if topic.is_time_to_review(&clock) {
                 today_topics.push(topic.clone())
             }
         });
@@ -268,11 +971,34 @@ mod tests {
         assert_eq!(test_1.topic_name, today_topics.pop().unwrap().topic_name);
     }
 
+    #[test]
+    fn test_pause_and_resume() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut storage = Storage::new(PreviousStorage::No, TEST_PATH.to_string().into());
+        storage.add_review_topic(ReviewTopic::new("due".to_string(), &clock.before_days(1)));
+
+        storage.pause(clock.today() + Days::new(3), &clock);
+        assert!(storage.get_today_list(&clock).is_empty());
+        assert_eq!(None, storage.resume_if_pause_over(&clock));
+
+        let back_from_vacation = clock.after_days(3);
+        assert_eq!(Some(3), storage.resume_if_pause_over(&back_from_vacation));
+        assert_eq!(None, storage.get_pause());
+        assert_eq!(1, storage.get_today_list(&back_from_vacation).len());
+        assert_eq!(
+            0,
+            storage.get_review_topic_list()[0].days_until_review(&back_from_vacation)
+        );
+    }
+
     #[test]
     #[serial]
     fn test_renaming_topics() {
         let mut storage = Storage::new(PreviousStorage::No, TEST_PATH.to_string().into());
-        let review_topic = ReviewTopic::new("Test1".to_string());
+        let review_topic = ReviewTopic::new(
+            "Test1".to_string(),
+            &FixedClock::utc("2024-05-10T12:00:00Z"),
+        );
         storage.add_review_topic(review_topic);
         assert!(storage
             .rename_review_topic("Test1".to_string(), "Test1_edited".to_string())
@@ -282,4 +1008,213 @@ mod tests {
             .find_review_topic("Test1_edited".to_string())
             .is_some());
     }
+
+    #[test]
+    fn test_rescheduling_topics() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut storage = Storage::new(PreviousStorage::No, TEST_PATH.to_string().into());
+        storage.add_review_topic(ReviewTopic::new("Test1".to_string(), &clock));
+
+        assert!(storage
+            .reschedule_topic("Test1".to_string(), clock.today() + Days::new(5))
+            .is_ok());
+        assert!(storage.get_today_list(&clock.after_days(4)).is_empty());
+        assert_eq!(1, storage.get_today_list(&clock.after_days(5)).len());
+
+        assert!(storage.reset_topic("Test1".to_string(), &clock).is_ok());
+        assert_eq!(1, storage.get_today_list(&clock.after_days(1)).len());
+        assert!(storage.reset_topic("Missing".to_string(), &clock).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_undo_survives_reloading() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut storage = Storage::new(PreviousStorage::No, TEST_PATH.to_string().into());
+        storage.add_review_topic(ReviewTopic::new("Test1".to_string(), &clock));
+        assert!(storage
+            .review_topic("Test1".to_string(), None, &clock)
+            .is_ok());
+        assert!(storage
+            .rename_review_topic("Test1".to_string(), "Test2".to_string())
+            .is_ok());
+        storage.write_storage();
+
+        let mut storage = get_previous_storage(TEST_PATH, None);
+        assert_eq!(3, storage.get_journal().recent().count());
+        assert!(storage.undo().is_ok());
+        assert!(storage.undo().is_ok());
+        let topic = storage.find_review_topic("Test1".to_string()).unwrap();
+        assert!(topic.review_history.is_empty());
+
+        assert!(storage.redo().is_ok());
+        assert_eq!(
+            1,
+            storage
+                .find_review_topic("Test1".to_string())
+                .unwrap()
+                .review_history
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_undoing_merges() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut storage = Storage::new(PreviousStorage::No, TEST_PATH.to_string().into());
+        storage.add_review_topic(ReviewTopic::new("Test1".to_string(), &clock));
+        let mut merged = storage.get_review_topic_list();
+        merged.push(ReviewTopic::new("Test2".to_string(), &clock));
+        storage.merge_review_topics("other.json".to_string(), merged);
+        assert_eq!(2, storage.get_review_topic_list().len());
+
+        assert_eq!("Merge 'other.json'", storage.undo().unwrap().to_string());
+        assert_eq!(1, storage.get_review_topic_list().len());
+        assert!(storage.redo().is_ok());
+        assert!(storage.find_review_topic("Test2".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_changes_are_appended_to_the_event_log() {
+        let path = "./data/test_event_storage.json";
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(log_path(path));
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut storage = Storage::new(PreviousStorage::No, path.to_string().into());
+        storage.add_review_topic(ReviewTopic::new("Test1".to_string(), &clock));
+        storage.write_storage();
+        let written = fs::read_to_string(path).unwrap();
+
+        let mut storage = get_previous_storage(path, None);
+        assert!(storage
+            .review_topic("Test1".to_string(), None, &clock)
+            .is_ok());
+        storage.add_review_topic(ReviewTopic::new("Test2".to_string(), &clock));
+        storage.write_storage();
+        assert_eq!(written, fs::read_to_string(path).unwrap());
+        assert_eq!(2, read_log(path).unwrap().len());
+
+        let mut reloaded = get_previous_storage(path, None);
+        assert_eq!(
+            storage.get_review_topic_list(),
+            reloaded.get_review_topic_list()
+        );
+        let topic = reloaded.find_review_topic("Test1".to_string()).unwrap();
+        assert_eq!(1, topic.review_history.len());
+    }
+
+    #[test]
+    fn test_stale_event_log_is_skipped() {
+        let path = "./data/test_stale_event_storage.json";
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(log_path(path));
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut storage = Storage::new(PreviousStorage::No, path.to_string().into());
+        storage.add_review_topic(ReviewTopic::new("Test1".to_string(), &clock));
+        storage.write_storage();
+
+        let mut storage = get_previous_storage(path, None);
+        assert!(storage
+            .review_topic("Test1".to_string(), None, &clock)
+            .is_ok());
+        storage.write_storage();
+        let log = fs::read_to_string(log_path(path)).unwrap();
+
+        // Suspending records no event, so the storage file is written in full
+        storage
+            .set_topic_state("Test1".to_string(), TopicState::Suspended)
+            .unwrap();
+        storage.write_storage();
+        assert!(!log_path(path).exists());
+
+        // As if revue stopped before the log was archived
+        fs::write(log_path(path), log).unwrap();
+        let mut reloaded = get_previous_storage(path, None);
+        let topic = reloaded.find_review_topic("Test1".to_string()).unwrap();
+        assert_eq!(TopicState::Suspended, topic.state);
+        assert_eq!(1, topic.review_history.len());
+    }
+
+    #[test]
+    fn test_encrypted_storage() {
+        let path = "./data/test_encrypted_storage.json";
+        let _ = fs::remove_file(log_path(path));
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut storage = Storage::new(PreviousStorage::No, path.to_string().into());
+        storage.add_review_topic(ReviewTopic::new("Secret".to_string(), &clock));
+        let passphrase = Passphrase::new("correct horse".to_string()).unwrap();
+        storage.set_passphrase(Some(passphrase.clone()));
+        storage.write_storage();
+        assert!(is_encrypted_file(path));
+        assert!(is_encrypted_file(Journal::path_for(path)));
+
+        let mut storage = get_previous_storage(path, Some(passphrase));
+        assert!(storage.is_encrypted());
+        assert!(storage
+            .review_topic("Secret".to_string(), None, &clock)
+            .is_ok());
+        storage.write_storage();
+        assert!(is_encrypted_file(path));
+        assert!(!log_path(path).exists());
+
+        storage.set_passphrase(None);
+        storage.write_storage();
+        let mut reloaded = get_previous_storage(path, None);
+        assert!(!reloaded.is_encrypted());
+        let topic = reloaded.find_review_topic("Secret".to_string()).unwrap();
+        assert_eq!(1, topic.review_history.len());
+    }
+
+    #[test]
+    fn test_migrate_drops_the_storage_file_path() {
+        let mut document = json!({
+            "review_topic_list": [],
+            "storage_file_path": "./data/storage.json"
+        });
+        migrate(&mut document);
+        assert_eq!(
+            json!({"review_topic_list": [], "format_version": FORMAT_VERSION}),
+            document
+        );
+    }
+
+    #[test]
+    fn test_migrate_gives_topics_an_id() {
+        let mut document = json!({
+            "format_version": 1,
+            "review_topic_list": [{"topic_name": "a"}, {"topic_name": "b", "id": "kept"}]
+        });
+        migrate(&mut document);
+        let topics = document["review_topic_list"].as_array().unwrap();
+        assert_eq!(json!(id_from_name("a")), topics[0]["id"]);
+        assert_eq!(json!("kept"), topics[1]["id"]);
+    }
+
+    #[test]
+    fn test_writes_back_to_the_loaded_path() {
+        let copied_path = "./data/test_copied_storage.json";
+        let original_path = "./data/test_original_storage.json";
+        let _ = fs::remove_file(original_path);
+        create_dir_all("./data").unwrap();
+        // A legacy storage file copied from somewhere else still records the old location
+        let legacy = json!({
+            "review_topic_list": [],
+            "storage_file_path": original_path
+        });
+        fs::write(copied_path, legacy.to_string()).unwrap();
+
+        let mut storage = Storage::new(PreviousStorage::Yes, copied_path.to_string().into());
+        storage.add_review_topic(ReviewTopic::new(
+            "Test1".to_string(),
+            &FixedClock::utc("2024-05-10T12:00:00Z"),
+        ));
+        storage.write_storage();
+
+        assert!(!Path::new(original_path).exists());
+        let written = fs::read_to_string(copied_path).unwrap();
+        assert!(!written.contains("storage_file_path"));
+        let reloaded = Storage::new(PreviousStorage::Yes, copied_path.to_string().into());
+        assert_eq!(copied_path, reloaded.get_storage_file_path());
+        assert_eq!(1, reloaded.get_review_topic_list().len());
+    }
 }
@@ -0,0 +1,458 @@
+//! Keeping the storage in a git repository and syncing it with a remote
+//!
+//! `revue sync init` turns the directory of the storage file into a git repository that only
+//! tracks the storage file, and marks it as revue's own with the `revue.sync` git setting. From
+//! then on every write of the storage is committed. A repository without that setting is left
+//! alone.
+//!
+//! `revue sync` pulls from the remote and pushes back to it. When both sides changed the topics,
+//! they are merged topic by topic with [`merge_topics`] rather than line by line, so syncing never
+//! ends in a conflict to resolve by hand.
+//!
+//! Git is run as a command, so the remote can be anything git can push to, with the credentials
+//! git is set up with.
+
+use crate::io::encryption::{decrypt_contents, is_encrypted_file, write_file, Passphrase};
+use crate::io::events::{compact, EventError};
+use crate::io::hooks::{HookEvent, PendingHook};
+use crate::io::storage::StorageDocument;
+use crate::topics::merge::merge_topics;
+use log::info;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{fs, io};
+
+/// The branch the topics are kept on, the same on every machine
+const BRANCH: &str = "main";
+
+/// The name of the remote revue pushes to and pulls from
+const REMOTE: &str = "origin";
+
+/// The git setting marking a repository as the one `revue sync init` created
+const OWNER_SETTING: &str = "revue.sync";
+
+#[derive(Debug)]
+pub enum SyncError {
+    /// `revue sync init` was not run for the storage
+    NotInitialized(PathBuf),
+    /// The storage's directory is a git repository revue did not create
+    ForeignRepository(PathBuf),
+    /// Neither `sync.remote` nor a git remote is set
+    NoRemote,
+    Git {
+        command: String,
+        message: String,
+    },
+    /// The storage file in a commit could not be read
+    InvalidStorage {
+        revision: String,
+        reason: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::NotInitialized(dir) => write!(
+                f,
+                "{} is not synced yet. Run `revue sync init` first.",
+                dir.display()
+            ),
+            SyncError::ForeignRepository(dir) => write!(
+                f,
+                "{} is a git repository revue did not create. Keep the storage file in a directory of its own to sync it.",
+                dir.display()
+            ),
+            SyncError::NoRemote => write!(
+                f,
+                "There is no remote to sync with. Set one with `revue config set sync.remote <url>`."
+            ),
+            SyncError::Git { command, message } => write!(f, "`{command}` failed: {message}"),
+            SyncError::InvalidStorage { revision, reason } => {
+                write!(f, "The storage in {revision} could not be read: {reason}")
+            }
+            SyncError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl From<io::Error> for SyncError {
+    fn from(error: io::Error) -> Self {
+        SyncError::Io(error)
+    }
+}
+
+impl From<EventError> for SyncError {
+    fn from(error: EventError) -> Self {
+        match error {
+            EventError::InvalidStorage(reason) => SyncError::InvalidStorage {
+                revision: "the working directory".to_string(),
+                reason,
+            },
+            EventError::Io(error) => SyncError::Io(error),
+        }
+    }
+}
+
+/// What `revue sync` did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    /// Both sides already had the same topics
+    UpToDate,
+    /// Only this side had changes, which were pushed
+    Pushed,
+    /// Only the remote had changes, which were pulled
+    Pulled,
+    /// Both sides had changes, which were merged and pushed
+    Merged,
+}
+
+impl fmt::Display for SyncOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncOutcome::UpToDate => write!(f, "The topics are up to date."),
+            SyncOutcome::Pushed => write!(f, "Pushed the changes to the remote."),
+            SyncOutcome::Pulled => write!(f, "Pulled the changes from the remote."),
+            SyncOutcome::Merged => write!(
+                f,
+                "Merged the changes from the remote and pushed the result."
+            ),
+        }
+    }
+}
+
+/// The git repository the storage file is kept in
+struct Repository {
+    dir: PathBuf,
+    file_name: String,
+    /// Settings to commit with when git has no identity set up
+    identity: Vec<&'static str>,
+}
+
+impl Repository {
+    fn for_storage(storage_file_path: &str) -> Self {
+        let path = Path::new(storage_file_path);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Repository {
+            dir,
+            file_name,
+            identity: Vec::new(),
+        }
+    }
+
+    /// The repository, if `revue sync init` was run for the storage
+    fn open(storage_file_path: &str) -> Result<Self, SyncError> {
+        let mut repository = Repository::for_storage(storage_file_path);
+        if !repository.is_initialized() {
+            return Err(SyncError::NotInitialized(repository.dir));
+        }
+        repository.find_identity();
+        Ok(repository)
+    }
+
+    /// Only the directory's own `.git` counts, not one of a directory further up
+    fn exists(&self) -> bool {
+        self.dir.join(".git").exists()
+    }
+
+    /// Whether the repository is the one `revue sync init` created
+    fn is_initialized(&self) -> bool {
+        self.exists()
+            && self
+                .run(&["config", "--local", "--get", OWNER_SETTING])
+                .is_ok_and(|owned| owned == "true")
+    }
+
+    fn find_identity(&mut self) {
+        let has_identity = self
+            .run(&["config", "user.email"])
+            .is_ok_and(|email| !email.is_empty());
+        if !has_identity {
+            self.identity = vec!["-c", "user.name=revue", "-c", "user.email=revue@localhost"];
+        }
+    }
+
+    /// Run a git command in the repository and return its output
+    fn run(&self, args: &[&str]) -> Result<String, SyncError> {
+        let output = self.run_for_bytes(args)?;
+        Ok(String::from_utf8_lossy(&output).trim().to_string())
+    }
+
+    /// Run git and return its output as it is, for files that may be encrypted
+    fn run_for_bytes(&self, args: &[&str]) -> Result<Vec<u8>, SyncError> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(&self.identity)
+            .args(args)
+            .output()?;
+        match output.status.success() {
+            true => Ok(output.stdout),
+            false => Err(SyncError::Git {
+                command: format!("git {}", args.join(" ")),
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            }),
+        }
+    }
+
+    /// Commit the storage file if it changed
+    ///
+    /// returns: Whether a commit was made
+    fn commit(&self, message: &str) -> Result<bool, SyncError> {
+        // Before the first write there is no storage file to add yet
+        let mut add_args = vec!["add", "--", ".gitignore"];
+        if self.dir.join(&self.file_name).exists() {
+            add_args.push(&self.file_name);
+        }
+        self.run(&add_args)?;
+        if self.run(&["diff", "--cached", "--quiet"]).is_ok() {
+            return Ok(false);
+        }
+        self.run(&["commit", "--quiet", "--message", message])?;
+        Ok(true)
+    }
+
+    /// The storage document in a commit. A commit without the storage file has no topics.
+    fn document_at(
+        &self,
+        revision: &str,
+        passphrase: Option<&Passphrase>,
+    ) -> Result<StorageDocument, SyncError> {
+        let Ok(contents) = self.run_for_bytes(&["show", &format!("{revision}:{}", self.file_name)])
+        else {
+            return Ok(StorageDocument::default());
+        };
+        let invalid_storage = |reason: String| SyncError::InvalidStorage {
+            revision: revision.to_string(),
+            reason,
+        };
+        let serialized = decrypt_contents(contents, passphrase)
+            .map_err(|error| invalid_storage(error.to_string()))?;
+        StorageDocument::from_json(&serialized).map_err(|error| invalid_storage(error.to_string()))
+    }
+
+    /// Point the remote at `url`, or check there is a remote when no url is given
+    ///
+    /// A url that is a path is taken relative to the current directory, not the repository.
+    fn set_remote(&self, url: Option<&str>) -> Result<(), SyncError> {
+        let url = url.map(|url| match fs::canonicalize(url) {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => url.to_string(),
+        });
+        let url = url.as_deref();
+        let current = self.run(&["remote", "get-url", REMOTE]).ok();
+        match (url, current) {
+            (None, None) => Err(SyncError::NoRemote),
+            (None, Some(_)) => Ok(()),
+            (Some(url), Some(current)) if current == url => Ok(()),
+            (Some(url), Some(_)) => self.run(&["remote", "set-url", REMOTE, url]).map(|_| ()),
+            (Some(url), None) => self.run(&["remote", "add", REMOTE, url]).map(|_| ()),
+        }
+    }
+}
+
+/// Whether the storage is kept in a git repository, so every write is committed
+pub fn is_initialized(storage_file_path: &str) -> bool {
+    Repository::for_storage(storage_file_path).is_initialized()
+}
+
+/// Turn the directory of the storage file into a git repository tracking only the storage file
+///
+/// A directory that already is a git repository is refused, unless `init` created it.
+/// `passphrase` is the one of an encrypted storage.
+///
+/// returns: Whether the repository was created, rather than already there
+pub fn init(storage_file_path: &str, passphrase: Option<&Passphrase>) -> Result<bool, SyncError> {
+    let repository = Repository::for_storage(storage_file_path);
+    if repository.is_initialized() {
+        return Ok(false);
+    }
+    if repository.exists() {
+        return Err(SyncError::ForeignRepository(repository.dir));
+    }
+    fs::create_dir_all(&repository.dir)?;
+    repository.run(&["init", "--quiet"])?;
+    repository.run(&["config", OWNER_SETTING, "true"])?;
+    repository.run(&["symbolic-ref", "HEAD", &format!("refs/heads/{BRANCH}")])?;
+    // Snapshots, the journal and hook state are kept per machine
+    fs::write(
+        repository.dir.join(".gitignore"),
+        format!("*\n!.gitignore\n!{}\n", repository.file_name),
+    )?;
+
+    let repository = Repository::open(storage_file_path)?;
+    compact(storage_file_path, passphrase)?;
+    repository.commit("Start syncing the topics")?;
+    Ok(true)
+}
+
+/// Commit the storage file, if it is kept in a git repository and changed
+pub fn commit(storage_file_path: &str, message: &str) -> Result<bool, SyncError> {
+    if !is_initialized(storage_file_path) {
+        return Ok(false);
+    }
+    Repository::open(storage_file_path)?.commit(message)
+}
+
+/// A commit message describing the changes whose hooks are pending
+pub fn commit_message(pending_hooks: &[PendingHook]) -> String {
+    let changes: Vec<String> = pending_hooks
+        .iter()
+        .map(|pending_hook| {
+            let topic_name = &pending_hook.topic.topic_name;
+            match (pending_hook.event, &pending_hook.previous_name) {
+                (HookEvent::PostRename, Some(previous_name)) => {
+                    format!("Rename '{previous_name}' to '{topic_name}'")
+                }
+                (HookEvent::PostAdd, _) => format!("Add '{topic_name}'"),
+                (HookEvent::PostRemove, _) => format!("Remove '{topic_name}'"),
+                (HookEvent::PostReview, _) => format!("Review '{topic_name}'"),
+                (_, _) => format!("Change '{topic_name}'"),
+            }
+        })
+        .collect();
+    match changes.is_empty() {
+        true => "Update the topics".to_string(),
+        false => changes.join(", "),
+    }
+}
+
+/// Pull the remote's changes, merging them with the ones made here, and push the result
+///
+/// `remote` is the url to sync with. Without one, the remote set up earlier is used.
+/// `passphrase` is the one of an encrypted storage.
+pub fn sync(
+    storage_file_path: &str,
+    remote: Option<&str>,
+    passphrase: Option<&Passphrase>,
+) -> Result<SyncOutcome, SyncError> {
+    let repository = Repository::open(storage_file_path)?;
+    // Git only sees the storage file, not the changes still in the event log
+    compact(storage_file_path, passphrase)?;
+    // The storage file may have been changed without revue, like by a restore or by hand
+    repository.commit("Update the topics")?;
+    repository.set_remote(remote)?;
+
+    info!("Fetching from the remote");
+    repository.run(&["fetch", "--quiet", REMOTE])?;
+    let remote_branch = format!("refs/remotes/{REMOTE}/{BRANCH}");
+    if repository
+        .run(&["rev-parse", "--verify", "--quiet", &remote_branch])
+        .is_err()
+    {
+        info!("The remote has no topics yet");
+        repository.run(&["push", "--quiet", "--set-upstream", REMOTE, BRANCH])?;
+        return Ok(SyncOutcome::Pushed);
+    }
+
+    let ours = repository.run(&["rev-parse", "HEAD"])?;
+    let theirs = repository.run(&["rev-parse", &remote_branch])?;
+    // Two machines that started syncing on their own have no commit in common
+    let base = repository.run(&["merge-base", &ours, &theirs]).ok();
+    if ours == theirs {
+        return Ok(SyncOutcome::UpToDate);
+    }
+    if base.as_ref() == Some(&ours) {
+        repository.run(&["merge", "--quiet", "--ff-only", &remote_branch])?;
+        return Ok(SyncOutcome::Pulled);
+    }
+    let outcome = match base.as_ref() == Some(&theirs) {
+        true => SyncOutcome::Pushed,
+        false => {
+            merge(&repository, base.as_deref(), &ours, &theirs, passphrase)?;
+            SyncOutcome::Merged
+        }
+    };
+
+    repository.run(&["push", "--quiet", "--set-upstream", REMOTE, BRANCH])?;
+    Ok(outcome)
+}
+
+/// Record a merge commit whose storage combines the topics of both sides
+///
+/// The settings of this side are kept. Git's own merge is only used to record both parents, as
+/// a textual merge of the JSON would conflict on any two changes close to each other.
+fn merge(
+    repository: &Repository,
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    passphrase: Option<&Passphrase>,
+) -> Result<(), SyncError> {
+    info!("Merging the topics with the ones of the remote");
+    let base_document = match base {
+        Some(base) => repository.document_at(base, passphrase)?,
+        None => StorageDocument::default(),
+    };
+    let mut document = repository.document_at(ours, passphrase)?;
+    let their_document = repository.document_at(theirs, passphrase)?;
+    document.set_review_topics(merge_topics(
+        base_document.review_topics(),
+        document.review_topics(),
+        their_document.review_topics(),
+    ));
+
+    let mut merge_args = vec!["merge", "--quiet", "--no-commit", "--no-ff", "-s", "ours"];
+    if base.is_none() {
+        merge_args.push("--allow-unrelated-histories");
+    }
+    merge_args.push(theirs);
+    repository.run(&merge_args)?;
+
+    let serialized =
+        serde_json::to_string_pretty(&document).expect("The storage document is valid JSON");
+    let storage_file_path = repository.dir.join(&repository.file_name);
+    let passphrase = passphrase.filter(|_| is_encrypted_file(&storage_file_path));
+    write_file(storage_file_path, &serialized, passphrase)?;
+    repository.run(&["add", "--", &repository.file_name])?;
+    repository.run(&[
+        "commit",
+        "--quiet",
+        "--message",
+        "Merge the topics of the remote",
+    ])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::hooks::{HookEvent, PendingHook};
+    use crate::io::sync::commit_message;
+    use crate::topics::clock::FixedClock;
+    use crate::topics::review_topics::ReviewTopic;
+
+    #[test]
+    fn test_commit_message() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let topic = ReviewTopic::new("rust".to_string(), &clock);
+        let pending_hooks = [
+            PendingHook {
+                event: HookEvent::PostAdd,
+                topic: topic.clone(),
+                previous_name: None,
+            },
+            PendingHook {
+                event: HookEvent::PostRename,
+                topic,
+                previous_name: Some("go".to_string()),
+            },
+        ];
+        assert_eq!(
+            "Add 'rust', Rename 'go' to 'rust'",
+            commit_message(&pending_hooks)
+        );
+        assert_eq!("Update the topics", commit_message(&[]));
+    }
+}
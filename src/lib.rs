@@ -0,0 +1,38 @@
+//! Keep track of the topics you learn and when to go back and review them.
+//!
+//! The `revue` command line tool is a thin layer over this library, so the same scheduling and
+//! storage can be embedded in other tools.
+//!
+//! - [`ReviewTopic`] is a single topic with its review history. Reviewing it moves its next
+//!   review further away.
+//! - [`topics::scheduling`] decides which topics are due today and in which order.
+//! - [`Storage`] keeps the topics and settings in a JSON file.
+//! - [`io::console`] renders topics, statistics and settings as text.
+//! - [`cli`] parses the arguments of the `revue` command line tool and runs its commands.
+//!
+//! Everything that depends on the current time takes a [`Clock`], so a [`FixedClock`] can be used
+//! to look at another day.
+//!
+//! # Examples
+//!
+//! ```
+//! use chrono::Days;
+//! use revue::{Calendar, Clock, FixedClock, NextReviewGap, ReviewTopic};
+//!
+//! let clock = FixedClock::new(chrono::Utc::now(), Calendar::default());
+//! let mut topic = ReviewTopic::new("Borrow checker".to_string(), &clock);
+//! assert_eq!(1, topic.days_until_review(&clock));
+//!
+//! topic.review(&clock);
+//! assert_eq!(NextReviewGap::Week, topic.next_review_gap);
+//! assert_eq!(clock.today() + Days::new(7), topic.review_day(&clock));
+//! ```
+
+pub mod cli;
+pub mod io;
+pub mod topics;
+
+pub use io::storage::{PreviousStorage, Storage, StorageDocument};
+pub use topics::calendar::Calendar;
+pub use topics::clock::{Clock, FixedClock, SystemClock};
+pub use topics::review_topics::{NextReviewGap, ReviewTopic, TopicState};
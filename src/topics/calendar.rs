@@ -0,0 +1,184 @@
+//! Turning points in time into days
+
+use crate::topics::review_topics::GapLengths;
+use chrono::{DateTime, Local, LocalResult, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Formatter;
+
+/// How points in time map onto the days topics are due on
+///
+/// Review times are stored in UTC and only turned into days here, so a topic stays due on the
+/// same day when crossing a DST transition or travelling, as long as the calendar is unchanged.
+#[derive(Default, Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Calendar {
+    /// Timezone the days are counted in. `None` means the system timezone.
+    #[serde(default)]
+    pub timezone: Option<Tz>,
+    /// Hour of the day at which the next day starts, for example 4 to count a review done at
+    /// 1 a.m. towards the previous day
+    #[serde(default)]
+    pub day_rollover_hour: u32,
+    /// Number of days each review gap lasts. Only the config sets it, so it is not stored.
+    #[serde(skip)]
+    pub gap_lengths: GapLengths,
+}
+
+impl fmt::Display for Calendar {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let timezone = match self.timezone {
+            None => "System".to_string(),
+            Some(timezone) => timezone.to_string(),
+        };
+        write!(
+            f,
+            "Timezone: {}\nDay rollover hour: {}",
+            timezone, self.day_rollover_hour
+        )
+    }
+}
+
+impl Calendar {
+    /// The day the given point in time belongs to
+    pub fn day_of(&self, instant: DateTime<Utc>) -> NaiveDate {
+        let local_time: NaiveDateTime = match self.timezone {
+            None => instant.with_timezone(&Local).naive_local(),
+            Some(timezone) => instant.with_timezone(&timezone).naive_local(),
+        };
+        (local_time - TimeDelta::hours(i64::from(self.day_rollover_hour))).date()
+    }
+
+    /// A point in time in the middle of the given day
+    pub fn instant_on(&self, day: NaiveDate) -> DateTime<Utc> {
+        let noon: NaiveDateTime = day.and_hms_opt(12, 0, 0).expect("Noon is a valid time")
+            + TimeDelta::hours(i64::from(self.day_rollover_hour));
+        match self.timezone {
+            None => from_local(&Local, noon),
+            Some(timezone) => from_local(&timezone, noon),
+        }
+    }
+
+    /// The same wall clock time `days` days later, or earlier for negative `days`, regardless of
+    /// DST changes in between
+    pub fn add_days(&self, instant: DateTime<Utc>, days: i64) -> DateTime<Utc> {
+        match self.timezone {
+            None => add_local_days(&Local, instant, days),
+            Some(timezone) => add_local_days(&timezone, instant, days),
+        }
+    }
+}
+
+fn from_local<T: TimeZone>(timezone: &T, local_time: NaiveDateTime) -> DateTime<Utc> {
+    match timezone.from_local_datetime(&local_time) {
+        LocalResult::Single(datetime) | LocalResult::Ambiguous(datetime, _) => datetime.to_utc(),
+        LocalResult::None => timezone
+            .from_local_datetime(&(local_time + TimeDelta::hours(1)))
+            .earliest()
+            .expect("Failed to find the local time")
+            .to_utc(),
+    }
+}
+
+fn add_local_days<T: TimeZone>(timezone: &T, instant: DateTime<Utc>, days: i64) -> DateTime<Utc> {
+    let shifted: NaiveDateTime =
+        instant.with_timezone(timezone).naive_local() + TimeDelta::days(days);
+
+    match timezone.from_local_datetime(&shifted) {
+        LocalResult::Single(datetime) | LocalResult::Ambiguous(datetime, _) => datetime.to_utc(),
+        // The wall clock time was skipped by a DST change, so keep the exact duration instead
+        LocalResult::None => instant + TimeDelta::days(days),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topics::calendar::Calendar;
+    use chrono::{DateTime, NaiveDate, Utc};
+    use chrono_tz::Europe::Berlin;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().to_utc()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_day_of_uses_the_configured_timezone() {
+        let calendar = Calendar {
+            timezone: Some(Berlin),
+            ..Calendar::default()
+        };
+        // 23:30 in Berlin, but already the next day in UTC+14
+        assert_eq!(
+            date(2024, 7, 1),
+            calendar.day_of(utc("2024-07-01T23:30:00+02:00"))
+        );
+        assert_eq!(
+            date(2024, 7, 1),
+            calendar.day_of(utc("2024-07-02T11:30:00+14:00"))
+        );
+    }
+
+    #[test]
+    fn test_day_rollover_hour() {
+        let calendar = Calendar {
+            timezone: Some(Berlin),
+            day_rollover_hour: 4,
+            ..Calendar::default()
+        };
+        assert_eq!(
+            date(2024, 7, 1),
+            calendar.day_of(utc("2024-07-02T03:59:00+02:00"))
+        );
+        assert_eq!(
+            date(2024, 7, 2),
+            calendar.day_of(utc("2024-07-02T04:00:00+02:00"))
+        );
+
+        // The night the clocks go forward only has 3 hours between midnight and 4 a.m.
+        assert_eq!(
+            date(2024, 3, 30),
+            calendar.day_of(utc("2024-03-31T03:30:00+02:00"))
+        );
+        assert_eq!(
+            date(2024, 3, 31),
+            calendar.day_of(utc("2024-03-31T04:00:00+02:00"))
+        );
+    }
+
+    #[test]
+    fn test_instant_on() {
+        let calendar = Calendar {
+            timezone: Some(Berlin),
+            day_rollover_hour: 4,
+            ..Calendar::default()
+        };
+        let day = date(2024, 3, 31);
+        assert_eq!(day, calendar.day_of(calendar.instant_on(day)));
+    }
+
+    #[test]
+    fn test_add_days_across_dst() {
+        let calendar = Calendar {
+            timezone: Some(Berlin),
+            ..Calendar::default()
+        };
+        // Clocks go forward on 2024-03-31 and back on 2024-10-27
+        assert_eq!(
+            utc("2024-04-02T09:00:00+02:00"),
+            calendar.add_days(utc("2024-03-29T09:00:00+01:00"), 4)
+        );
+        assert_eq!(
+            utc("2024-10-28T09:00:00+01:00"),
+            calendar.add_days(utc("2024-10-26T09:00:00+02:00"), 2)
+        );
+        // 02:30 does not exist on 2024-03-31
+        assert_eq!(
+            utc("2024-03-31T03:30:00+02:00"),
+            calendar.add_days(utc("2024-03-30T02:30:00+01:00"), 1)
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Where the current time comes from
+
+use crate::topics::calendar::Calendar;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+/// Source of the current time for everything that depends on it
+///
+/// Every day used for scheduling comes from [`Clock::day_of`], so the configured calendar is
+/// applied the same way everywhere.
+pub trait Clock {
+    /// The current point in time
+    fn now(&self) -> DateTime<Utc>;
+
+    /// How points in time are turned into days
+    fn calendar(&self) -> &Calendar;
+
+    /// The day the given point in time belongs to
+    fn day_of(&self, instant: DateTime<Utc>) -> NaiveDate {
+        self.calendar().day_of(instant)
+    }
+
+    /// The current day
+    fn today(&self) -> NaiveDate {
+        self.day_of(self.now())
+    }
+}
+
+/// The real time, used when running revue normally
+#[derive(Default, Debug, Clone)]
+pub struct SystemClock {
+    calendar: Calendar,
+}
+
+impl SystemClock {
+    /// A clock counting days with the given calendar
+    pub fn new(calendar: Calendar) -> Self {
+        SystemClock { calendar }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn calendar(&self) -> &Calendar {
+        &self.calendar
+    }
+}
+
+/// A clock stopped at a given time, used to simulate other days
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedClock {
+    now: DateTime<Utc>,
+    calendar: Calendar,
+}
+
+impl FixedClock {
+    /// A clock stopped at `now`, counting days with the given calendar
+    pub fn new(now: DateTime<Utc>, calendar: Calendar) -> Self {
+        FixedClock { now, calendar }
+    }
+}
+
+#[cfg(test)]
+impl FixedClock {
+    /// A clock stopped at an RFC 3339 time, counting days in UTC so tests do not depend on the
+    /// time zone of the machine running them
+    pub fn utc(now: &str) -> Self {
+        let now = DateTime::parse_from_rfc3339(now).expect("The time is RFC 3339");
+        let calendar = Calendar {
+            timezone: Some(chrono_tz::Tz::UTC),
+            ..Calendar::default()
+        };
+        FixedClock::new(now.to_utc(), calendar)
+    }
+
+    /// A clock stopped `days` days after this one
+    pub fn after_days(&self, days: u64) -> Self {
+        FixedClock::new(
+            self.calendar.add_days(self.now, days as i64),
+            self.calendar.clone(),
+        )
+    }
+
+    /// A clock stopped `days` days before this one
+    pub fn before_days(&self, days: u64) -> Self {
+        FixedClock::new(
+            self.calendar.add_days(self.now, -(days as i64)),
+            self.calendar.clone(),
+        )
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    fn calendar(&self) -> &Calendar {
+        &self.calendar
+    }
+}
+
+/// Parse a point in time given on the command line
+///
+/// Accepts RFC 3339 (`2024-05-01T09:30:00+02:00`), a local date and time (`2024-05-01T09:30:00`)
+/// or a local date (`2024-05-01`), which is read as noon on that day.
+pub fn parse_datetime(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.to_utc());
+    }
+
+    let naive_datetime = match NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        Ok(naive_datetime) => naive_datetime,
+        Err(_) => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => {
+                date.and_time(NaiveTime::from_hms_opt(12, 0, 0).expect("Noon is a valid time"))
+            }
+            Err(error) => return Err(format!("Invalid date and time '{value}': {error}")),
+        },
+    };
+
+    match Local.from_local_datetime(&naive_datetime).earliest() {
+        None => Err(format!("'{value}' does not exist in the local timezone")),
+        Some(datetime) => Ok(datetime.to_utc()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topics::calendar::Calendar;
+    use crate::topics::clock::{parse_datetime, Clock, FixedClock};
+    use chrono::{Days, NaiveDate};
+
+    #[test]
+    fn test_fixed_clock() {
+        let clock = FixedClock::new(parse_datetime("2024-02-28").unwrap(), Calendar::default());
+        assert_eq!(NaiveDate::from_ymd_opt(2024, 2, 28).unwrap(), clock.today());
+        assert_eq!(clock.today() + Days::new(2), clock.after_days(2).today());
+        assert_eq!(clock.today() - Days::new(3), clock.before_days(3).today());
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        let expected = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let calendar = Calendar::default();
+        assert_eq!(
+            expected,
+            calendar.day_of(parse_datetime("2024-05-01").unwrap())
+        );
+        assert_eq!(
+            expected,
+            calendar.day_of(parse_datetime("2024-05-01T09:30:00").unwrap())
+        );
+        assert!(parse_datetime("2024-05-01T09:30:00+00:00").is_ok());
+        assert!(parse_datetime("yesterday").is_err());
+    }
+}
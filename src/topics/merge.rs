@@ -0,0 +1,332 @@
+//! Merging topic lists that were changed in two places
+//!
+//! Topics are matched by their id, so a topic renamed on one side is still the same topic on the
+//! other. Each field of a topic is merged on its own: a field changed on only one side takes that
+//! side's value, and a field changed on both sides takes the value of the side that reviewed the
+//! topic last. Reviews are never lost, as the review histories of both sides are combined.
+//!
+//! Without the version both sides started from, as when two copies of a storage file drifted
+//! apart, [`merge_by_identity`] cannot tell an added topic from a removed one or which side
+//! renamed a topic, so it keeps every topic and reports what it could not settle.
+
+use crate::topics::review_topics::ReviewTopic;
+use std::fmt;
+use std::fmt::Formatter;
+
+/// A difference between two topic lists that a merge without their common past cannot settle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeConflict {
+    /// The topic has another name on each side. The name of the side reviewed last is kept.
+    Renamed { kept: String, dropped: String },
+    /// The topic is on one side only. It is kept, though the other side may have removed it.
+    OnlyOnOneSide { topic_name: String, ours: bool },
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeConflict::Renamed { kept, dropped } => write!(
+                f,
+                "'{dropped}' and '{kept}' are the same topic. The name '{kept}' was kept."
+            ),
+            MergeConflict::OnlyOnOneSide {
+                topic_name,
+                ours: true,
+            } => write!(
+                f,
+                "'{topic_name}' is only here, so the other side may have removed it. It was kept."
+            ),
+            MergeConflict::OnlyOnOneSide {
+                topic_name,
+                ours: false,
+            } => write!(
+                f,
+                "'{topic_name}' is only on the other side, so it may have been removed here. It was added."
+            ),
+        }
+    }
+}
+
+/// The merged topics and what the merge could not settle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    pub topics: Vec<ReviewTopic>,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Merge the topics of two sides that both started from `base`
+///
+/// A topic removed on one side stays removed, unless the other side changed it since. The
+/// result keeps the order of `ours`, followed by the topics only `theirs` added.
+pub fn merge_topics(
+    base: &[ReviewTopic],
+    ours: &[ReviewTopic],
+    theirs: &[ReviewTopic],
+) -> Vec<ReviewTopic> {
+    let find = |topics: &[ReviewTopic], id: &str| -> Option<ReviewTopic> {
+        topics.iter().find(|topic| topic.id == id).cloned()
+    };
+
+    let mut merged: Vec<ReviewTopic> = Vec::new();
+    for our_topic in ours {
+        let base_topic = find(base, &our_topic.id);
+        match (find(theirs, &our_topic.id), &base_topic) {
+            (Some(their_topic), _) => {
+                merged.push(merge_topic(base_topic.as_ref(), our_topic, &their_topic))
+            }
+            // Removed on their side, and not changed on ours since
+            (None, Some(base_topic)) if base_topic == our_topic => {}
+            (None, _) => merged.push(our_topic.clone()),
+        }
+    }
+    for their_topic in theirs {
+        if find(ours, &their_topic.id).is_some() {
+            continue;
+        }
+        match find(base, &their_topic.id) {
+            // Removed on our side, and not changed on theirs since
+            Some(base_topic) if base_topic == *their_topic => {}
+            _ => push_topic(&mut merged, their_topic.clone()),
+        }
+    }
+    merged
+}
+
+/// Merge two topic lists that have no known common version, keeping every topic
+///
+/// Topics with the same id, or with the same name, are merged with [`merge_topic`]. The result
+/// keeps the order of `ours`, followed by the topics only in `theirs`.
+pub fn merge_by_identity(ours: &[ReviewTopic], theirs: &[ReviewTopic]) -> MergeResult {
+    let mut topics: Vec<ReviewTopic> = Vec::new();
+    let mut conflicts = Vec::new();
+    let in_list = |topics: &[ReviewTopic], topic: &ReviewTopic| {
+        topics
+            .iter()
+            .any(|other| other.id == topic.id || other.topic_name == topic.topic_name)
+    };
+
+    for our_topic in ours {
+        match theirs.iter().find(|topic| topic.id == our_topic.id) {
+            Some(their_topic) => {
+                let merged_topic = merge_topic(None, our_topic, their_topic);
+                if our_topic.topic_name != their_topic.topic_name {
+                    let dropped = match merged_topic.topic_name == our_topic.topic_name {
+                        true => their_topic.topic_name.clone(),
+                        false => our_topic.topic_name.clone(),
+                    };
+                    conflicts.push(MergeConflict::Renamed {
+                        kept: merged_topic.topic_name.clone(),
+                        dropped,
+                    });
+                }
+                topics.push(merged_topic);
+            }
+            None => {
+                if !in_list(theirs, our_topic) {
+                    conflicts.push(MergeConflict::OnlyOnOneSide {
+                        topic_name: our_topic.topic_name.clone(),
+                        ours: true,
+                    });
+                }
+                topics.push(our_topic.clone());
+            }
+        }
+    }
+    for their_topic in theirs {
+        if ours.iter().any(|topic| topic.id == their_topic.id) {
+            continue;
+        }
+        if !in_list(ours, their_topic) {
+            conflicts.push(MergeConflict::OnlyOnOneSide {
+                topic_name: their_topic.topic_name.clone(),
+                ours: false,
+            });
+        }
+        push_topic(&mut topics, their_topic.clone());
+    }
+    MergeResult { topics, conflicts }
+}
+
+/// Add a topic, combining it with a topic of the same name that has another id, as happens
+/// when the same topic was added on both sides
+fn push_topic(merged: &mut Vec<ReviewTopic>, topic: ReviewTopic) {
+    match merged
+        .iter_mut()
+        .find(|merged_topic| merged_topic.topic_name == topic.topic_name)
+    {
+        Some(merged_topic) => *merged_topic = merge_topic(None, merged_topic, &topic),
+        None => merged.push(topic),
+    }
+}
+
+/// Merge two versions of a topic, keeping the id of `ours`
+pub fn merge_topic(
+    base: Option<&ReviewTopic>,
+    ours: &ReviewTopic,
+    theirs: &ReviewTopic,
+) -> ReviewTopic {
+    let ours_is_later = ours.last_reviewed >= theirs.last_reviewed;
+    let later = if ours_is_later { ours } else { theirs };
+
+    let mut review_history: Vec<_> = ours
+        .review_history
+        .iter()
+        .chain(&theirs.review_history)
+        .copied()
+        .collect();
+    review_history.sort();
+    review_history.dedup();
+
+    ReviewTopic {
+        topic_name: pick(
+            base.map(|base| &base.topic_name),
+            &ours.topic_name,
+            &theirs.topic_name,
+            ours_is_later,
+        ),
+        id: ours.id.clone(),
+        last_reviewed: later.last_reviewed,
+        next_review_gap: later.next_review_gap.clone(),
+        review_history,
+        tags: pick(
+            base.map(|base| &base.tags),
+            &ours.tags,
+            &theirs.tags,
+            ours_is_later,
+        ),
+        priority: pick(
+            base.map(|base| &base.priority),
+            &ours.priority,
+            &theirs.priority,
+            ours_is_later,
+        ),
+        scheduled_review: pick(
+            base.map(|base| &base.scheduled_review),
+            &ours.scheduled_review,
+            &theirs.scheduled_review,
+            ours_is_later,
+        ),
+        state: pick(
+            base.map(|base| &base.state),
+            &ours.state,
+            &theirs.state,
+            ours_is_later,
+        ),
+    }
+}
+
+/// The value of the side that changed a field, or of the later side when both did
+fn pick<T: PartialEq + Clone>(base: Option<&T>, ours: &T, theirs: &T, ours_is_later: bool) -> T {
+    if ours == theirs || base == Some(theirs) {
+        ours.clone()
+    } else if base == Some(ours) || !ours_is_later {
+        theirs.clone()
+    } else {
+        ours.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::merge::{merge_by_identity, merge_topics, MergeConflict};
+    use crate::topics::review_topics::{ReviewTopic, TopicState};
+    use std::slice;
+
+    fn names(topics: &[ReviewTopic]) -> Vec<&str> {
+        topics
+            .iter()
+            .map(|topic| topic.topic_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_changes_on_both_sides_are_kept() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let rust = ReviewTopic::new("rust".to_string(), &clock.before_days(10));
+        let go = ReviewTopic::new("go".to_string(), &clock.before_days(10));
+        let base = vec![rust.clone(), go.clone()];
+
+        // We review rust, they rename it and add a tag, and remove go
+        let mut our_rust = rust.clone();
+        our_rust.review(&clock.before_days(2));
+        let mut ours = vec![our_rust, go.clone()];
+        ours.push(ReviewTopic::new("zig".to_string(), &clock));
+        let mut their_rust = rust.clone();
+        their_rust.topic_name = "rust lang".to_string();
+        their_rust.tags = vec!["systems".to_string()];
+        their_rust.review(&clock.before_days(5));
+        let theirs = vec![
+            their_rust,
+            ReviewTopic::new("python".to_string(), &clock.before_days(1)),
+        ];
+
+        let merged = merge_topics(&base, &ours, &theirs);
+        assert_eq!(vec!["rust lang", "zig", "python"], names(&merged));
+        let merged_rust = &merged[0];
+        assert_eq!(rust.id, merged_rust.id);
+        assert_eq!(vec!["systems".to_string()], merged_rust.tags);
+        assert_eq!(2, merged_rust.review_history.len());
+        assert_eq!(clock.before_days(2).now(), merged_rust.last_reviewed);
+    }
+
+    #[test]
+    fn test_changed_topics_survive_removal() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let rust = ReviewTopic::new("rust".to_string(), &clock.before_days(10));
+        let mut suspended = rust.clone();
+        suspended.state = TopicState::Suspended;
+
+        let merged = merge_topics(&[rust], &[], &[suspended]);
+        assert_eq!(vec!["rust"], names(&merged));
+        assert_eq!(TopicState::Suspended, merged[0].state);
+    }
+
+    #[test]
+    fn test_merge_by_identity() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let rust = ReviewTopic::new("rust".to_string(), &clock.before_days(10));
+        let mut our_rust = rust.clone();
+        our_rust.review(&clock.before_days(4));
+        let mut their_rust = rust.clone();
+        their_rust.topic_name = "rust lang".to_string();
+        their_rust.review(&clock.before_days(1));
+        let go = ReviewTopic::new("go".to_string(), &clock.before_days(3));
+        let zig = ReviewTopic::new("zig".to_string(), &clock.before_days(3));
+
+        let merged = merge_by_identity(&[our_rust, go], &[their_rust, zig]);
+        assert_eq!(vec!["rust lang", "go", "zig"], names(&merged.topics));
+        assert_eq!(2, merged.topics[0].review_history.len());
+        assert_eq!(clock.before_days(1).now(), merged.topics[0].last_reviewed);
+        assert_eq!(
+            vec![
+                MergeConflict::Renamed {
+                    kept: "rust lang".to_string(),
+                    dropped: "rust".to_string()
+                },
+                MergeConflict::OnlyOnOneSide {
+                    topic_name: "go".to_string(),
+                    ours: true
+                },
+                MergeConflict::OnlyOnOneSide {
+                    topic_name: "zig".to_string(),
+                    ours: false
+                },
+            ],
+            merged.conflicts
+        );
+    }
+
+    #[test]
+    fn test_same_topic_added_on_both_sides() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let ours = ReviewTopic::new("rust".to_string(), &clock.before_days(3));
+        let theirs = ReviewTopic::new("rust".to_string(), &clock.before_days(2));
+        assert_ne!(ours.id, theirs.id);
+
+        let merged = merge_topics(&[], slice::from_ref(&ours), slice::from_ref(&theirs));
+        assert_eq!(1, merged.len());
+        assert_eq!(ours.id, merged[0].id);
+        assert_eq!(theirs.last_reviewed, merged[0].last_reviewed);
+    }
+}
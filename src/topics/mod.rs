@@ -1 +1,7 @@
-pub(crate) mod review_topics;
+//! Topics and everything that decides when they are due
+
+pub mod calendar;
+pub mod clock;
+pub mod merge;
+pub mod review_topics;
+pub mod scheduling;
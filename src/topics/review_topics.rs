@@ -1,167 +1,465 @@
-use chrono::{DateTime, Days, Local, NaiveDate};
+//! A single topic to review and its review gaps
+
+use crate::topics::clock::Clock;
+use crate::topics::scheduling::Scheduler;
+use chrono::{DateTime, Days, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::Formatter;
 use tabled::Tabled;
 
+/// How long to wait after a review before the topic is due again
+///
+/// How many days each gap lasts is set by [`GapLengths`].
 #[derive(Default, Debug, PartialEq, Serialize, Deserialize, Clone, Eq, Ord, PartialOrd, Tabled)]
 pub enum NextReviewGap {
+    /// 1 day by default
     #[default]
     Day,
+    /// 7 days by default
     Week,
+    /// 30 days by default
     Month,
 }
 
+/// Number of days each review gap lasts
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct GapLengths {
+    pub day: u64,
+    pub week: u64,
+    pub month: u64,
+}
+
+impl Default for GapLengths {
+    fn default() -> Self {
+        GapLengths {
+            day: 1,
+            week: 7,
+            month: 30,
+        }
+    }
+}
+
+impl GapLengths {
+    /// Length of the given gap in days
+    pub fn days(&self, gap: &NextReviewGap) -> u64 {
+        match gap {
+            NextReviewGap::Day => self.day,
+            NextReviewGap::Week => self.week,
+            NextReviewGap::Month => self.month,
+        }
+    }
+}
+
 impl fmt::Display for NextReviewGap {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            NextReviewGap::Day => {write!(f, "Day")}
-            NextReviewGap::Week => {write!(f, "Week")}
-            NextReviewGap::Month => {write!(f, "Month")}
+            NextReviewGap::Day => {
+                write!(f, "Day")
+            }
+            NextReviewGap::Week => {
+                write!(f, "Week")
+            }
+            NextReviewGap::Month => {
+                write!(f, "Month")
+            }
+        }
+    }
+}
+
+impl NextReviewGap {
+    /// The gap that follows this one after a review
+    pub fn next(&self) -> NextReviewGap {
+        match self {
+            NextReviewGap::Day => NextReviewGap::Week,
+            NextReviewGap::Week => NextReviewGap::Month,
+            NextReviewGap::Month => NextReviewGap::Month,
+        }
+    }
+
+    /// The gap to use after a review done `elapsed_days` after the previous one
+    ///
+    /// Remembering a topic for longer than its gap shows it can be left for longer, so a late
+    /// review moves on to the longest gap that does not exceed the time that actually elapsed.
+    pub fn after_review(&self, elapsed_days: i64, gap_lengths: &GapLengths) -> NextReviewGap {
+        let mut next_gap = self.next();
+        while next_gap != next_gap.next()
+            && elapsed_days >= gap_lengths.days(&next_gap.next()) as i64
+        {
+            next_gap = next_gap.next();
+        }
+        next_gap
+    }
+}
+
+/// Whether a topic can become due
+#[derive(Default, Debug, PartialEq, Serialize, Deserialize, Clone, Eq)]
+pub enum TopicState {
+    /// Due whenever its review gap says so
+    #[default]
+    Active,
+    /// Never due until the topic is made active again
+    Suspended,
+    /// Hidden for the rest of the day the topic was buried on
+    Buried(NaiveDate),
+    /// Mastered. Hidden from the views, but kept for the statistics.
+    Archived,
+}
+
+impl fmt::Display for TopicState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TopicState::Active => write!(f, "Active"),
+            TopicState::Suspended => write!(f, "Suspended"),
+            TopicState::Buried(_) => write!(f, "Buried"),
+            TopicState::Archived => write!(f, "Archived"),
+        }
+    }
+}
+
+/// Returned by [`ReviewTopic::review_on`] for a day a review cannot have happened on
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReviewDateError {
+    /// The review day has not happened yet
+    InFuture,
+    /// The review day is before the topic was last reviewed or added
+    BeforeLastReview,
+}
+
+impl fmt::Display for ReviewDateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReviewDateError::InFuture => write!(f, "The review day cannot be in the future."),
+            ReviewDateError::BeforeLastReview => write!(
+                f,
+                "The review day cannot be before the topic was last reviewed or added."
+            ),
         }
     }
 }
 
+/// A topic to review, together with when it was reviewed and when it is due next
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(from = "StoredReviewTopic")]
 pub struct ReviewTopic {
+    /// Name of the topic, unique within a storage
     pub topic_name: String,
-    pub last_reviewed: DateTime<Local>,
+    /// Identity of the topic that stays the same when it is renamed
+    pub id: String,
+    /// When the topic was last reviewed, or added if it was never reviewed
+    pub last_reviewed: DateTime<Utc>,
+    /// Gap between the last review and the next one
     pub next_review_gap: NextReviewGap,
+    /// Every review of the topic, oldest first
+    pub review_history: Vec<DateTime<Utc>>,
+    /// Tags to group the topic by
+    pub tags: Vec<String>,
+    /// Higher priority topics come first when ordering by priority
+    pub priority: u8,
+    /// Day the topic is due on instead of the one given by its review gap
+    pub scheduled_review: Option<NaiveDate>,
+    /// Whether the topic can become due
+    pub state: TopicState,
 }
 
-impl PartialOrd for ReviewTopic {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+/// A topic as it was written by any version of revue, in the storage, the journal or the event
+/// log. Fields added later are missing from older files.
+#[derive(Deserialize)]
+struct StoredReviewTopic {
+    topic_name: String,
+    #[serde(default)]
+    id: String,
+    last_reviewed: DateTime<Utc>,
+    next_review_gap: NextReviewGap,
+    #[serde(default)]
+    review_history: Vec<DateTime<Utc>>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    priority: u8,
+    #[serde(default)]
+    scheduled_review: Option<NaiveDate>,
+    #[serde(default)]
+    state: TopicState,
 }
 
-impl Ord for ReviewTopic {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match self.days_until_review().cmp(&other.days_until_review()) {
-            Ordering::Less => Ordering::Less,
-            Ordering::Equal => self.topic_name.cmp(&other.topic_name),
-            Ordering::Greater => Ordering::Greater,
+impl From<StoredReviewTopic> for ReviewTopic {
+    fn from(stored: StoredReviewTopic) -> Self {
+        // Topics written before topics had ids get the same one wherever they are read
+        let id = match stored.id.is_empty() {
+            true => id_from_name(&stored.topic_name),
+            false => stored.id,
+        };
+        ReviewTopic {
+            topic_name: stored.topic_name,
+            id,
+            last_reviewed: stored.last_reviewed,
+            next_review_gap: stored.next_review_gap,
+            review_history: stored.review_history,
+            tags: stored.tags,
+            priority: stored.priority,
+            scheduled_review: stored.scheduled_review,
+            state: stored.state,
         }
     }
 }
 
 impl ReviewTopic {
-    pub fn new(topic_name: String) -> Self {
+    /// A new topic, due the day after it was added
+    pub fn new(topic_name: String, clock: &dyn Clock) -> Self {
+        let now = clock.now();
         ReviewTopic {
+            id: hash_id(&format!("{topic_name}@{}", now.to_rfc3339())),
             topic_name,
-            last_reviewed: Local::now(),
+            last_reviewed: now,
             next_review_gap: Default::default(),
+            review_history: Vec::new(),
+            tags: Vec::new(),
+            priority: 0,
+            scheduled_review: None,
+            state: TopicState::Active,
+        }
+    }
+
+    /// The state the topic is in today. A buried topic is active again the day after burying it.
+    pub fn current_state(&self, clock: &dyn Clock) -> TopicState {
+        match self.state {
+            TopicState::Buried(buried_day) if buried_day < clock.today() => TopicState::Active,
+            _ => self.state.clone(),
+        }
+    }
+
+    /// A topic is new until it has been reviewed for the first time
+    ///
+    /// Topics written before the reviews were recorded have no history either, but their gap
+    /// grew past a day with their reviews.
+    pub fn is_new(&self) -> bool {
+        self.review_history.is_empty() && self.next_review_gap == NextReviewGap::Day
+    }
+
+    /// Number of times the topic was reviewed on the given day
+    pub fn reviews_on(&self, day: NaiveDate, clock: &dyn Clock) -> usize {
+        self.review_history
+            .iter()
+            .filter(|reviewed| clock.day_of(**reviewed) == day)
+            .count()
+    }
+
+    /// Order by the day the topics are due on, then by name
+    pub fn cmp_due(&self, other: &ReviewTopic, clock: &dyn Clock) -> Ordering {
+        match self.review_day(clock).cmp(&other.review_day(clock)) {
+            Ordering::Less => Ordering::Less,
+            Ordering::Equal => self.topic_name.cmp(&other.topic_name),
+            Ordering::Greater => Ordering::Greater,
         }
     }
 
-    pub fn review(&mut self) {
-        match self.next_review_gap {
-            NextReviewGap::Day => self.next_review_gap = NextReviewGap::Week,
-            NextReviewGap::Week => self.next_review_gap = NextReviewGap::Month,
-            NextReviewGap::Month => {}
+    /// Mark the topic as reviewed now and move on to the next review gap
+    pub fn review(&mut self, clock: &dyn Clock) {
+        self.review_at(clock.now(), Scheduler::default(), clock);
+    }
+
+    /// Log a review done on an earlier day, as if it had been logged on that day
+    pub fn review_on(&mut self, day: NaiveDate, clock: &dyn Clock) -> Result<(), ReviewDateError> {
+        self.review_with(Some(day), Scheduler::default(), clock)
+    }
+
+    /// Log a review done today, or on the given earlier day, moving the review gap on the way
+    /// the scheduler says
+    pub fn review_with(
+        &mut self,
+        reviewed_on: Option<NaiveDate>,
+        scheduler: Scheduler,
+        clock: &dyn Clock,
+    ) -> Result<(), ReviewDateError> {
+        let Some(day) = reviewed_on else {
+            self.review_at(clock.now(), scheduler, clock);
+            return Ok(());
+        };
+        if day > clock.today() {
+            return Err(ReviewDateError::InFuture);
+        }
+        if day < clock.day_of(self.last_reviewed) {
+            return Err(ReviewDateError::BeforeLastReview);
         }
-        self.last_reviewed = Local::now();
+
+        match day == clock.today() {
+            true => self.review_at(clock.now(), scheduler, clock),
+            false => self.review_at(clock.calendar().instant_on(day), scheduler, clock),
+        }
+        Ok(())
+    }
+
+    fn review_at(&mut self, reviewed: DateTime<Utc>, scheduler: Scheduler, clock: &dyn Clock) {
+        let elapsed_days = clock
+            .day_of(reviewed)
+            .signed_duration_since(clock.day_of(self.last_reviewed))
+            .num_days();
+        self.next_review_gap = scheduler.next_gap(
+            &self.next_review_gap,
+            elapsed_days,
+            &clock.calendar().gap_lengths,
+        );
+        self.scheduled_review = None;
+        self.last_reviewed = reviewed;
+        self.review_history.push(reviewed);
+        self.review_history.sort();
+    }
+
+    /// Make the topic due on the given day, whatever its review gap says
+    pub fn reschedule(&mut self, review_day: NaiveDate) {
+        self.scheduled_review = Some(review_day);
+    }
+
+    /// Go back to the first review gap, making the topic due tomorrow
+    pub fn reset(&mut self, clock: &dyn Clock) {
+        self.next_review_gap = NextReviewGap::default();
+        self.scheduled_review = clock.today().checked_add_days(Days::new(
+            clock.calendar().gap_lengths.days(&self.next_review_gap),
+        ));
     }
 
-    pub fn is_time_to_review(&self) -> bool {
-        let days_until_review = self.days_until_review();
+    /// Whether the topic is active and due today or overdue
+    pub fn is_time_to_review(&self, clock: &dyn Clock) -> bool {
+        if self.current_state(clock) != TopicState::Active {
+            return false;
+        }
+        let days_until_review = self.days_until_review(clock);
         // Less than 1 rather than 0 because of day offset in days_until_review
         let time_to_review: bool = days_until_review.le(&0);
         time_to_review
     }
 
-    pub fn days_until_review(&self) -> i64 {
-        let current_date: NaiveDate = Local::now().date_naive();
+    /// The day the topic is due on
+    ///
+    /// All due dates are worked out here, so they always agree with the days shown to the user.
+    pub fn review_day(&self, clock: &dyn Clock) -> NaiveDate {
+        if let Some(scheduled_review) = self.scheduled_review {
+            return scheduled_review;
+        }
 
-        let days_to_add = match self.next_review_gap {
-            NextReviewGap::Day => 1,
-            NextReviewGap::Week => 7,
-            NextReviewGap::Month => 30,
-        };
-        let review_day: NaiveDate = match self.last_reviewed.date_naive().checked_add_days(Days::new(days_to_add)) {
+        let days_to_add = clock.calendar().gap_lengths.days(&self.next_review_gap);
+        match clock
+            .day_of(self.last_reviewed)
+            .checked_add_days(Days::new(days_to_add))
+        {
             None => {
                 panic!("Failed to get review day");
             }
             Some(review_day) => review_day,
-        };
-
-        review_day.signed_duration_since(current_date).num_days()
-    }
-
-    #[cfg(test)]
-    pub fn add_days(&mut self, num: u64) {
-        match self.last_reviewed.checked_add_days(Days::new(num)) {
-            None => {}
-            Some(new_time) => {
-                self.last_reviewed = new_time;
-            }
         }
     }
 
-    #[cfg(test)]
-    pub fn sub_days(&mut self, num: u64) {
-        match self.last_reviewed.checked_sub_days(Days::new(num)) {
-            None => {}
-            Some(new_time) => {
-                self.last_reviewed = new_time;
-            }
-        }
+    /// Days from today until the topic is due. Negative when it is overdue.
+    pub fn days_until_review(&self, clock: &dyn Clock) -> i64 {
+        let current_date: NaiveDate = clock.today();
+
+        self.review_day(clock)
+            .signed_duration_since(current_date)
+            .num_days()
     }
 }
 
+/// The id of a topic written before topics had ids, so it is the same every time it is loaded
+pub fn id_from_name(topic_name: &str) -> String {
+    hash_id(topic_name)
+}
+
+/// FNV-1a, which unlike the standard library hasher is the same across Rust versions
+fn hash_id(seed: &str) -> String {
+    let hash = seed.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::topics::review_topics::{NextReviewGap, ReviewTopic};
-    use chrono::Local;
+    use crate::topics::calendar::Calendar;
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::review_topics::{
+        GapLengths, NextReviewGap, ReviewDateError, ReviewTopic, TopicState,
+    };
+    use chrono::{DateTime, Days};
+    use chrono_tz::Europe::Berlin;
+    use std::cmp::Ordering;
+
+    fn berlin_clock(now: &str) -> FixedClock {
+        let calendar = Calendar {
+            timezone: Some(Berlin),
+            ..Calendar::default()
+        };
+        FixedClock::new(
+            DateTime::parse_from_rfc3339(now).unwrap().to_utc(),
+            calendar,
+        )
+    }
 
     #[test]
     fn test_default_topic() {
-        let default_review_topic: ReviewTopic = ReviewTopic::new(String::from("test"));
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let default_review_topic: ReviewTopic = ReviewTopic::new(String::from("test"), &clock);
 
         let topic_name_is_not_empty = !default_review_topic.topic_name.is_empty();
         assert!(topic_name_is_not_empty);
 
-        let is_not_time_to_review = !default_review_topic.is_time_to_review();
-        assert!(is_not_time_to_review);
+        assert_eq!(clock.now(), default_review_topic.last_reviewed);
+        assert!(!default_review_topic.is_time_to_review(&clock));
+        assert!(default_review_topic.is_time_to_review(&clock.after_days(1)));
     }
 
     #[test]
     fn test_updating_review_gap() {
-        let mut review_topic: ReviewTopic = ReviewTopic::new(String::from("Review Topic Name"));
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut review_topic: ReviewTopic =
+            ReviewTopic::new(String::from("Review Topic Name"), &clock);
 
         assert_eq!(review_topic.next_review_gap, NextReviewGap::Day);
-        review_topic.review();
+        review_topic.review(&clock.after_days(1));
         assert_eq!(review_topic.next_review_gap, NextReviewGap::Week);
-        review_topic.review();
+        assert_eq!(7, review_topic.days_until_review(&clock.after_days(1)));
+        review_topic.review(&clock.after_days(8));
         assert_eq!(review_topic.next_review_gap, NextReviewGap::Month);
+        let gap = review_topic.review_history[1] - review_topic.review_history[0];
+        assert_eq!(7, gap.num_days());
     }
 
     #[test]
     fn test_changing_days() {
-        let mut review_topic: ReviewTopic = ReviewTopic::new("test_topic".to_string());
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let review_topic: ReviewTopic = ReviewTopic::new("test_topic".to_string(), &clock);
 
-        assert_eq!(
-            Local::now().date_naive(),
-            review_topic.last_reviewed.date_naive()
-        );
+        assert_eq!(clock.today(), clock.day_of(review_topic.last_reviewed));
 
-        review_topic.sub_days(1);
-
-        let delta_time = Local::now().signed_duration_since(review_topic.last_reviewed);
+        let delta_time = clock
+            .after_days(1)
+            .now()
+            .signed_duration_since(review_topic.last_reviewed);
         assert_eq!(1, delta_time.num_days());
 
-        review_topic.add_days(1);
-
-        let delta_time = Local::now().signed_duration_since(review_topic.last_reviewed);
-        assert_eq!(0, delta_time.num_days());
+        let delta_time = clock
+            .after_days(3)
+            .now()
+            .signed_duration_since(review_topic.last_reviewed);
+        assert_eq!(3, delta_time.num_days());
+        let days_between = clock.after_days(3).today() - clock.day_of(review_topic.last_reviewed);
+        assert_eq!(3, days_between.num_days());
     }
 
     #[test]
     fn test_review() {
-        let mut review_topic: ReviewTopic = ReviewTopic::new("test1".to_owned());
-        review_topic.review();
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut review_topic: ReviewTopic = ReviewTopic::new("test1".to_owned(), &clock);
+        let reviewing = clock.after_days(2);
+        review_topic.review(&reviewing);
         assert_eq!(NextReviewGap::Week, review_topic.next_review_gap);
+        assert_eq!(reviewing.now(), review_topic.last_reviewed);
+        assert_eq!(
+            vec![review_topic.last_reviewed],
+            review_topic.review_history
+        );
+        assert_eq!(7, review_topic.days_until_review(&reviewing));
     }
 
     #[test]
@@ -170,25 +468,179 @@ mod tests {
         assert!(NextReviewGap::Week < NextReviewGap::Month);
         assert_eq!(NextReviewGap::Day, NextReviewGap::Day);
 
-        let mut topic1: ReviewTopic = ReviewTopic::new("z".to_owned());
-        topic1.review();
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut topic1: ReviewTopic = ReviewTopic::new("z".to_owned(), &clock);
+        topic1.review(&clock.after_days(1));
 
-        let topic2: ReviewTopic = ReviewTopic::new("a".to_owned());
+        let topic2: ReviewTopic = ReviewTopic::new("a".to_owned(), &clock);
 
-        assert!(topic1 > topic2);
+        let comparing = clock.after_days(1);
+        assert_eq!(7, topic1.days_until_review(&comparing));
+        assert_eq!(0, topic2.days_until_review(&comparing));
+        assert_eq!(Ordering::Greater, topic1.cmp_due(&topic2, &comparing));
     }
 
     #[test]
     fn test_is_review_day() {
-        let mut topic = ReviewTopic::new("topic".to_owned());
-        assert_eq!(1, topic.days_until_review());
-        assert!(!topic.is_time_to_review());
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let topic = ReviewTopic::new("topic".to_owned(), &clock);
+        assert_eq!(1, topic.days_until_review(&clock));
+        assert!(!topic.is_time_to_review(&clock));
+
+        let tomorrow = clock.after_days(1);
+        assert_eq!(0, topic.days_until_review(&tomorrow));
+        assert!(topic.is_time_to_review(&tomorrow));
+    }
+
+    #[test]
+    fn test_days_until_review() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut topic = ReviewTopic::new("topic".to_owned(), &clock);
+        topic.review(&clock);
+        assert_eq!(7, topic.days_until_review(&clock));
+        assert_eq!(clock.today() + Days::new(7), topic.review_day(&clock));
+
+        let later = clock.after_days(10);
+        assert_eq!(-3, topic.days_until_review(&later));
+
+        topic.scheduled_review = Some(later.today() + Days::new(2));
+        assert_eq!(2, topic.days_until_review(&later));
+        assert!(!topic.is_time_to_review(&later));
+    }
+
+    #[test]
+    fn test_topic_states() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut topic = ReviewTopic::new("topic".to_owned(), &clock.before_days(3));
+        assert!(topic.is_time_to_review(&clock));
 
-        topic.sub_days(1);
-        assert_eq!(0, topic.days_until_review());
-        assert!(topic.is_time_to_review());
+        topic.state = TopicState::Suspended;
+        assert!(!topic.is_time_to_review(&clock));
+
+        topic.state = TopicState::Buried(clock.today());
+        assert!(!topic.is_time_to_review(&clock));
+        assert_eq!(
+            TopicState::Active,
+            topic.current_state(&clock.after_days(1))
+        );
+        assert!(topic.is_time_to_review(&clock.after_days(1)));
+
+        topic.state = TopicState::Archived;
+        assert!(!topic.is_time_to_review(&clock));
+    }
+
+    #[test]
+    fn test_overdue_review() {
+        let gap_lengths = GapLengths::default();
+        let after_review =
+            |gap: NextReviewGap, elapsed_days| gap.after_review(elapsed_days, &gap_lengths);
+        assert_eq!(NextReviewGap::Week, after_review(NextReviewGap::Day, 1));
+        assert_eq!(NextReviewGap::Week, after_review(NextReviewGap::Day, 10));
+        assert_eq!(NextReviewGap::Month, after_review(NextReviewGap::Day, 35));
+        assert_eq!(NextReviewGap::Month, after_review(NextReviewGap::Week, 47));
+        assert_eq!(NextReviewGap::Month, after_review(NextReviewGap::Month, 90));
+
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut topic = ReviewTopic::new("late".to_owned(), &clock.before_days(40));
+        topic.scheduled_review = Some(clock.today());
+        topic.review(&clock);
+        assert_eq!(NextReviewGap::Month, topic.next_review_gap);
+        assert_eq!(None, topic.scheduled_review);
+    }
+
+    #[test]
+    fn test_gap_lengths() {
+        let calendar = Calendar {
+            gap_lengths: GapLengths {
+                day: 2,
+                week: 5,
+                month: 20,
+            },
+            ..Calendar::default()
+        };
+        let clock = FixedClock::new(FixedClock::utc("2024-05-10T12:00:00Z").now(), calendar);
+        let mut topic = ReviewTopic::new("short".to_owned(), &clock);
+        assert_eq!(2, topic.days_until_review(&clock));
+
+        topic.review(&clock.after_days(2));
+        assert_eq!(NextReviewGap::Week, topic.next_review_gap);
+        assert_eq!(5, topic.days_until_review(&clock.after_days(2)));
+
+        // Remembering a topic for 20 days skips to the month, which is only 20 days long here
+        let mut late = ReviewTopic::new("late".to_owned(), &clock);
+        late.review(&clock.after_days(20));
+        assert_eq!(NextReviewGap::Month, late.next_review_gap);
+    }
+
+    #[test]
+    fn test_due_days_across_dst() {
+        // Clocks go forward in Berlin on 2024-03-31, so a week later is an hour less than 7 days
+        let mut topic =
+            ReviewTopic::new("dst".to_owned(), &berlin_clock("2024-03-20T23:30:00+01:00"));
+        topic.review(&berlin_clock("2024-03-28T23:30:00+01:00"));
+        assert_eq!(NextReviewGap::Week, topic.next_review_gap);
+
+        let clock = berlin_clock("2024-04-04T00:30:00+02:00");
+        assert_eq!(0, topic.days_until_review(&clock));
+        assert!(topic.is_time_to_review(&clock));
+        assert!(!topic.is_time_to_review(&berlin_clock("2024-04-03T23:30:00+02:00")));
+
+        // Clocks go back on 2024-10-27, so a day later is an hour more than 24 hours
+        let topic = ReviewTopic::new("dst".to_owned(), &berlin_clock("2024-10-26T23:30:00+02:00"));
+        assert_eq!(
+            1,
+            topic.days_until_review(&berlin_clock("2024-10-26T23:59:00+02:00"))
+        );
+        assert_eq!(
+            0,
+            topic.days_until_review(&berlin_clock("2024-10-27T23:59:00+01:00"))
+        );
+        assert_eq!(
+            -1,
+            topic.days_until_review(&berlin_clock("2024-10-28T00:01:00+01:00"))
+        );
     }
 
     #[test]
-    fn test_days_until_review() {}
+    fn test_review_on_earlier_day() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut topic = ReviewTopic::new("backdated".to_owned(), &clock.before_days(8));
+
+        assert_eq!(
+            Err(ReviewDateError::InFuture),
+            topic.review_on(clock.today() + Days::new(1), &clock)
+        );
+        assert_eq!(
+            Err(ReviewDateError::BeforeLastReview),
+            topic.review_on(clock.today() - Days::new(9), &clock)
+        );
+
+        // Reviewed 7 days after adding it, but only logged today
+        assert_eq!(
+            Ok(()),
+            topic.review_on(clock.today() - Days::new(1), &clock)
+        );
+        assert_eq!(NextReviewGap::Week, topic.next_review_gap);
+        assert_eq!(
+            clock.today() - Days::new(1),
+            clock.day_of(topic.last_reviewed)
+        );
+        assert_eq!(6, topic.days_until_review(&clock));
+    }
+
+    #[test]
+    fn test_reschedule_and_reset() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut topic = ReviewTopic::new("topic".to_owned(), &clock);
+        topic.review(&clock);
+        topic.review(&clock);
+        assert_eq!(NextReviewGap::Month, topic.next_review_gap);
+
+        topic.reschedule(clock.today() + Days::new(3));
+        assert_eq!(3, topic.days_until_review(&clock));
+
+        topic.reset(&clock);
+        assert_eq!(NextReviewGap::Day, topic.next_review_gap);
+        assert_eq!(1, topic.days_until_review(&clock));
+    }
 }
@@ -0,0 +1,461 @@
+//! Which topics are due today, in which order, and how schedules move around
+
+use crate::topics::clock::Clock;
+use crate::topics::review_topics::{GapLengths, NextReviewGap, ReviewTopic};
+use chrono::{Days, NaiveDate};
+use clap::ValueEnum;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::fmt::Formatter;
+use std::hash::{Hash, Hasher};
+
+/// Order in which today's topics are shown
+#[derive(Default, Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewOrder {
+    /// Most overdue topics first
+    #[default]
+    MostOverdue,
+    /// Shuffled, but stable for the whole day
+    Random,
+    /// Topics with the shortest review gap first
+    ShortestInterval,
+    /// Grouped by their first tag
+    Tag,
+    /// Highest priority first
+    Priority,
+}
+
+impl fmt::Display for ReviewOrder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReviewOrder::MostOverdue => write!(f, "most-overdue"),
+            ReviewOrder::Random => write!(f, "random"),
+            ReviewOrder::ShortestInterval => write!(f, "shortest-interval"),
+            ReviewOrder::Tag => write!(f, "tag"),
+            ReviewOrder::Priority => write!(f, "priority"),
+        }
+    }
+}
+
+/// How the review gap moves on after a review
+#[derive(Default, Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Eq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scheduler {
+    /// A late review skips the gaps shorter than the time that actually elapsed
+    #[default]
+    OverdueAware,
+    /// Every review moves on to the next gap, however late it was
+    Fixed,
+}
+
+impl fmt::Display for Scheduler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Scheduler::OverdueAware => write!(f, "overdue-aware"),
+            Scheduler::Fixed => write!(f, "fixed"),
+        }
+    }
+}
+
+impl Scheduler {
+    /// The gap to use after a review done `elapsed_days` after the previous one
+    pub fn next_gap(
+        &self,
+        gap: &NextReviewGap,
+        elapsed_days: i64,
+        gap_lengths: &GapLengths,
+    ) -> NextReviewGap {
+        match self {
+            Scheduler::OverdueAware => gap.after_review(elapsed_days, gap_lengths),
+            Scheduler::Fixed => gap.next(),
+        }
+    }
+}
+
+/// Settings deciding which of the due topics are shown each day and in which order
+#[derive(Default, Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ScheduleSettings {
+    /// Maximum number of already reviewed topics to show per day. `None` means no limit.
+    #[serde(default)]
+    pub max_reviews_per_day: Option<usize>,
+    /// Maximum number of never reviewed topics to show per day. `None` means no limit.
+    #[serde(default)]
+    pub max_new_per_day: Option<usize>,
+    /// Order in which today's topics are shown
+    #[serde(default)]
+    pub review_order: ReviewOrder,
+    /// How the review gap moves on after a review
+    #[serde(default)]
+    pub scheduler: Scheduler,
+}
+
+/// A break from reviewing. No topics are due while the reviews are paused.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Pause {
+    /// Day the reviews were paused on
+    pub since: NaiveDate,
+    /// Day the reviews resume on, the first day that is not paused
+    pub until: NaiveDate,
+}
+
+impl Pause {
+    /// Number of days the reviews have been paused for, counting up to `today`
+    pub fn days_paused(&self, today: NaiveDate) -> u64 {
+        let end = today.min(self.until);
+        end.signed_duration_since(self.since).num_days().max(0) as u64
+    }
+
+    /// Whether the reviews should be resumed by `today`, which they are on the day `until`
+    pub fn is_over(&self, today: NaiveDate) -> bool {
+        today >= self.until
+    }
+}
+
+/// Move the schedule of every topic not reviewed during the pause forward by the paused days
+pub fn shift_schedules(review_list: &mut [ReviewTopic], pause: &Pause, clock: &dyn Clock) {
+    let paused_days = pause.days_paused(clock.today());
+
+    review_list
+        .iter_mut()
+        .filter(|review_topic| clock.day_of(review_topic.last_reviewed) <= pause.since)
+        .for_each(|review_topic| {
+            review_topic.last_reviewed = clock
+                .calendar()
+                .add_days(review_topic.last_reviewed, paused_days as i64);
+            review_topic.scheduled_review =
+                review_topic.scheduled_review.and_then(|scheduled_review| {
+                    scheduled_review.checked_add_days(Days::new(paused_days))
+                });
+        });
+    debug!("Shifted schedules by {} days", paused_days);
+}
+
+/// Get the topics to review today, ordered and limited according to the settings
+///
+/// Reviews already done today count towards the daily limits.
+pub fn get_today_list(
+    review_list: &[ReviewTopic],
+    settings: &ScheduleSettings,
+    clock: &dyn Clock,
+) -> Vec<ReviewTopic> {
+    let today: NaiveDate = clock.today();
+
+    let mut due_list: Vec<ReviewTopic> = review_list
+        .iter()
+        .filter(|review_topic| review_topic.is_time_to_review(clock))
+        .cloned()
+        .collect();
+    sort_review_list(&mut due_list, settings.review_order, clock);
+
+    let mut reviews_left = remaining(
+        settings.max_reviews_per_day,
+        reviews_done_on(review_list, today, clock),
+    );
+    let mut new_left = remaining(
+        settings.max_new_per_day,
+        new_done_on(review_list, today, clock),
+    );
+
+    let mut today_list: Vec<ReviewTopic> = Vec::new();
+    due_list.into_iter().for_each(|review_topic| {
+        let left = match review_topic.is_new() {
+            true => &mut new_left,
+            false => &mut reviews_left,
+        };
+        match left {
+            None => today_list.push(review_topic),
+            Some(0) => {}
+            Some(count) => {
+                *count -= 1;
+                today_list.push(review_topic);
+            }
+        }
+    });
+
+    debug!("Today list: {:#?}", today_list);
+    today_list
+}
+
+/// Sort topics in the given review order
+pub fn sort_review_list(
+    review_list: &mut [ReviewTopic],
+    review_order: ReviewOrder,
+    clock: &dyn Clock,
+) {
+    match review_order {
+        ReviewOrder::MostOverdue => review_list.sort_by(|topic, other| topic.cmp_due(other, clock)),
+        ReviewOrder::Random => {
+            let today: NaiveDate = clock.today();
+            review_list.sort_by_cached_key(|topic| shuffle_key(&topic.topic_name, today))
+        }
+        ReviewOrder::ShortestInterval => review_list.sort_by(|topic, other| {
+            topic
+                .next_review_gap
+                .cmp(&other.next_review_gap)
+                .then_with(|| topic.cmp_due(other, clock))
+        }),
+        ReviewOrder::Tag => review_list.sort_by(|topic, other| {
+            compare_first_tag(topic, other).then_with(|| topic.cmp_due(other, clock))
+        }),
+        ReviewOrder::Priority => review_list.sort_by(|topic, other| {
+            other
+                .priority
+                .cmp(&topic.priority)
+                .then_with(|| topic.cmp_due(other, clock))
+        }),
+    }
+}
+
+/// Spread the overdue topics over the next `days` days, starting today
+///
+/// The topics are handed out in review order, so with the default order the most overdue topics
+/// are due first. Topics that are due today but not overdue are left as they are.
+///
+/// returns: The number of topics that were spread
+pub fn spread_overdue(
+    review_list: &mut [ReviewTopic],
+    days: u64,
+    review_order: ReviewOrder,
+    clock: &dyn Clock,
+) -> usize {
+    let today: NaiveDate = clock.today();
+
+    let mut overdue_list: Vec<ReviewTopic> = review_list
+        .iter()
+        .filter(|review_topic| {
+            review_topic.is_time_to_review(clock) && review_topic.days_until_review(clock) < 0
+        })
+        .cloned()
+        .collect();
+    sort_review_list(&mut overdue_list, review_order, clock);
+
+    let topics_per_day = overdue_list.len().div_ceil(days.max(1) as usize);
+    overdue_list
+        .iter()
+        .enumerate()
+        .for_each(|(index, overdue_topic)| {
+            let catch_up_day = today + Days::new((index / topics_per_day) as u64);
+            if let Some(review_topic) = review_list
+                .iter_mut()
+                .find(|topic| topic.topic_name == overdue_topic.topic_name)
+            {
+                review_topic.scheduled_review = Some(catch_up_day);
+            }
+        });
+
+    debug!("Spread overdue topics: {:#?}", overdue_list);
+    overdue_list.len()
+}
+
+fn compare_first_tag(topic: &ReviewTopic, other: &ReviewTopic) -> Ordering {
+    // Untagged topics go last
+    match (topic.tags.first(), other.tags.first()) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(tag), Some(other_tag)) => tag.cmp(other_tag),
+    }
+}
+
+fn shuffle_key(topic_name: &str, today: NaiveDate) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    today.hash(&mut hasher);
+    topic_name.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn remaining(limit: Option<usize>, done: usize) -> Option<usize> {
+    limit.map(|limit| limit.saturating_sub(done))
+}
+
+fn reviews_done_on(review_list: &[ReviewTopic], day: NaiveDate, clock: &dyn Clock) -> usize {
+    review_list
+        .iter()
+        .map(|topic| match is_first_review_on(topic, day, clock) {
+            true => topic.reviews_on(day, clock) - 1,
+            false => topic.reviews_on(day, clock),
+        })
+        .sum()
+}
+
+fn new_done_on(review_list: &[ReviewTopic], day: NaiveDate, clock: &dyn Clock) -> usize {
+    review_list
+        .iter()
+        .filter(|topic| is_first_review_on(topic, day, clock))
+        .count()
+}
+
+fn is_first_review_on(topic: &ReviewTopic, day: NaiveDate, clock: &dyn Clock) -> bool {
+    match topic.review_history.first() {
+        None => false,
+        Some(first_review) => clock.day_of(*first_review) == day,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topics::clock::{Clock, FixedClock};
+    use crate::topics::review_topics::{NextReviewGap, ReviewTopic};
+    use crate::topics::scheduling::{
+        get_today_list, shift_schedules, sort_review_list, spread_overdue, Pause, ReviewOrder,
+        ScheduleSettings,
+    };
+    use chrono::Days;
+
+    fn due_topic(name: &str, overdue_days: u64, clock: &FixedClock) -> ReviewTopic {
+        ReviewTopic::new(name.to_owned(), &clock.before_days(1 + overdue_days))
+    }
+
+    fn names(list: &[ReviewTopic]) -> Vec<&str> {
+        list.iter().map(|topic| topic.topic_name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_default_settings_show_everything_due() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let list = vec![
+            due_topic("b", 0, &clock),
+            due_topic("a", 3, &clock),
+            ReviewTopic::new("c".to_owned(), &clock),
+        ];
+        let today_list = get_today_list(&list, &ScheduleSettings::default(), &clock);
+        assert_eq!(vec!["a", "b"], names(&today_list));
+    }
+
+    #[test]
+    fn test_daily_limits() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut reviewed = due_topic("reviewed", 2, &clock);
+        reviewed.review_history.push(reviewed.last_reviewed);
+        let mut reviewed_today = ReviewTopic::new("reviewed_today".to_owned(), &clock);
+        reviewed_today.review(&clock);
+        reviewed_today.review(&clock);
+        let list = vec![
+            due_topic("new1", 2, &clock),
+            due_topic("new2", 1, &clock),
+            reviewed,
+            reviewed_today,
+        ];
+
+        let settings = ScheduleSettings {
+            max_reviews_per_day: Some(2),
+            max_new_per_day: Some(2),
+            review_order: ReviewOrder::MostOverdue,
+            ..ScheduleSettings::default()
+        };
+        // One review and one new topic were already done today
+        let today_list = get_today_list(&list, &settings, &clock);
+        assert_eq!(vec!["new1", "reviewed"], names(&today_list));
+
+        // The limits start over the next day
+        let today_list = get_today_list(&list, &settings, &clock.after_days(1));
+        assert_eq!(vec!["new1", "reviewed", "new2"], names(&today_list));
+    }
+
+    #[test]
+    fn test_topics_reviewed_before_the_history_are_not_new() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut legacy = due_topic("legacy", 2, &clock);
+        legacy.next_review_gap = NextReviewGap::Week;
+        legacy.last_reviewed = clock.before_days(9).now();
+        let list = vec![due_topic("new", 2, &clock), legacy];
+
+        let settings = ScheduleSettings {
+            max_reviews_per_day: Some(1),
+            max_new_per_day: Some(0),
+            ..ScheduleSettings::default()
+        };
+        let today_list = get_today_list(&list, &settings, &clock);
+        assert_eq!(vec!["legacy"], names(&today_list));
+    }
+
+    #[test]
+    fn test_review_orders() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let mut high_priority = due_topic("high_priority", 0, &clock);
+        high_priority.priority = 5;
+        high_priority.tags = vec!["rust".to_owned()];
+        let mut weekly = due_topic("weekly", 7, &clock);
+        weekly.next_review_gap = NextReviewGap::Week;
+        weekly.tags = vec!["math".to_owned()];
+        let overdue = due_topic("overdue", 10, &clock);
+        let list = vec![high_priority, weekly, overdue];
+
+        let mut sorted = list.clone();
+        sort_review_list(&mut sorted, ReviewOrder::Priority, &clock);
+        assert_eq!("high_priority", sorted[0].topic_name);
+
+        sort_review_list(&mut sorted, ReviewOrder::ShortestInterval, &clock);
+        assert_eq!(vec!["overdue", "high_priority", "weekly"], names(&sorted));
+
+        sort_review_list(&mut sorted, ReviewOrder::Tag, &clock);
+        assert_eq!(vec!["weekly", "high_priority", "overdue"], names(&sorted));
+
+        let mut shuffled = list.clone();
+        sort_review_list(&mut shuffled, ReviewOrder::Random, &clock);
+        let mut shuffled_again = list.clone();
+        shuffled_again.reverse();
+        sort_review_list(&mut shuffled_again, ReviewOrder::Random, &clock);
+        assert_eq!(shuffled, shuffled_again);
+    }
+
+    #[test]
+    fn test_spread_overdue() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let today = clock.today();
+        let mut list: Vec<ReviewTopic> = (1..=5)
+            .map(|overdue_days| due_topic(&format!("topic{overdue_days}"), overdue_days, &clock))
+            .collect();
+        list.push(due_topic("due_today", 0, &clock));
+
+        assert_eq!(
+            5,
+            spread_overdue(&mut list, 3, ReviewOrder::MostOverdue, &clock)
+        );
+
+        let scheduled: Vec<_> = list.iter().map(|topic| topic.scheduled_review).collect();
+        assert_eq!(
+            vec![
+                Some(today + Days::new(2)),
+                Some(today + Days::new(1)),
+                Some(today + Days::new(1)),
+                Some(today),
+                Some(today),
+                None,
+            ],
+            scheduled
+        );
+        let today_list = get_today_list(&list, &ScheduleSettings::default(), &clock);
+        assert_eq!(vec!["due_today", "topic4", "topic5"], names(&today_list));
+    }
+
+    #[test]
+    fn test_shift_schedules() {
+        let clock = FixedClock::utc("2024-05-10T12:00:00Z");
+        let today = clock.today();
+        let pause = Pause {
+            since: today - Days::new(10),
+            until: today + Days::new(4),
+        };
+        assert_eq!(10, pause.days_paused(today));
+        assert_eq!(14, pause.days_paused(today + Days::new(20)));
+        assert!(!pause.is_over(today));
+        assert!(!pause.is_over(today + Days::new(3)));
+        assert!(pause.is_over(today + Days::new(4)));
+        assert_eq!(14, pause.days_paused(today + Days::new(4)));
+
+        // Due the day the reviews were paused
+        let mut list = vec![
+            due_topic("paused", 9, &clock),
+            ReviewTopic::new("added_during_pause".to_owned(), &clock),
+        ];
+        shift_schedules(&mut list, &pause, &clock);
+
+        assert_eq!(1, list[0].days_until_review(&clock));
+        assert_eq!(1, list[1].days_until_review(&clock));
+    }
+}
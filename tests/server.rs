@@ -0,0 +1,210 @@
+use revue::io::server::Server;
+use revue::{PreviousStorage, ReviewTopic, Storage, SystemClock};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+
+/// Start a server on a free port with an empty storage at the given path
+fn start_server(storage_file_path: &str) -> SocketAddr {
+    let _ = fs::remove_file(storage_file_path);
+    let storage = Storage::new(PreviousStorage::No, Some(storage_file_path.to_string()));
+    let mut server = Server::bind("127.0.0.1:0", storage).unwrap();
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run(&SystemClock::default()));
+    address
+}
+
+/// Send a JSON request and return the status code and the JSON body of the response
+fn request(address: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    request_with_headers(
+        address,
+        method,
+        path,
+        "Content-Type: application/json\r\n",
+        body,
+    )
+}
+
+/// Send a request with extra header lines, each ending in CRLF, which can replace the `Host`
+fn request_with_headers(
+    address: SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &str,
+    body: Option<Value>,
+) -> (u16, Value) {
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let host = match headers.contains("Host: ") {
+        true => String::new(),
+        false => format!("Host: {address}\r\n"),
+    };
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\n{host}Connection: close\r\n{headers}Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+fn topic_names(topics: &Value) -> Vec<&str> {
+    topics
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|topic| topic["topic_name"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_add_list_and_review_topics() {
+    let address = start_server("./data/test_server_review.json");
+
+    let (status, topic) = request(
+        address,
+        "POST",
+        "/topics",
+        Some(json!({"topic_name": "Ownership", "tags": ["rust"]})),
+    );
+    assert_eq!(201, status);
+    assert_eq!(json!(["rust"]), topic["tags"]);
+    let (status, _) = request(
+        address,
+        "POST",
+        "/topics",
+        Some(json!({"topic_name": "Ownership"})),
+    );
+    assert_eq!(409, status);
+
+    let (status, topics) = request(address, "GET", "/topics", None);
+    assert_eq!(200, status);
+    assert_eq!(vec!["Ownership"], topic_names(&topics));
+    let (_, today) = request(address, "GET", "/topics/today", None);
+    assert!(topic_names(&today).is_empty());
+
+    let (status, topic) = request(address, "POST", "/topics/Ownership/reviews", None);
+    assert_eq!(200, status);
+    assert_eq!("Week", topic["next_review_gap"]);
+    let (status, _) = request(
+        address,
+        "POST",
+        "/topics/Ownership/reviews",
+        Some(json!({"on": "2999-01-01"})),
+    );
+    assert_eq!(422, status);
+    let (status, _) = request(address, "POST", "/topics/Missing/reviews", None);
+    assert_eq!(404, status);
+
+    // Every change is written to the storage file
+    let storage = Storage::new(
+        PreviousStorage::Yes,
+        Some("./data/test_server_review.json".to_string()),
+    );
+    assert_eq!(1, storage.get_review_topic_list()[0].review_history.len());
+}
+
+#[test]
+fn test_rename_and_remove_topics() {
+    let address = start_server("./data/test_server_rename.json");
+    request(
+        address,
+        "POST",
+        "/topics",
+        Some(json!({"topic_name": "Traits and generics"})),
+    );
+
+    let (status, topic) = request(
+        address,
+        "PATCH",
+        "/topics/Traits%20and%20generics",
+        Some(json!({"topic_name": "Traits"})),
+    );
+    assert_eq!(200, status);
+    assert_eq!("Traits", topic["topic_name"]);
+
+    let (status, _) = request(address, "DELETE", "/topics/Traits", None);
+    assert_eq!(200, status);
+    let (status, _) = request(address, "DELETE", "/topics/Traits", None);
+    assert_eq!(404, status);
+    let (_, topics) = request(address, "GET", "/topics", None);
+    assert!(topic_names(&topics).is_empty());
+
+    let (status, body) = request(address, "GET", "/nothing", None);
+    assert_eq!(404, status);
+    assert!(body["error"].is_string());
+}
+
+#[test]
+fn test_requests_from_other_sites_are_refused() {
+    let address = start_server("./data/test_server_origin.json");
+    let topic = Some(json!({"topic_name": "Lifetimes"}));
+
+    let (status, _) = request_with_headers(
+        address,
+        "POST",
+        "/topics",
+        "Content-Type: text/plain\r\n",
+        topic.clone(),
+    );
+    assert_eq!(415, status);
+    let (status, _) = request_with_headers(
+        address,
+        "POST",
+        "/topics",
+        "Content-Type: application/json\r\nOrigin: http://example.com\r\n",
+        topic.clone(),
+    );
+    assert_eq!(403, status);
+    // A page on a domain pointed at this machine sends its own name as both Host and Origin
+    let port = address.port();
+    let rebound = format!(
+        "Host: evil.example:{port}\r\nContent-Type: application/json\r\nOrigin: http://evil.example:{port}\r\n"
+    );
+    let (status, _) = request_with_headers(address, "POST", "/topics", &rebound, topic.clone());
+    assert_eq!(403, status);
+    let localhost = format!("Host: localhost:{port}\r\nContent-Type: application/json\r\n");
+    let (status, _) = request_with_headers(address, "GET", "/topics", &localhost, None);
+    assert_eq!(200, status);
+
+    let own_origin =
+        format!("Content-Type: application/json; charset=utf-8\r\nOrigin: http://{address}\r\n");
+    let (status, _) = request_with_headers(address, "POST", "/topics", &own_origin, topic);
+    assert_eq!(201, status);
+}
+
+#[test]
+fn test_changes_by_other_commands_are_served() {
+    let storage_file_path = "./data/test_server_reload.json";
+    let address = start_server(storage_file_path);
+    request(
+        address,
+        "POST",
+        "/topics",
+        Some(json!({"topic_name": "Closures"})),
+    );
+
+    let mut storage = Storage::new(PreviousStorage::Yes, Some(storage_file_path.to_string()));
+    let clock = SystemClock::default();
+    storage.add_review_topic(ReviewTopic::new("Iterators".to_string(), &clock));
+    storage.write_storage();
+
+    request(
+        address,
+        "POST",
+        "/topics",
+        Some(json!({"topic_name": "Macros"})),
+    );
+    let (_, topics) = request(address, "GET", "/topics", None);
+    assert_eq!(
+        vec!["Closures", "Iterators", "Macros"],
+        topic_names(&topics)
+    );
+}
@@ -0,0 +1,109 @@
+use revue::io::sync::{init, is_initialized, sync, SyncError, SyncOutcome};
+use revue::{PreviousStorage, ReviewTopic, Storage, SystemClock};
+use std::fs;
+use std::process::Command;
+
+static SYNC_DIR: &str = "./data/sync_test";
+
+fn topic_names(storage_file_path: &str) -> Vec<String> {
+    let mut names: Vec<String> =
+        Storage::new(PreviousStorage::Yes, Some(storage_file_path.to_string()))
+            .get_review_topic_list()
+            .into_iter()
+            .map(|topic| topic.topic_name)
+            .collect();
+    names.sort();
+    names
+}
+
+fn add_topic(storage_file_path: &str, topic_name: &str) {
+    let mut storage = Storage::new(PreviousStorage::Yes, Some(storage_file_path.to_string()));
+    storage.add_review_topic(ReviewTopic::new(
+        topic_name.to_string(),
+        &SystemClock::default(),
+    ));
+    storage.write_storage();
+}
+
+fn commit_count(dir: &str) -> usize {
+    let output = Command::new("git")
+        .args(["-C", dir, "rev-list", "--count", "HEAD"])
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn test_two_machines_sync_through_a_bare_remote() {
+    let _ = fs::remove_dir_all(SYNC_DIR);
+    fs::create_dir_all(SYNC_DIR).unwrap();
+    let remote = format!("{SYNC_DIR}/remote.git");
+    let status = Command::new("git")
+        .args(["init", "--quiet", "--bare", &remote])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let laptop = format!("{SYNC_DIR}/laptop/storage.json");
+    let desktop = format!("{SYNC_DIR}/desktop/storage.json");
+    let remote_url = remote.as_str();
+
+    assert!(init(&laptop, None).unwrap());
+    assert!(!init(&laptop, None).unwrap());
+    add_topic(&laptop, "rust");
+    assert_eq!(2, commit_count(&format!("{SYNC_DIR}/laptop")));
+    assert_eq!(
+        SyncOutcome::Pushed,
+        sync(&laptop, Some(remote_url), None).unwrap()
+    );
+
+    // The desktop started on its own, so its topics are merged with the ones of the laptop
+    init(&desktop, None).unwrap();
+    add_topic(&desktop, "go");
+    assert_eq!(
+        SyncOutcome::Merged,
+        sync(&desktop, Some(remote_url), None).unwrap()
+    );
+    assert_eq!(vec!["go", "rust"], topic_names(&desktop));
+
+    add_topic(&laptop, "zig");
+    assert_eq!(SyncOutcome::Merged, sync(&laptop, None, None).unwrap());
+    assert_eq!(vec!["go", "rust", "zig"], topic_names(&laptop));
+
+    assert_eq!(SyncOutcome::Pulled, sync(&desktop, None, None).unwrap());
+    assert_eq!(vec!["go", "rust", "zig"], topic_names(&desktop));
+    assert_eq!(SyncOutcome::UpToDate, sync(&desktop, None, None).unwrap());
+}
+
+#[test]
+fn test_foreign_repositories_are_left_alone() {
+    let dir = "./data/foreign_sync_test";
+    let _ = fs::remove_dir_all(dir);
+    fs::create_dir_all(dir).unwrap();
+    let status = Command::new("git")
+        .args(["init", "--quiet", dir])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let storage_file_path = format!("{dir}/storage.json");
+
+    assert!(!is_initialized(&storage_file_path));
+    assert!(matches!(
+        init(&storage_file_path, None),
+        Err(SyncError::ForeignRepository(_))
+    ));
+    assert!(matches!(
+        sync(&storage_file_path, None, None),
+        Err(SyncError::NotInitialized(_))
+    ));
+
+    // Writing the storage does not commit to the foreign repository
+    add_topic(&storage_file_path, "rust");
+    let output = Command::new("git")
+        .args(["-C", dir, "rev-list", "--count", "--all"])
+        .output()
+        .unwrap();
+    assert_eq!("0", String::from_utf8_lossy(&output.stdout).trim());
+}